@@ -0,0 +1,34 @@
+// Runs the test mock node on a fixed port for offline development:
+//
+//     cargo run --example mock_node -- [regtest|testnet|mainnet] [port] [address=amount ...]
+//
+// It defaults to the regtest profile's seed node, 127.0.0.1:18233, so selecting regtest in
+// the app is enough to use it. Each `address=amount` funds that address with one UTXO.
+
+#[path = "../tests/support/mock_node.rs"]
+mod mock_node;
+
+use std::env;
+
+use app::config::Network;
+use mock_node::MockNode;
+
+#[tokio::main]
+async fn main() {
+    let mut args = env::args().skip(1);
+
+    let network: Network = match args.next() {
+        Some(name) => serde_json::from_value(serde_json::Value::String(name)).expect("network is mainnet, testnet or regtest"),
+        None => Network::Regtest,
+    };
+    let port = args.next().map_or(network.default_port(), |port| port.parse().expect("port is a number"));
+
+    let node = MockNode::bind(&format!("127.0.0.1:{}", port), network).await.expect("port is free");
+    for funding in args {
+        let (address, amount) = funding.split_once('=').expect("funding is address=amount");
+        node.fund(address, amount.parse().expect("amount is a number"));
+    }
+
+    println!("mock {} node listening on {}", network.name(), node.host());
+    tokio::signal::ctrl_c().await.expect("can wait for ctrl-c");
+}
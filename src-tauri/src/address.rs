@@ -0,0 +1,58 @@
+use std::sync::{Mutex, PoisonError};
+
+use bech32::{primitives::decode::CheckedHrpstring, Bech32m};
+use hex::decode;
+use k256::{elliptic_curve::sec1::ToEncodedPoint, PublicKey};
+use tauri::State;
+
+use crate::config::{selected_network, Network, NetworkConfig};
+use crate::crypto_utils::hash160;
+use crate::error::WalletError;
+use crate::session::WalletSession;
+
+/// length of the address payload, HASH160 of the compressed public key
+pub const ADDRESS_PAYLOAD_LEN: usize = 20;
+
+/// bech32m address for a SEC1 public key (compressed or not), `cj1...` on mainnet
+pub fn address_from_public_key(public_key_hex: &str, network: Network) -> Result<String, WalletError> {
+    let public_key = PublicKey::from_sec1_bytes(&decode(public_key_hex)?).map_err(|_| WalletError::InvalidAddress("not a valid public key".to_string()))?;
+    let payload = hash160(public_key.to_encoded_point(true).as_bytes());
+
+    bech32::encode::<Bech32m>(network.hrp(), &payload).map_err(|e| WalletError::InvalidAddress(e.to_string()))
+}
+
+/// checks prefix, checksum and length, returning the 20-byte payload.
+/// Plain bech32 (non-m) checksums and addresses of other networks are rejected.
+pub fn parse_address(address: &str, network: Network) -> Result<[u8; ADDRESS_PAYLOAD_LEN], WalletError> {
+    let checked = CheckedHrpstring::new::<Bech32m>(address.trim()).map_err(|e| WalletError::InvalidAddress(e.to_string()))?;
+
+    if checked.hrp() != network.hrp() {
+        return Err(WalletError::InvalidAddress(match Network::from_hrp(&checked.hrp()) {
+            Some(other) => format!("{} address cannot be used on {}", other.name(), network.name()),
+            None => format!("expected prefix \"{}\", got \"{}\"", network.hrp(), checked.hrp()),
+        }));
+    }
+
+    let payload: Vec<u8> = checked.byte_iter().collect();
+    payload.try_into().map_err(|payload: Vec<u8>| {
+        WalletError::InvalidAddress(format!("expected {} bytes, got {}", ADDRESS_PAYLOAD_LEN, payload.len()))
+    })
+}
+
+pub fn is_valid_address(address: &str, network: Network) -> bool {
+    parse_address(address, network).is_ok()
+}
+
+/// address of the unlocked wallet key on the selected network
+#[tauri::command]
+pub fn get_address(session: State<'_, Mutex<WalletSession>>, config: State<'_, Mutex<NetworkConfig>>) -> Result<String, WalletError> {
+    let network = selected_network(&config);
+    let mut session = session.lock().unwrap_or_else(PoisonError::into_inner);
+    address_from_public_key(session.public_key().ok_or(WalletError::Locked)?, network)
+}
+
+/// lets the send form reject a mistyped or wrong-network address before anything is signed
+#[tauri::command]
+pub fn validate_address(address: &str, config: State<'_, Mutex<NetworkConfig>>) -> Result<(), WalletError> {
+    parse_address(address, selected_network(&config)).map(|_| ())
+}
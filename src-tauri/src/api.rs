@@ -1,170 +1,733 @@
 // src/api.rs
-use crate::crypto::{double_sha256, generate_key_pair, sign, verify, calculate_merkle_root};
-use crate::models::{Node, Transaction, Block, BlockHeader, UTXO, Output};
-use reqwest::{Client, Error};
-use serde_json::{json, Value};
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 
-const VERSION: f64 = 1.0;
-const SEED_NODE: &str = "192.168.1.117:8133";
+use futures::stream::{self, StreamExt};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::RwLock;
+use tokio::time::sleep;
 
+use crate::address::{address_from_public_key, parse_address};
+use crate::codec::{block_hash, txid};
+use crate::config::{selected_network, Network, NetworkConfig, NetworkProfile};
+use crate::consensus::{check_nbits, next_required_nbits, ConsensusParams};
+use crate::crypto_utils::{Block, BlockHeader, Node, Transaction};
+use crate::error::WalletError;
+use crate::fees::{self, fee_rate, FeeEstimate, FeeRates, RECENT_BLOCKS};
+use crate::miner;
+use crate::peers::{peer_store_file, unix_now, PeerRecord, PeerStore};
+use crate::pow::check_proof_of_work;
+use crate::quorum::{quorum_query, tally, QuorumPolicy, QuorumReport, QuorumResult};
+use crate::session::WalletSession;
+use crate::transaction::{check_outpoints, sign_transaction, verify_transaction, CoinSelection, TransactionBuilder, Utxo, UtxoSet};
+
+/// event emitted when the nodes behind an answer did not all agree
+pub const NODE_DISAGREEMENT_EVENT: &str = "node-disagreement";
+
+/// longest a single request to a node may take, connecting included
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// most peers asked per query, best ranked first
+pub const MAX_QUERIED_PEERS: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Balance {
+    pub confirmed: u64,
+    pub unconfirmed: u64,
+}
+
+/// How often a failed idempotent request is retried. The wait doubles after every attempt,
+/// starting at `initial_backoff` and capped at `max_backoff`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// attempts in total, the first one included
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// wait before retry number `retry`, counting from 0
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff.saturating_mul(2u32.saturating_pow(retry)).min(self.max_backoff)
+    }
+}
+
+/// Talks to CJ-Coin nodes of one network over HTTP. Peers are discovered from the profile's
+/// seed nodes once per profile and kept in a `PeerStore`, which ranks them by how well they
+/// have answered, bans repeat offenders and stands in for the seeds when they are down.
 pub struct NodeClient {
     client: Client,
-    nodes: Vec<Node>,
+    profile: RwLock<NetworkProfile>,
+    peers: Mutex<PeerStore>,
+    peer_dir: Option<PathBuf>,
+    // why the peers could not be read or saved, since the profile was selected
+    peer_store_error: Mutex<Option<String>>,
+    discovered: AtomicBool,
+    policy: QuorumPolicy,
+    retry: RetryPolicy,
+}
+
+impl Default for NodeClient {
+    fn default() -> Self {
+        Self::new(Network::default().into())
+    }
 }
 
 impl NodeClient {
-    pub async fn new() -> Result<Self, Error> {
-        let client = Client::new();
-        let nodes = discover_nodes(&client).await?;
-        Ok(Self { client, nodes })
+    pub fn new(profile: NetworkProfile) -> Self {
+        Self {
+            client: http_client(DEFAULT_REQUEST_TIMEOUT),
+            profile: RwLock::new(profile),
+            peers: Mutex::new(PeerStore::in_memory()),
+            peer_dir: None,
+            peer_store_error: Mutex::new(None),
+            discovered: AtomicBool::new(false),
+            policy: QuorumPolicy::default(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// skips discovery and queries exactly `nodes`
+    pub fn with_nodes(network: Network, nodes: Vec<Node>) -> Self {
+        let client = Self::new(NetworkProfile::new(network, Vec::new()));
+        client.peer_store().add_all(&nodes);
+        client.discovered.store(true, Ordering::SeqCst);
+        client
+    }
+
+    /// keeps each network's peers in `dir`, loading the current network's now
+    pub fn with_peer_dir(mut self, dir: PathBuf) -> Self {
+        let network = self.profile.get_mut().network;
+        let (store, error) = load_peer_store(&dir, network);
+        self.peers = Mutex::new(store);
+        self.peer_store_error = Mutex::new(error);
+        self.peer_dir = Some(dir);
+        self
+    }
+
+    pub fn with_quorum_policy(mut self, policy: QuorumPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.client = http_client(timeout);
+        self
+    }
+
+    pub async fn network(&self) -> Network {
+        self.profile.read().await.network
+    }
+
+    /// switches to another profile and its peers; discovery runs again on next use
+    pub async fn set_profile(&self, profile: NetworkProfile) {
+        let mut current = self.profile.write().await;
+        self.switch_profile(&mut current, profile);
+    }
+
+    /// Runs `update`, which persists a config change and returns the profile then selected,
+    /// and switches to that profile unless it is already the current one. Both happen under
+    /// the profile's lock, so concurrent updates take turns and the client always ends up on
+    /// the profile that was saved last.
+    pub async fn update_profile<F>(&self, update: F) -> Result<NetworkProfile, WalletError>
+    where
+        F: FnOnce() -> Result<NetworkProfile, WalletError>,
+    {
+        let mut current = self.profile.write().await;
+        let profile = update()?;
+        if *current != profile {
+            self.switch_profile(&mut current, profile.clone());
+        }
+        Ok(profile)
     }
 
-    pub async fn discover_nodes(client: &Client) -> Result<Vec<Node>, Error> {
-        let url = format!("http://{}/discover/nodes", SEED_NODE);
-        let response = client.get(&url).send().await?;
-        let nodes = response.json().await?;
+    fn switch_profile(&self, current: &mut NetworkProfile, profile: NetworkProfile) {
+        let (store, error) = match &self.peer_dir {
+            Some(dir) => load_peer_store(dir, profile.network),
+            None => (PeerStore::in_memory(), None),
+        };
+        *current = profile;
+        *self.peer_store() = store;
+        *self.peer_store_error.lock().unwrap_or_else(PoisonError::into_inner) = error;
+        self.discovered.store(false, Ordering::SeqCst);
+    }
+
+    /// Asks each seed node in turn until one answers and adds what it returns to the peer
+    /// store. If no seed answers, the peers cached from earlier runs are used instead.
+    pub async fn discover_nodes(&self) -> Result<Vec<Node>, WalletError> {
+        let seed_nodes = self.profile.read().await.seed_nodes.clone();
+        let mut last_error = WalletError::Network("no seed nodes configured".to_string());
+
+        for seed_node in seed_nodes {
+            match self.get_json::<Vec<Node>>(format!("http://{}/discover/nodes", seed_node)).await {
+                Ok(nodes) => {
+                    let mut store = self.peer_store();
+                    store.add_all(&nodes);
+                    self.save_peer_store(&store);
+                    self.discovered.store(true, Ordering::SeqCst);
+                    return Ok(nodes);
+                }
+                Err(e) => last_error = e,
+            }
+        }
+
+        let cached = self.peer_store().ranked(unix_now());
+        if cached.is_empty() {
+            return Err(last_error);
+        }
+        self.discovered.store(true, Ordering::SeqCst);
+        Ok(cached)
+    }
+
+    /// the peers to query, best first, discovering them first if needed
+    pub async fn nodes(&self) -> Result<Vec<Node>, WalletError> {
+        if !self.discovered.load(Ordering::SeqCst) {
+            self.discover_nodes().await?;
+        }
+
+        let mut nodes = self.peer_store().ranked(unix_now());
+        if nodes.is_empty() {
+            return Err(WalletError::Network("no usable peers; every known node is banned".to_string()));
+        }
+        nodes.truncate(MAX_QUERIED_PEERS);
         Ok(nodes)
     }
 
-    pub async fn broadcast_transaction(&self, tx: &Transaction) -> Result<bool, Error> {
-        let mut responses = Vec::new();
-        
-        for node in &self.nodes {
-            let url = format!("http://{}:{}/validate/tx", node.ipv4_address, node.port);
-            let response = self.client.post(&url)
-                .json(tx)
-                .send()
-                .await?
-                .json::<Value>()
-                .await?;
-            
-            responses.push(response);
+    /// what the peer store knows about every peer
+    pub fn peer_records(&self) -> Vec<PeerRecord> {
+        self.peer_store().records().to_vec()
+    }
+
+    pub fn unban_peer(&self, node: &Node) {
+        let mut store = self.peer_store();
+        store.unban(node);
+        self.save_peer_store(&store);
+    }
+
+    /// Gives each of `nodes` a strike for an answer the quorum accepted but that turned out to
+    /// break the consensus rules once checked against the rest of the chain.
+    pub fn strike_peers(&self, nodes: &[Node]) {
+        let mut store = self.peer_store();
+        for node in nodes {
+            store.record_failure(node, true, unix_now());
         }
+        self.save_peer_store(&store);
+    }
+
+    /// why the selected network's cached peers could not be read or saved, if that happened
+    /// since it was selected
+    pub fn peer_store_error(&self) -> Option<String> {
+        self.peer_store_error.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    fn peer_store(&self) -> MutexGuard<'_, PeerStore> {
+        self.peers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
 
-        // Find most common response (mode)
-        let mut counts = HashMap::new();
-        for response in &responses {
-            *counts.entry(response).or_insert(0) += 1;
+    // the store is only a cache, so failing to write it must not fail the query; the error is
+    // kept for `peer_store_error` instead
+    fn save_peer_store(&self, store: &PeerStore) {
+        if let Err(e) = store.save() {
+            *self.peer_store_error.lock().unwrap_or_else(PoisonError::into_inner) = Some(format!("could not save peers: {}", e));
         }
-        
-        let mode = counts.into_iter()
-            .max_by_key(|&(_, count)| count)
-            .map(|(val, _)| val)
-            .unwrap();
+    }
 
-        Ok(mode["valid"].as_bool().unwrap_or(false))
+    /// Runs `query` as a quorum over the ranked peers, recording how each one answered:
+    /// latency and failures as they come in, then who agreed with the outcome.
+    async fn query_peers<T, F, Fut>(&self, query: F) -> Result<QuorumResult<T>, WalletError>
+    where
+        T: Serialize,
+        F: Fn(Node) -> Fut,
+        Fut: Future<Output = Result<T, WalletError>>,
+    {
+        let nodes = self.nodes().await?;
+        let result = quorum_query(&nodes, &self.policy, |node| self.recorded(node.clone(), query(node))).await;
+        self.record_outcome(&result);
+        result
     }
 
-    pub async fn send(
-        &self,
-        private_key: &str,
-        amount: u64,
-        address: &str,
-        fee: u64,
-    ) -> Result<bool, Error> {
-        // Get UTXOs
-        let (inputs, mempool_inputs) = self.get_utxos().await?;
-        let mut all_inputs = [inputs, mempool_inputs].concat();
-
-        // Select inputs
-        let mut selected_inputs = Vec::new();
-        let mut total = 0;
-        
-        for input in all_inputs.drain(..) {
-            if total >= amount + fee {
-                break;
+    /// Runs `post` against every ranked peer and waits for all of them, however early the
+    /// outcome is clear, then tallies the answers like `query_peers`. For broadcasts, which
+    /// must reach every node rather than just enough of them.
+    async fn post_to_peers<T, F, Fut>(&self, post: F) -> Result<QuorumResult<T>, WalletError>
+    where
+        T: Serialize,
+        F: Fn(Node) -> Fut,
+        Fut: Future<Output = Result<T, WalletError>>,
+    {
+        let nodes = self.nodes().await?;
+        let responses = stream::iter(nodes)
+            .map(|node| {
+                let response = self.recorded(node.clone(), post(node.clone()));
+                async move { (node, response.await) }
+            })
+            .buffered(self.policy.max_concurrent.max(1))
+            .collect()
+            .await;
+        let result = tally(responses, &self.policy);
+        self.record_outcome(&result);
+        result
+    }
+
+    // `response` from `node`, with its latency or failure recorded in the peer store
+    async fn recorded<T>(&self, node: Node, response: impl Future<Output = Result<T, WalletError>>) -> Result<T, WalletError> {
+        let started = Instant::now();
+        let response = response.await;
+
+        let mut store = self.peer_store();
+        match &response {
+            Ok(_) => store.record_success(&node, started.elapsed().as_millis() as u64, unix_now()),
+            Err(e) => store.record_failure(&node, e.is_malformed_response(), unix_now()),
+        }
+        response
+    }
+
+    // records who agreed with the outcome and who did not, then saves the peer store
+    fn record_outcome<T>(&self, result: &Result<QuorumResult<T>, WalletError>) {
+        let mut store = self.peer_store();
+        if let Ok(result) = result {
+            for node in &result.report.agreeing {
+                store.record_agreement(node);
+            }
+            for node in &result.report.dissenting {
+                store.record_disagreement(node, unix_now());
             }
-            total += input.amount;
-            selected_inputs.push(input);
         }
+        self.save_peer_store(&store);
+    }
+
+    /// Fee rates from each node's recent blocks and mempool, combined by taking the median of
+    /// every rate. Mempools differ from node to node, so the nodes are not expected to agree.
+    pub async fn fee_rates(&self) -> Result<FeeRates, WalletError> {
+        let nodes = self.nodes().await?;
+        let samples: Vec<FeeRates> = stream::iter(nodes)
+            .map(|node| async move { self.node_fee_rates(&node).await })
+            .buffer_unordered(self.policy.max_concurrent.max(1))
+            .filter_map(|rates| async move { rates.ok() })
+            .collect()
+            .await;
 
-        // Generate outputs
-        let outputs = generate_outputs(selected_inputs, amount, address, fee)?;
-        if outputs.is_empty() {
-            return Ok(false);
+        if samples.is_empty() {
+            return Err(WalletError::Network("no node reported fee data".to_string()));
         }
+        Ok(FeeRates::median(&samples))
+    }
 
-        // Create and sign transaction
-        let mut tx = Transaction {
-            version: VERSION as u32,
-            inputs: selected_inputs,
-            outputs,
-            signature: None,
-            txid: None,
-            public_key: None,
-        };
+    async fn node_fee_rates(&self, node: &Node) -> Result<FeeRates, WalletError> {
+        let blocks: Vec<Block> = self.get_json(node_url(node, &format!("/blocks/recent/{}", RECENT_BLOCKS))).await?;
+        let mempool: Vec<Transaction> = self.get_json(node_url(node, "/mempool")).await?;
 
-        let (signature, pubkey) = sign(private_key, &serde_json::to_string(&tx)?);
-        tx.signature = Some(signature);
-        tx.public_key = Some(pubkey);
+        let block_rates: Vec<u64> = blocks.iter().flat_map(|block| &block.txs).filter_map(fee_rate).collect();
+        let mempool_rates: Vec<u64> = mempool.iter().filter_map(fee_rate).collect();
+        Ok(FeeRates::from_samples(&block_rates, &mempool_rates))
+    }
+
+    /// Posts `tx` to every node for validation and waits for each of them. The value is whether
+    /// the quorum accepted it; the report says how every node answered.
+    pub async fn broadcast_transaction(&self, tx: &Transaction) -> Result<QuorumResult<bool>, WalletError> {
+        self.post_to_peers(|node| async move {
+            let response: Value = self.client.post(node_url(&node, "/validate/tx")).json(tx).send().await?.error_for_status()?.json().await?;
+            Ok(response["valid"].as_bool().unwrap_or(false))
+        })
+        .await
+    }
+
+    /// blocks in the chain as agreed by the quorum, which is also the height of the next one
+    pub async fn chain_height(&self) -> Result<QuorumResult<u64>, WalletError> {
+        self.query_peers(|node| async move { self.get_json(node_url(&node, "/blocks/count")).await }).await
+    }
 
-        let txid = hex::encode(double_sha256(&serde_json::to_vec(&tx)?));
-        tx.txid = Some(txid);
+    /// Up to `count` headers from height `start`, oldest first, as agreed by the quorum. Fewer
+    /// come back if the chain ends first. A node offering headers that were not mined, that do
+    /// not link up or that carry the wrong difficulty counts as unreachable and gets a strike.
+    pub async fn headers(&self, start: u64, count: u64) -> Result<QuorumResult<Vec<BlockHeader>>, WalletError> {
+        let params = &ConsensusParams::for_network(self.network().await);
+        self.query_peers(|node| async move {
+            let mut headers: Vec<BlockHeader> = Vec::new();
+            while (headers.len() as u64) < count {
+                let batch: Vec<BlockHeader> = self.get_json(node_url(&node, &format!("/headers/{}", start + headers.len() as u64))).await?;
+                if batch.is_empty() {
+                    break;
+                }
+                headers.extend(batch);
+            }
+            headers.truncate(count as usize);
 
-        // Broadcast
-        self.broadcast_transaction(&tx).await
+            for (index, header) in headers.iter().enumerate() {
+                let height = start + index as u64;
+                check_proof_of_work(header)?;
+                if index > 0 && header.prev_hash != block_hash(&headers[index - 1])? {
+                    return Err(WalletError::InvalidBlock(format!("header {} does not follow the one before it", height)));
+                }
+                // a retarget reads more headers than the batch may hold; the chain checks those
+                if (index as u64) >= params.headers_needed(height) {
+                    check_nbits(params, height, &headers[..index], header)?;
+                }
+            }
+            Ok(headers)
+        })
+        .await
     }
 
-    async fn get_utxos(&self) -> Result<(Vec<UTXO>, Vec<UTXO>), Error> {
-        let mut responses = Vec::new();
-        
-        for node in &self.nodes {
-            let utxos = self.client.get(&format!(
-                "http://{}:{}/utxos/address/{}", 
-                node.ipv4_address, node.port, address
-            )).send().await?.json().await?;
-            
-            let mempool_utxos = self.client.get(&format!(
-                "http://{}:{}/utxos_mempool/address/{}",
-                node.ipv4_address, node.port, address
-            )).send().await?.json().await?;
-            
-            responses.push((utxos, mempool_utxos));
+    /// Transactions in the mempools of more than half the nodes that answered, in the order
+    /// they were first seen. Mempools differ from node to node, so there is no quorum to reach.
+    pub async fn mempool(&self) -> Result<Vec<Transaction>, WalletError> {
+        let nodes = self.nodes().await?;
+        let mempools: Vec<Vec<Transaction>> = stream::iter(nodes)
+            .map(|node| async move { self.get_json(node_url(&node, "/mempool")).await })
+            .buffer_unordered(self.policy.max_concurrent.max(1))
+            .filter_map(|mempool: Result<Vec<Transaction>, WalletError>| async move { mempool.ok() })
+            .collect()
+            .await;
+        if mempools.is_empty() {
+            return Err(WalletError::Network("no node reported its mempool".to_string()));
         }
 
-        // Find mode response
-        let mut counts = HashMap::new();
-        for response in &responses {
-            *counts.entry(response).or_insert(0) += 1;
+        let mut seen_by: HashMap<String, usize> = HashMap::new();
+        let mut first_seen = Vec::new();
+        for mempool in &mempools {
+            let mut counted = HashSet::new();
+            for tx in mempool {
+                let Ok(id) = txid(tx) else { continue };
+                if !counted.insert(id.clone()) {
+                    continue;
+                }
+                let count = seen_by.entry(id.clone()).or_insert(0);
+                if *count == 0 {
+                    first_seen.push((id, tx));
+                }
+                *count += 1;
+            }
         }
-        
-        let mode = counts.into_iter()
-            .max_by_key(|&(_, count)| count)
-            .map(|(val, _)| val)
-            .unwrap();
+        Ok(first_seen.into_iter().filter(|(id, _)| seen_by[id] * 2 > mempools.len()).map(|(_, tx)| tx.clone()).collect())
+    }
+
+    /// the next block to mine for `address`, on the agreed tip at the difficulty the rules
+    /// require, with the majority's mempool and `extra_nonce` in the coinbase
+    pub async fn block_template(&self, address: &str, extra_nonce: u64) -> Result<Block, WalletError> {
+        let network = self.network().await;
+        parse_address(address, network)?;
 
-        Ok(mode.clone())
+        let params = ConsensusParams::for_network(network);
+        let height = self.chain_height().await?.value;
+        // enough for the difficulty and for the median time past the timestamp must beat
+        let needed = params.context_needed(height);
+        let prev_headers = if needed == 0 { Vec::new() } else { self.headers(height - needed, needed).await?.value };
+        let nbits = next_required_nbits(&params, height, &prev_headers)?;
+
+        let mempool = self.mempool().await?;
+        let utxos = self.spent_outputs(&mempool).await;
+        miner::block_template(&prev_headers, height, nbits, mempool, utxos, network, address, unix_now(), extra_nonce)
     }
-}
 
-fn generate_outputs(
-    inputs: Vec<UTXO>,
-    amount: u64,
-    address: &str,
-    fee: u64,
-) -> Result<Vec<Output>, &'static str> {
-    let total: u64 = inputs.iter().map(|i| i.amount).sum();
-    
-    if total < amount + fee {
-        return Ok(Vec::new());
+    /// the transaction with txid `id`, confirmed or in the mempool; one whose contents do not
+    /// hash to `id` is a malformed answer
+    pub async fn transaction(&self, id: &str) -> Result<QuorumResult<Transaction>, WalletError> {
+        self.query_peers(|node| async move {
+            let tx: Transaction = self.get_json(node_url(&node, &format!("/tx/{}", id))).await?;
+            if txid(&tx)? != id {
+                return Err(WalletError::Codec(format!("node sent another transaction for {}", id)));
+            }
+            Ok(tx)
+        })
+        .await
+    }
+
+    // The outputs `mempool` spends that no transaction in it creates, from the transactions
+    // that created them, so their amounts are what the txids commit to rather than what the
+    // inputs claim. Outputs that cannot be fetched are left out, which leaves out whatever
+    // spends them.
+    async fn spent_outputs(&self, mempool: &[Transaction]) -> UtxoSet {
+        let created: HashSet<String> = mempool.iter().filter_map(|tx| txid(tx).ok()).collect();
+        let spent: HashSet<String> = mempool
+            .iter()
+            .filter(|tx| miner::coinbase_height(tx).is_none())
+            .flat_map(|tx| &tx.inputs)
+            .filter(|input| !created.contains(&input.prev_txid))
+            .map(|input| input.prev_txid.clone())
+            .collect();
+
+        let funding: Vec<Transaction> = stream::iter(spent)
+            .map(|prev_txid| async move { self.transaction(&prev_txid).await })
+            .buffer_unordered(self.policy.max_concurrent.max(1))
+            .filter_map(|tx| async move { tx.ok().map(|tx| tx.value) })
+            .collect()
+            .await;
+
+        let mut utxos = UtxoSet::new();
+        for tx in funding {
+            if let Ok(id) = txid(&tx) {
+                for (index, output) in tx.outputs.into_iter().enumerate() {
+                    utxos.insert(&id, index as u32, output);
+                }
+            }
+        }
+        utxos
+    }
+
+    /// Posts `block` to every node for validation and waits for each of them, like
+    /// `broadcast_transaction`.
+    pub async fn submit_block(&self, block: &Block) -> Result<QuorumResult<bool>, WalletError> {
+        self.post_to_peers(|node| async move {
+            let response: Value = self.client.post(node_url(&node, "/validate/block")).json(block).send().await?.error_for_status()?.json().await?;
+            Ok(response["valid"].as_bool().unwrap_or(false))
+        })
+        .await
+    }
+
+    /// (confirmed, mempool) UTXOs for `address`, as agreed by the quorum. A node listing
+    /// outpoints that cannot be spent counts as a malformed answer.
+    pub async fn get_utxos(&self, address: &str) -> Result<QuorumResult<(Vec<Utxo>, Vec<Utxo>)>, WalletError> {
+        self.query_peers(|node| async move {
+            let utxos: Vec<Utxo> = self.get_json(node_url(&node, &format!("/utxos/address/{}", address))).await?;
+            let mempool_utxos: Vec<Utxo> = self.get_json(node_url(&node, &format!("/utxos_mempool/address/{}", address))).await?;
+            check_outpoints(&utxos)?;
+            check_outpoints(&mempool_utxos)?;
+            Ok((utxos, mempool_utxos))
+        })
+        .await
+    }
+
+    /// Fails with `InvalidAmount` if the nodes report more than a u64 of either.
+    pub async fn get_balance(&self, address: &str) -> Result<QuorumResult<Balance>, WalletError> {
+        let QuorumResult { value: (utxos, mempool_utxos), report } = self.get_utxos(address).await?;
+        let balance = Balance {
+            confirmed: sum_amounts(&utxos)?,
+            unconfirmed: sum_amounts(&mempool_utxos)?,
+        };
+        Ok(QuorumResult { value: balance, report })
+    }
+
+    /// Has `builder` build a payment from `own_address`'s UTXOs, with change back to `own_address`,
+    /// has `sign_sighash` sign each input, verifies the result and broadcasts it. Returns the txid
+    /// once the quorum accepts it, with the report of the broadcast.
+    pub async fn send<F>(
+        &self,
+        own_address: &str,
+        amount: u64,
+        address: &str,
+        fee: u64,
+        builder: &TransactionBuilder,
+        sign_sighash: F,
+    ) -> Result<QuorumResult<String>, WalletError>
+    where
+        F: FnMut(&[u8]) -> Result<(String, String), WalletError>,
+    {
+        // Refuse to build anything for an address that fails its checksum or is on another network
+        let network = self.network().await;
+        parse_address(address, network)?;
+
+        let (inputs, mempool_inputs) = self.get_utxos(own_address).await?.value;
+        let mut tx = builder.build(&inputs, &mempool_inputs, amount, address, fee, own_address)?;
+        sign_transaction(&mut tx, network, sign_sighash)?;
+
+        // Catches a key that does not own the address before any node sees the transaction
+        let mut utxos = UtxoSet::new();
+        utxos.add_owned(own_address, &inputs);
+        utxos.add_owned(own_address, &mempool_inputs);
+        verify_transaction(&tx, &utxos, network)?;
+        let txid = tx.txid.clone().expect("signing sets the txid");
+
+        let broadcast = self.broadcast_transaction(&tx).await?;
+        if !broadcast.value {
+            return Err(WalletError::Rejected("nodes did not accept the transaction".to_string()));
+        }
+        Ok(broadcast.map(|_| txid))
     }
 
-    let change = total - amount - fee;
-    let mut outputs = vec![
-        Output {
-            amount,
-            address: address.to_string(),
+    /// GETs are idempotent, so timeouts, dropped connections and server errors are retried
+    async fn get_json<T: DeserializeOwned>(&self, url: String) -> Result<T, WalletError> {
+        let mut retry = 0;
+        loop {
+            let response = match self.client.get(&url).send().await.and_then(|response| response.error_for_status()) {
+                Ok(response) => response,
+                Err(e) if is_transient(&e) && retry + 1 < self.retry.max_attempts => {
+                    sleep(self.retry.backoff(retry)).await;
+                    retry += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            return Ok(response.json().await?);
         }
-    ];
+    }
+}
 
-    if change > 0 {
-        outputs.push(Output {
-            amount: change,
-            address: own_address, // Would need to be passed in
-        });
+// the cached peers of `network`, or an empty store and why they could not be read
+fn load_peer_store(dir: &Path, network: Network) -> (PeerStore, Option<String>) {
+    let path = dir.join(peer_store_file(network));
+    match PeerStore::load(&path) {
+        Ok(store) => (store, None),
+        Err(e) => (PeerStore::at(&path), Some(format!("starting with no cached peers: {}", e))),
     }
+}
+
+// what `utxos` hold together; a node can claim amounts that add up past a u64
+fn sum_amounts(utxos: &[Utxo]) -> Result<u64, WalletError> {
+    utxos
+        .iter()
+        .try_fold(0u64, |total, utxo| total.checked_add(utxo.amount))
+        .ok_or_else(|| WalletError::InvalidAmount("the UTXOs add up to more than a u64 holds".to_string()))
+}
+
+fn http_client(timeout: Duration) -> Client {
+    Client::builder()
+        .timeout(timeout)
+        .connect_timeout(CONNECT_TIMEOUT.min(timeout))
+        .build()
+        .expect("static client configuration is valid")
+}
+
+// worth another attempt: the node may answer next time
+fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect() || e.is_request() || matches!(e.status(), Some(status) if status.is_server_error())
+}
+
+fn node_url(node: &Node, path: &str) -> String {
+    format!("http://{}:{}{}", node.ipv4_address, node.port, path)
+}
+
+pub(crate) fn own_address(session: &Mutex<WalletSession>, config: &Mutex<NetworkConfig>) -> Result<String, WalletError> {
+    let network = selected_network(config);
+    let mut session = session.lock().unwrap_or_else(PoisonError::into_inner);
+    address_from_public_key(session.public_key().ok_or(WalletError::Locked)?, network)
+}
+
+#[derive(Serialize, Clone)]
+struct NodeDisagreement<'a> {
+    operation: &'a str,
+    report: &'a QuorumReport,
+}
+
+// the quorum's answer is still used, but the UI gets to warn about the nodes that disagreed
+pub(crate) fn unwrap_quorum<T>(app: &AppHandle, operation: &str, result: QuorumResult<T>) -> T {
+    if !result.report.is_unanimous() {
+        let _ = app.emit_all(NODE_DISAGREEMENT_EVENT, NodeDisagreement { operation, report: &result.report });
+    }
+    result.value
+}
+
+#[tauri::command]
+pub async fn get_balance(
+    app: AppHandle,
+    session: State<'_, Mutex<WalletSession>>,
+    config: State<'_, Mutex<NetworkConfig>>,
+    nodes: State<'_, NodeClient>,
+) -> Result<Balance, WalletError> {
+    let address = own_address(&session, &config)?;
+    Ok(unwrap_quorum(&app, "get_balance", nodes.get_balance(&address).await?))
+}
+
+/// (confirmed, mempool) UTXOs of the unlocked wallet
+#[tauri::command]
+pub async fn get_utxos(
+    app: AppHandle,
+    session: State<'_, Mutex<WalletSession>>,
+    config: State<'_, Mutex<NetworkConfig>>,
+    nodes: State<'_, NodeClient>,
+) -> Result<(Vec<Utxo>, Vec<Utxo>), WalletError> {
+    let address = own_address(&session, &config)?;
+    Ok(unwrap_quorum(&app, "get_utxos", nodes.get_utxos(&address).await?))
+}
+
+/// signs with the wallet `handle` unlocked and broadcasts; returns the txid. Inputs are picked
+/// oldest first and may be unconfirmed unless told otherwise.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_transaction(
+    handle: String,
+    amount: u64,
+    address: String,
+    fee: u64,
+    strategy: Option<CoinSelection>,
+    include_unconfirmed: Option<bool>,
+    app: AppHandle,
+    session: State<'_, Mutex<WalletSession>>,
+    config: State<'_, Mutex<NetworkConfig>>,
+    nodes: State<'_, NodeClient>,
+) -> Result<String, WalletError> {
+    let own_address = own_address(&session, &config)?;
+    let builder = payment_builder(strategy, include_unconfirmed);
+
+    let sent = nodes
+        .send(&own_address, amount, &address, fee, &builder, |digest| {
+            session.lock().unwrap_or_else(PoisonError::into_inner).sign(&handle, digest)
+        })
+        .await?;
+    Ok(unwrap_quorum(&app, "send_transaction", sent))
+}
+
+/// What sending `amount` to `address` would cost at each priority, priced from the wallet's
+/// UTXOs and the nodes' recent fee rates with inputs picked as `send_transaction` would
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn estimate_fee(
+    amount: u64,
+    address: String,
+    strategy: Option<CoinSelection>,
+    include_unconfirmed: Option<bool>,
+    app: AppHandle,
+    session: State<'_, Mutex<WalletSession>>,
+    config: State<'_, Mutex<NetworkConfig>>,
+    nodes: State<'_, NodeClient>,
+) -> Result<FeeEstimate, WalletError> {
+    let own_address = own_address(&session, &config)?;
+    parse_address(&address, selected_network(&config))?;
 
-    Ok(outputs)
-}
\ No newline at end of file
+    let (utxos, mempool_utxos) = unwrap_quorum(&app, "estimate_fee", nodes.get_utxos(&own_address).await?);
+    let rates = nodes.fee_rates().await?;
+    let builder = payment_builder(strategy, include_unconfirmed);
+    fees::estimate(&builder, &utxos, &mempool_utxos, amount, &address, &own_address, rates)
+}
+
+// the builder both sending and estimating use, so an estimate prices the inputs a send would spend
+fn payment_builder(strategy: Option<CoinSelection>, include_unconfirmed: Option<bool>) -> TransactionBuilder {
+    TransactionBuilder::new(strategy.unwrap_or_default()).with_unconfirmed(include_unconfirmed.unwrap_or(true))
+}
+
+/// known peers with their health, for the network settings page
+#[tauri::command]
+pub fn get_peers(nodes: State<'_, NodeClient>) -> Vec<PeerRecord> {
+    nodes.peer_records()
+}
+
+#[tauri::command]
+pub fn get_peer_store_error(nodes: State<'_, NodeClient>) -> Option<String> {
+    nodes.peer_store_error()
+}
+
+#[tauri::command]
+pub fn unban_peer(node: Node, nodes: State<'_, NodeClient>) {
+    nodes.unban_peer(&node);
+}
@@ -0,0 +1,473 @@
+// Header-only (SPV) chain: the wallet downloads every block header and checks it itself
+// rather than taking the nodes' word for the chain, keeps the headers on disk, and follows
+// whichever valid chain carries the most work, reorganizing when a heavier branch appears.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use hex::encode;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+use tokio::time::sleep;
+
+use crate::api::NodeClient;
+use crate::codec::{block_hash, Codec};
+use crate::config::Network;
+use crate::consensus::{check_header, ConsensusParams};
+use crate::crypto_utils::{BlockHeader, Node};
+use crate::error::WalletError;
+use crate::merkle::{verify_merkle_proof, MerkleProof};
+use crate::peers::unix_now;
+use crate::pow::{block_work, U256};
+
+/// event emitted after every batch of headers downloaded, with a `SyncProgress`
+pub const HEADER_SYNC_EVENT: &str = "header-sync";
+/// event emitted whenever the best chain's tip changes, with a `ChainTip`
+pub const CHAIN_TIP_EVENT: &str = "chain-tip";
+/// how often the background sync asks the nodes for new headers
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+// headers asked for per request; a node answers a `/headers/{start}` request with up to 2000
+const HEADERS_PER_REQUEST: u64 = 2000;
+// bytes each header takes in the store file, its canonical encoding
+const HEADER_RECORD_LEN: usize = 88;
+
+/// How `HeaderChain::connect` changed the best chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChainUpdate {
+    /// the headers were already known or did not carry more work
+    Unchanged,
+    Extended { added: u64 },
+    /// the blocks from `fork_height` on were replaced by a branch with more work
+    Reorganized { fork_height: u64, disconnected: u64, added: u64 },
+}
+
+/// The best chain of headers known on one network, every one checked against the consensus
+/// rules, optionally persisted as fixed-size header records.
+#[derive(Debug)]
+pub struct HeaderChain {
+    network: Network,
+    params: ConsensusParams,
+    path: Option<PathBuf>,
+    // each header with its hash set, from the first block on
+    headers: Vec<BlockHeader>,
+    // cumulative work up to and including each header
+    work: Vec<U256>,
+    // headers at the start of the file that are still in the chain
+    saved: usize,
+}
+
+impl HeaderChain {
+    /// an empty chain that is never written to disk
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            params: ConsensusParams::for_network(network),
+            path: None,
+            headers: Vec::new(),
+            work: Vec::new(),
+            saved: 0,
+        }
+    }
+
+    /// an empty chain that will be saved to `path`
+    pub fn at(path: &Path, network: Network) -> Self {
+        Self {
+            path: Some(path.to_path_buf()),
+            ..Self::new(network)
+        }
+    }
+
+    /// reads `path`, starting empty if it does not exist yet; every header is checked again.
+    /// A record cut short by an interrupted save is dropped.
+    pub fn load(path: &Path, network: Network) -> Result<Self, WalletError> {
+        let mut chain = Self::at(path, network);
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let headers = bytes.chunks_exact(HEADER_RECORD_LEN).map(BlockHeader::decode).collect::<Result<Vec<_>, _>>()?;
+        // the headers were accepted when they arrived, so the clock no longer matters
+        chain.connect(0, &headers, u64::MAX)?;
+        chain.saved = chain.headers.len();
+        Ok(chain)
+    }
+
+    /// Writes the chain to its file, if it has one. Records still in the chain since the last
+    /// save are kept, so only the blocks connected since, reorganized ones included, are written.
+    pub fn save(&mut self) -> Result<(), WalletError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut records = Vec::with_capacity((self.headers.len() - self.saved) * HEADER_RECORD_LEN);
+        for header in &self.headers[self.saved..] {
+            header.encode_to(&mut records)?;
+        }
+
+        let mut file = OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+        // drops the records a reorganization replaced, and any left half-written
+        file.set_len((self.saved * HEADER_RECORD_LEN) as u64)?;
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&records)?;
+        self.saved = self.headers.len();
+        Ok(())
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// blocks in the chain, which is also the height of the next one
+    pub fn height(&self) -> u64 {
+        self.headers.len() as u64
+    }
+
+    pub fn headers(&self) -> &[BlockHeader] {
+        &self.headers
+    }
+
+    pub fn tip(&self) -> Option<&BlockHeader> {
+        self.headers.last()
+    }
+
+    /// hash of the block at `height`, if the chain has it
+    pub fn hash_at(&self, height: u64) -> Option<&str> {
+        self.headers.get(height as usize).and_then(|header| header.hash.as_deref())
+    }
+
+    /// work summed over every block
+    pub fn chain_work(&self) -> U256 {
+        self.work.last().copied().unwrap_or(U256::ZERO)
+    }
+
+    /// Checks `headers`, claimed to be the blocks from height `start` on, and makes them the
+    /// best chain if the chain they end carries more work than the current one. Headers the
+    /// chain already has are skipped; any after them that differ form a branch, which replaces
+    /// the blocks from the fork on only if it is heavier. Ties keep the chain seen first.
+    /// A header breaking the rules fails the call, but the valid headers before it are still
+    /// connected if they alone carry more work.
+    pub fn connect(&mut self, start: u64, headers: &[BlockHeader], now: u64) -> Result<ChainUpdate, WalletError> {
+        let height = self.height();
+        if start > height {
+            return Err(WalletError::InvalidBlock(format!("headers from block {} leave a gap after block {}", start, height)));
+        }
+        let mut known = 0;
+        while known < headers.len() && start + (known as u64) < height && self.hash_at(start + known as u64) == Some(block_hash(&headers[known])?.as_str()) {
+            known += 1;
+        }
+        let (fork, branch) = (start as usize + known, &headers[known..]);
+        if branch.is_empty() {
+            return Ok(ChainUpdate::Unchanged);
+        }
+
+        // the branch goes straight onto the chain so every header is checked against the ones
+        // before it in place; the blocks it would replace are put back unless the headers that
+        // passed are heavier
+        let current_work = self.chain_work();
+        let displaced = (self.headers.split_off(fork), self.work.split_off(fork));
+        let appended = self.append_checked(branch, now);
+        if self.chain_work() <= current_work {
+            self.headers.truncate(fork);
+            self.work.truncate(fork);
+            self.headers.extend(displaced.0);
+            self.work.extend(displaced.1);
+            return appended.map(|_| ChainUpdate::Unchanged);
+        }
+
+        let disconnected = height - fork as u64;
+        let added = (self.headers.len() - fork) as u64;
+        self.saved = self.saved.min(fork);
+        appended?;
+        Ok(match disconnected {
+            0 => ChainUpdate::Extended { added },
+            _ => ChainUpdate::Reorganized {
+                fork_height: fork as u64,
+                disconnected,
+                added,
+            },
+        })
+    }
+
+    // appends `branch` to the chain, checking each header against the blocks before it
+    fn append_checked(&mut self, branch: &[BlockHeader], now: u64) -> Result<(), WalletError> {
+        for header in branch {
+            let block = self.headers.len();
+            let first = block - self.params.context_needed(block as u64) as usize;
+            check_header(&self.params, block as u64, &self.headers[first..], header, now)?;
+
+            let total = self.chain_work().saturating_add(block_work(header.nbits)?);
+            self.headers.push(BlockHeader {
+                hash: Some(block_hash(header)?),
+                ..header.clone()
+            });
+            self.work.push(total);
+        }
+        Ok(())
+    }
+
+    /// Whether `proof` shows the transaction `txid` is in the block at `height`, checked against
+    /// that block's header alone; `false` if the chain does not reach `height`.
+    pub fn verify_inclusion(&self, height: u64, txid: &str, proof: &MerkleProof) -> Result<bool, WalletError> {
+        match self.headers.get(height as usize) {
+            Some(header) => verify_merkle_proof(txid, proof, &header.merkle_root),
+            None => Ok(false),
+        }
+    }
+
+    pub fn chain_tip(&self) -> ChainTip {
+        ChainTip {
+            network: self.network,
+            height: self.height(),
+            hash: self.tip().and_then(|tip| tip.hash.clone()),
+            chain_work: encode(self.chain_work().to_be_bytes()),
+        }
+    }
+}
+
+/// The best chain's tip, as shown to the GUI.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChainTip {
+    pub network: Network,
+    /// blocks in the chain
+    pub height: u64,
+    pub hash: Option<String>,
+    /// total work as 32 bytes of big-endian hex
+    pub chain_work: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct SyncProgress {
+    /// headers downloaded and checked so far
+    pub height: u64,
+    /// the chain height the nodes reported when the sync started
+    pub network_height: u64,
+    /// why the sync stopped early, or why the stored headers are being downloaded again
+    pub error: Option<String>,
+}
+
+/// Finds the height from which the nodes' chain may differ from `chain`: the block after the
+/// last one whose hash both agree on, stepping back twice as far after every mismatch. Steps
+/// can overshoot the fork, which only costs headers `connect` skips as already known.
+async fn find_fork(nodes: &NodeClient, chain: &HeaderChain) -> Result<u64, WalletError> {
+    let mut start = chain.height();
+    let mut step = 1;
+    while start > 0 {
+        let theirs = nodes.headers(start - 1, 1).await?.value;
+        if let Some(header) = theirs.first() {
+            if chain.hash_at(start - 1) == Some(block_hash(header)?.as_str()) {
+                return Ok(start);
+            }
+        }
+        start = start.saturating_sub(step);
+        step *= 2;
+    }
+    Ok(0)
+}
+
+/// Downloads the nodes' headers past the point where they agree with `chain` and connects
+/// them, calling `progress` after every batch. Headers extending the tip are connected and
+/// saved batch by batch; a branch from further back is connected once it is complete, so its
+/// work can be weighed against the current chain's.
+pub async fn sync_headers<F>(nodes: &NodeClient, chain: &mut HeaderChain, mut progress: F) -> Result<ChainUpdate, WalletError>
+where
+    F: FnMut(SyncProgress),
+{
+    let network_height = nodes.chain_height().await?.value;
+    let mut start = find_fork(nodes, chain).await?;
+    let mut branch: Vec<BlockHeader> = Vec::new();
+    // the nodes that agreed on some batch of `branch`
+    let mut served: Vec<Node> = Vec::new();
+    let mut update = ChainUpdate::Unchanged;
+
+    loop {
+        let batch = nodes.headers(start + branch.len() as u64, HEADERS_PER_REQUEST).await?;
+        let complete = (batch.value.len() as u64) < HEADERS_PER_REQUEST;
+        branch.extend(batch.value);
+        for node in batch.report.agreeing {
+            if !served.contains(&node) {
+                served.push(node);
+            }
+        }
+
+        if start == chain.height() && !branch.is_empty() {
+            update = combine(update, connect_served(nodes, chain, start, &branch, &served)?);
+            start += branch.len() as u64;
+            branch.clear();
+            served.clear();
+        }
+        let height = start + branch.len() as u64;
+        progress(SyncProgress {
+            height,
+            network_height: network_height.max(height),
+            error: None,
+        });
+        if complete {
+            break;
+        }
+    }
+
+    if !branch.is_empty() {
+        update = combine(update, connect_served(nodes, chain, start, &branch, &served)?);
+    }
+    Ok(update)
+}
+
+// Connects `branch` and saves whatever part of it the chain kept. The quorum agreed on every
+// batch, so a header the chain rejects was served by each node in `served`, which all get a
+// strike for it.
+fn connect_served(nodes: &NodeClient, chain: &mut HeaderChain, start: u64, branch: &[BlockHeader], served: &[Node]) -> Result<ChainUpdate, WalletError> {
+    let connected = chain.connect(start, branch, unix_now());
+    chain.save()?;
+    if connected.is_err() {
+        nodes.strike_peers(served);
+    }
+    connected
+}
+
+// the effect of two updates in a row; a sync only ever extends the chain or reorganizes it once
+fn combine(first: ChainUpdate, second: ChainUpdate) -> ChainUpdate {
+    match (first, second) {
+        (ChainUpdate::Unchanged, update) | (update, ChainUpdate::Unchanged) => update,
+        (ChainUpdate::Extended { added }, ChainUpdate::Extended { added: more }) => ChainUpdate::Extended { added: added + more },
+        (ChainUpdate::Reorganized { fork_height, disconnected, added }, ChainUpdate::Extended { added: more }) => ChainUpdate::Reorganized {
+            fork_height,
+            disconnected,
+            added: added + more,
+        },
+        (_, reorganized) => reorganized,
+    }
+}
+
+/// `headers-<network>.dat`, next to the rest of the app data
+pub fn header_store_file(network: Network) -> String {
+    format!("headers-{}.dat", network.name())
+}
+
+/// The header chain of the selected network, synced in the background.
+pub struct ChainState {
+    dir: Option<PathBuf>,
+    chain: tokio::sync::Mutex<HeaderChain>,
+    // kept apart from the chain so reading it never waits for a sync to finish
+    tip: Mutex<ChainTip>,
+    // why the stored headers could not be loaded, until the next sync reports it
+    load_error: Mutex<Option<String>>,
+}
+
+impl ChainState {
+    /// keeps each network's headers in `dir` if there is one, loading `network`'s now
+    pub fn new(network: Network, dir: Option<PathBuf>) -> Self {
+        let (chain, load_error) = load_header_chain(dir.as_deref(), network);
+        Self {
+            tip: Mutex::new(chain.chain_tip()),
+            chain: tokio::sync::Mutex::new(chain),
+            load_error: Mutex::new(load_error),
+            dir,
+        }
+    }
+
+    pub fn tip(&self) -> ChainTip {
+        self.tip.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Syncs with `nodes`, first switching to the chain of their network if it changed. If the
+    /// stored headers could not be loaded, the first progress reported says why.
+    pub async fn sync<F>(&self, nodes: &NodeClient, mut progress: F) -> Result<ChainUpdate, WalletError>
+    where
+        F: FnMut(SyncProgress),
+    {
+        let mut chain = self.chain.lock().await;
+        let network = nodes.network().await;
+        if chain.network() != network {
+            let (loaded, load_error) = load_header_chain(self.dir.as_deref(), network);
+            *chain = loaded;
+            *self.load_error.lock().unwrap_or_else(PoisonError::into_inner) = load_error;
+            *self.tip.lock().unwrap_or_else(PoisonError::into_inner) = chain.chain_tip();
+        }
+        if let Some(error) = self.load_error.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            progress(SyncProgress {
+                height: chain.height(),
+                network_height: chain.height(),
+                error: Some(error),
+            });
+        }
+        let update = sync_headers(nodes, &mut chain, progress).await;
+        *self.tip.lock().unwrap_or_else(PoisonError::into_inner) = chain.chain_tip();
+        update
+    }
+}
+
+// the stored chain of `network`, or an empty one and why the stored one could not be loaded
+fn load_header_chain(dir: Option<&Path>, network: Network) -> (HeaderChain, Option<String>) {
+    let Some(dir) = dir else {
+        return (HeaderChain::new(network), None);
+    };
+    let path = dir.join(header_store_file(network));
+    match HeaderChain::load(&path, network) {
+        Ok(chain) => (chain, None),
+        Err(e) => (HeaderChain::at(&path, network), Some(format!("syncing the headers from scratch: {}", e))),
+    }
+}
+
+// syncs, emitting the progress and any error as `HEADER_SYNC_EVENT` and a new tip as
+// `CHAIN_TIP_EVENT`
+async fn sync_and_report(app: &AppHandle) -> Result<ChainTip, WalletError> {
+    let (chain, nodes) = (app.state::<ChainState>(), app.state::<NodeClient>());
+    let before = chain.tip();
+    let mut last = SyncProgress {
+        height: before.height,
+        network_height: before.height,
+        error: None,
+    };
+    let result = chain
+        .sync(&nodes, |progress| {
+            let _ = app.emit_all(HEADER_SYNC_EVENT, &progress);
+            last = progress;
+        })
+        .await;
+
+    // a failed sync may still have connected some batches
+    let tip = chain.tip();
+    if let Err(e) = &result {
+        let _ = app.emit_all(
+            HEADER_SYNC_EVENT,
+            SyncProgress {
+                height: tip.height,
+                error: Some(format!("header sync failed: {}", e)),
+                ..last
+            },
+        );
+    }
+    if tip != before {
+        let _ = app.emit_all(CHAIN_TIP_EVENT, &tip);
+    }
+    result.map(|_| tip)
+}
+
+/// syncs the headers every `SYNC_INTERVAL` for as long as the app runs; failures are reported
+/// through `HEADER_SYNC_EVENT` and the next round tries again
+pub fn spawn_header_sync(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let _ = sync_and_report(&app).await;
+            sleep(SYNC_INTERVAL).await;
+        }
+    });
+}
+
+/// syncs the headers now rather than at the next interval
+#[tauri::command]
+pub async fn sync_headers_now(app: AppHandle) -> Result<ChainTip, WalletError> {
+    sync_and_report(&app).await
+}
+
+#[tauri::command]
+pub fn get_chain_tip(chain: State<'_, ChainState>) -> ChainTip {
+    chain.tip()
+}
@@ -0,0 +1,315 @@
+// Canonical binary encoding for transactions and block headers: little-endian fixed-width
+// integers, CompactSize length prefixes, a 0/1 byte for optional fields and raw bytes for
+// hex fields. Decoding rejects anything that would not re-encode to the same bytes.
+
+use hex::{decode, encode};
+
+use crate::config::Network;
+use crate::crypto_utils::{double_sha256, hex_to_bytes, BlockHeader, Input, Output, Transaction};
+use crate::error::WalletError;
+
+/// `version | inputs | outputs` of a transaction with each input reduced to its outpoint and
+/// amount: what the txid commits to and what every input's signature covers.
+/// Signatures, public keys and the txid are left out so attaching them changes nothing.
+pub fn sighash_preimage(tx: &Transaction) -> Result<Vec<u8>, WalletError> {
+    let mut out = Vec::new();
+    write_u32(&mut out, tx.version);
+    write_compact_size(&mut out, tx.inputs.len() as u64);
+    for input in &tx.inputs {
+        write_outpoint(&mut out, input)?;
+    }
+    write_list(&mut out, &tx.outputs);
+    Ok(out)
+}
+
+/// `double_sha256(magic | preimage | input_index)`, signed by input `input_index` on `network`.
+/// Committing to the index keeps a signature from being reused for another input of the same
+/// transaction, and the magic from being replayed on another network.
+pub fn sighash(tx: &Transaction, input_index: u32, network: Network) -> Result<Vec<u8>, WalletError> {
+    let mut preimage = network.magic().to_vec();
+    preimage.extend(sighash_preimage(tx)?);
+    write_u32(&mut preimage, input_index);
+    Ok(double_sha256(&preimage))
+}
+
+/// Prefixed to every signed message, so no message signature is ever a valid sighash signature.
+pub const MESSAGE_MAGIC: &str = "CJ Coin Signed Message:\n";
+
+/// `double_sha256` of `MESSAGE_MAGIC` and `message`, each length-prefixed; what `sign_message`
+/// signs in place of the message itself
+pub fn message_hash(message: &str) -> Vec<u8> {
+    let mut preimage = Vec::new();
+    for part in [MESSAGE_MAGIC, message] {
+        write_compact_size(&mut preimage, part.len() as u64);
+        preimage.extend_from_slice(part.as_bytes());
+    }
+    double_sha256(&preimage)
+}
+
+/// hex `double_sha256` of the sighash preimage, so it is fixed before signing
+pub fn txid(tx: &Transaction) -> Result<String, WalletError> {
+    Ok(encode(double_sha256(&sighash_preimage(tx)?)))
+}
+
+/// hex `double_sha256` of the 88-byte header encoding, `hash` itself excluded
+pub fn block_hash(header: &BlockHeader) -> Result<String, WalletError> {
+    Ok(encode(double_sha256(&header.encode()?)))
+}
+
+/// A value with a canonical byte encoding.
+pub trait Codec: Sized {
+    fn encode_to(&self, out: &mut Vec<u8>) -> Result<(), WalletError>;
+
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, WalletError>;
+
+    fn encode(&self) -> Result<Vec<u8>, WalletError> {
+        let mut out = Vec::new();
+        self.encode_to(&mut out)?;
+        Ok(out)
+    }
+
+    /// decodes a whole buffer; trailing bytes are an error
+    fn decode(bytes: &[u8]) -> Result<Self, WalletError> {
+        let mut reader = Reader::new(bytes);
+        let value = Self::decode_from(&mut reader)?;
+        reader.finish()?;
+        Ok(value)
+    }
+}
+
+/// `prev_txid(32) | output_index(4) | amount(8)` followed by the optional signature and public key
+impl Codec for Input {
+    fn encode_to(&self, out: &mut Vec<u8>) -> Result<(), WalletError> {
+        write_outpoint(out, self)?;
+        write_optional_hex(out, self.signature.as_deref())?;
+        write_optional_hex(out, self.public_key.as_deref())
+    }
+
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, WalletError> {
+        Ok(Input {
+            prev_txid: encode(reader.read_array::<32>()?),
+            output_index: reader.read_u32()?,
+            amount: reader.read_u64()?,
+            signature: reader.read_optional_hex()?,
+            public_key: reader.read_optional_hex()?,
+        })
+    }
+}
+
+impl Codec for Output {
+    fn encode_to(&self, out: &mut Vec<u8>) -> Result<(), WalletError> {
+        write_u64(out, self.amount);
+        write_bytes(out, self.address.as_bytes());
+        Ok(())
+    }
+
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, WalletError> {
+        let amount = reader.read_u64()?;
+        let address = String::from_utf8(reader.read_bytes()?).map_err(|_| codec_error("address is not UTF-8"))?;
+        Ok(Output { amount, address })
+    }
+}
+
+/// Full encoding: `version | inputs | outputs` with every input's signature and public key.
+/// The txid is derived, so it is not stored and is filled in on decode.
+impl Codec for Transaction {
+    fn encode_to(&self, out: &mut Vec<u8>) -> Result<(), WalletError> {
+        write_u32(out, self.version);
+        write_compact_size(out, self.inputs.len() as u64);
+        for input in &self.inputs {
+            input.encode_to(out)?;
+        }
+        write_list(out, &self.outputs);
+        Ok(())
+    }
+
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, WalletError> {
+        let mut tx = Transaction {
+            version: reader.read_u32()?,
+            inputs: reader.read_list()?,
+            outputs: reader.read_list()?,
+            txid: None,
+        };
+        tx.txid = Some(txid(&tx)?);
+        Ok(tx)
+    }
+}
+
+/// `version(4) | prev_hash(32) | merkle_root(32) | timestamp(8) | nbits(4) | nonce(8)`.
+/// The hash is derived, so it is not stored and is filled in on decode.
+impl Codec for BlockHeader {
+    fn encode_to(&self, out: &mut Vec<u8>) -> Result<(), WalletError> {
+        write_u32(out, self.version);
+        out.extend(hex_to_bytes(&self.prev_hash)?);
+        out.extend(hex_to_bytes(&self.merkle_root)?);
+        write_u64(out, self.timestamp);
+        write_u32(out, self.nbits);
+        write_u64(out, self.nonce);
+        Ok(())
+    }
+
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, WalletError> {
+        let mut header = BlockHeader {
+            version: reader.read_u32()?,
+            prev_hash: encode(reader.read_array::<32>()?),
+            merkle_root: encode(reader.read_array::<32>()?),
+            timestamp: reader.read_u64()?,
+            nbits: reader.read_u32()?,
+            nonce: reader.read_u64()?,
+            hash: None,
+        };
+        header.hash = Some(block_hash(&header)?);
+        Ok(header)
+    }
+}
+
+/// Cursor over an encoded buffer.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    pub fn finish(&self) -> Result<(), WalletError> {
+        match self.remaining() {
+            0 => Ok(()),
+            extra => Err(codec_error(&format!("{} trailing bytes", extra))),
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], WalletError> {
+        if len > self.remaining() {
+            return Err(codec_error("unexpected end of data"));
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], WalletError> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, WalletError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, WalletError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, WalletError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    /// CompactSize, rejecting encodings longer than necessary
+    pub fn read_compact_size(&mut self) -> Result<u64, WalletError> {
+        let (value, min) = match self.read_u8()? {
+            0xfd => (u16::from_le_bytes(self.read_array()?) as u64, 0xfd),
+            0xfe => (u32::from_le_bytes(self.read_array()?) as u64, 0x1_0000),
+            0xff => (self.read_u64()?, 0x1_0000_0000),
+            small => return Ok(small as u64),
+        };
+        if value < min {
+            return Err(codec_error("non-canonical length prefix"));
+        }
+        Ok(value)
+    }
+
+    // a length can never exceed the bytes left, which also bounds allocations
+    fn read_len(&mut self) -> Result<usize, WalletError> {
+        let len = self.read_compact_size()?;
+        if len > self.remaining() as u64 {
+            return Err(codec_error("length prefix exceeds data"));
+        }
+        Ok(len as usize)
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, WalletError> {
+        let len = self.read_len()?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub fn read_list<T: Codec>(&mut self) -> Result<Vec<T>, WalletError> {
+        let len = self.read_len()?;
+        (0..len).map(|_| T::decode_from(self)).collect()
+    }
+
+    fn read_optional_hex(&mut self) -> Result<Option<String>, WalletError> {
+        match self.read_u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(encode(self.read_bytes()?))),
+            flag => Err(codec_error(&format!("invalid presence flag {}", flag))),
+        }
+    }
+}
+
+pub fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend(value.to_le_bytes());
+}
+
+pub fn write_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend(value.to_le_bytes());
+}
+
+pub fn write_compact_size(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xfc => out.push(value as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend((value as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend((value as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend(value.to_le_bytes());
+        }
+    }
+}
+
+pub fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_compact_size(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+// outputs never fail to encode
+fn write_list(out: &mut Vec<u8>, outputs: &[Output]) {
+    write_compact_size(out, outputs.len() as u64);
+    for output in outputs {
+        output.encode_to(out).expect("outputs always encode");
+    }
+}
+
+// what an input spends, without its signature
+fn write_outpoint(out: &mut Vec<u8>, input: &Input) -> Result<(), WalletError> {
+    out.extend(hex_to_bytes(&input.prev_txid)?);
+    write_u32(out, input.output_index);
+    write_u64(out, input.amount);
+    Ok(())
+}
+
+fn write_optional_hex(out: &mut Vec<u8>, value: Option<&str>) -> Result<(), WalletError> {
+    match value {
+        None => out.push(0),
+        Some(hex_str) => {
+            out.push(1);
+            write_bytes(out, &decode(hex_str)?);
+        }
+    }
+    Ok(())
+}
+
+fn codec_error(message: &str) -> WalletError {
+    WalletError::Codec(message.to_string())
+}
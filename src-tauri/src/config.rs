@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use bech32::Hrp;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::api::NodeClient;
+use crate::error::WalletError;
+
+/// file name of the network config inside the app config directory
+pub const NETWORK_CONFIG_FILE: &str = "network.json";
+
+/// The chain the wallet talks to. Each network has its own address prefix and magic
+/// bytes, so an address or message from one is rejected by the others.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+    #[serde(alias = "local")]
+    Regtest,
+}
+
+impl Network {
+    pub const ALL: [Network; 3] = [Network::Mainnet, Network::Testnet, Network::Regtest];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    /// human-readable part of this network's bech32m addresses
+    pub fn hrp(self) -> Hrp {
+        match self {
+            Network::Mainnet => Hrp::parse_unchecked("cj"),
+            Network::Testnet => Hrp::parse_unchecked("tcj"),
+            Network::Regtest => Hrp::parse_unchecked("cjrt"),
+        }
+    }
+
+    /// bytes identifying this network on the wire
+    pub fn magic(self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => [0xc3, 0x6a, 0x63, 0xd9],
+            Network::Testnet => [0x0b, 0x63, 0x6a, 0x74],
+            Network::Regtest => [0xfa, 0x63, 0x6a, 0x72],
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            Network::Mainnet => 8133,
+            Network::Testnet => 18133,
+            Network::Regtest => 18233,
+        }
+    }
+
+    pub fn default_seed_nodes(self) -> Vec<String> {
+        match self {
+            Network::Mainnet => vec!["192.168.1.117:8133".to_string()],
+            Network::Testnet => vec!["192.168.1.117:18133".to_string()],
+            Network::Regtest => vec!["127.0.0.1:18233".to_string()],
+        }
+    }
+
+    /// the network whose addresses use `hrp`, if any
+    pub fn from_hrp(hrp: &Hrp) -> Option<Network> {
+        Network::ALL.into_iter().find(|network| network.hrp() == *hrp)
+    }
+}
+
+/// Everything the wallet needs to know about one network, as shown to the frontend.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NetworkProfile {
+    pub network: Network,
+    pub address_prefix: String,
+    pub magic: String,
+    pub seed_nodes: Vec<String>,
+}
+
+impl NetworkProfile {
+    pub fn new(network: Network, seed_nodes: Vec<String>) -> Self {
+        Self {
+            network,
+            address_prefix: network.hrp().to_string(),
+            magic: hex::encode(network.magic()),
+            seed_nodes,
+        }
+    }
+}
+
+impl From<Network> for NetworkProfile {
+    fn from(network: Network) -> Self {
+        Self::new(network, network.default_seed_nodes())
+    }
+}
+
+/// The selected network and the seed nodes of every profile, persisted as JSON.
+/// Prefixes and magic bytes are fixed per network and never read from disk.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NetworkConfig {
+    #[serde(default)]
+    pub selected: Network,
+    #[serde(default)]
+    pub seed_nodes: BTreeMap<Network, Vec<String>>,
+}
+
+impl NetworkConfig {
+    /// reads `path`, falling back to the defaults if the file does not exist yet
+    pub fn load(path: &Path) -> Result<Self, WalletError> {
+        match fs::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Reads `path` like `load`, but starts from the defaults if it cannot. A file that cannot
+    /// be read is moved aside to `<path>.invalid` first, so saving the defaults does not
+    /// overwrite it. The message says what happened, for the settings page.
+    pub fn load_or_default(path: &Path) -> (Self, Option<String>) {
+        let e = match Self::load(path) {
+            Ok(config) => return (config, None),
+            Err(e) => e,
+        };
+        let aside = path.with_extension("json.invalid");
+        let message = match fs::rename(path, &aside) {
+            Ok(()) => format!("could not read the network config, so the defaults are used and the file was moved to {}: {}", aside.display(), e),
+            Err(moved) => format!("could not read the network config, so the defaults are used and cannot be saved until {} is fixed or removed: {} ({})", path.display(), e, moved),
+        };
+        (Self::default(), Some(message))
+    }
+
+    /// writes the config to `path`, refusing to replace a file there that is not a config
+    pub fn save(&self, path: &Path) -> Result<(), WalletError> {
+        Self::load(path).map_err(|e| WalletError::Config(format!("{} was left as it is, it does not hold a network config: {}", path.display(), e)))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// configured seed nodes of `network`, or its defaults if none were set
+    pub fn seed_nodes(&self, network: Network) -> Vec<String> {
+        match self.seed_nodes.get(&network) {
+            Some(nodes) if !nodes.is_empty() => nodes.clone(),
+            _ => network.default_seed_nodes(),
+        }
+    }
+
+    /// replaces the seed nodes of `network`; an empty list restores the defaults
+    pub fn set_seed_nodes(&mut self, network: Network, seed_nodes: Vec<String>) -> Result<(), WalletError> {
+        let seed_nodes: Vec<String> = seed_nodes.iter().map(|node| node.trim().to_string()).filter(|node| !node.is_empty()).collect();
+        for node in &seed_nodes {
+            check_seed_node(node)?;
+        }
+
+        if seed_nodes.is_empty() {
+            self.seed_nodes.remove(&network);
+        } else {
+            self.seed_nodes.insert(network, seed_nodes);
+        }
+        Ok(())
+    }
+
+    pub fn profile(&self, network: Network) -> NetworkProfile {
+        NetworkProfile::new(network, self.seed_nodes(network))
+    }
+
+    pub fn selected_profile(&self) -> NetworkProfile {
+        self.profile(self.selected)
+    }
+
+    pub fn profiles(&self) -> Vec<NetworkProfile> {
+        Network::ALL.into_iter().map(|network| self.profile(network)).collect()
+    }
+}
+
+/// Why the network config could not be read at startup, if it could not; managed next to
+/// the config so the settings page can show it.
+pub struct ConfigLoadError(pub Option<String>);
+
+// seed nodes are `host:port`; the host may be a name or an IPv4 address
+fn check_seed_node(node: &str) -> Result<(), WalletError> {
+    let invalid = || WalletError::Config(format!("seed node \"{}\" is not host:port", node));
+
+    let (host, port) = node.rsplit_once(':').ok_or_else(invalid)?;
+    if host.is_empty() || host.contains(['/', ' ']) || !matches!(port.parse::<u16>(), Ok(port) if port != 0) {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// `network.json` in the app config directory
+pub fn config_path(app: &AppHandle) -> Result<PathBuf, WalletError> {
+    let dir = app
+        .path_resolver()
+        .app_config_dir()
+        .ok_or_else(|| WalletError::Config("no app config directory".to_string()))?;
+    Ok(dir.join(NETWORK_CONFIG_FILE))
+}
+
+/// the network currently selected in the managed config
+pub fn selected_network(config: &Mutex<NetworkConfig>) -> Network {
+    config.lock().unwrap_or_else(PoisonError::into_inner).selected
+}
+
+// Applies `update` to the managed config, saves it and points the node client at the
+// selected profile if that changed, which drops any nodes discovered on the previous one.
+// The node client's profile stays locked throughout, so two updates cannot interleave.
+async fn update_config<F>(app: &AppHandle, config: &Mutex<NetworkConfig>, nodes: &NodeClient, update: F) -> Result<NetworkProfile, WalletError>
+where
+    F: FnOnce(&mut NetworkConfig) -> Result<(), WalletError>,
+{
+    nodes
+        .update_profile(|| {
+            let mut config = config.lock().unwrap_or_else(PoisonError::into_inner);
+            let mut updated = config.clone();
+            update(&mut updated)?;
+            updated.save(&config_path(app)?)?;
+
+            *config = updated;
+            Ok(config.selected_profile())
+        })
+        .await
+}
+
+#[derive(Serialize)]
+pub struct NetworkSettings {
+    selected: Network,
+    profiles: Vec<NetworkProfile>,
+    load_error: Option<String>,
+}
+
+#[tauri::command]
+pub fn get_network_config(config: State<'_, Mutex<NetworkConfig>>, load_error: State<'_, ConfigLoadError>) -> NetworkSettings {
+    let config = config.lock().unwrap_or_else(PoisonError::into_inner);
+    NetworkSettings {
+        selected: config.selected,
+        profiles: config.profiles(),
+        load_error: load_error.0.clone(),
+    }
+}
+
+/// switches profiles; the wallet's address changes with the network's prefix
+#[tauri::command]
+pub async fn select_network(
+    network: Network,
+    app: AppHandle,
+    config: State<'_, Mutex<NetworkConfig>>,
+    nodes: State<'_, NodeClient>,
+) -> Result<NetworkProfile, WalletError> {
+    update_config(&app, &config, &nodes, |config| {
+        config.selected = network;
+        Ok(())
+    })
+    .await
+}
+
+/// an empty list restores the network's default seed nodes
+#[tauri::command]
+pub async fn set_seed_nodes(
+    network: Network,
+    seed_nodes: Vec<String>,
+    app: AppHandle,
+    config: State<'_, Mutex<NetworkConfig>>,
+    nodes: State<'_, NodeClient>,
+) -> Result<NetworkProfile, WalletError> {
+    update_config(&app, &config, &nodes, |config| config.set_seed_nodes(network, seed_nodes)).await?;
+    Ok(config.lock().unwrap_or_else(PoisonError::into_inner).profile(network))
+}
@@ -0,0 +1,158 @@
+// Difficulty rules: how much work each block must prove, retargeted every interval so blocks
+// keep arriving at the target block time however much hash power joins or leaves.
+
+use crate::codec::block_hash;
+use crate::config::Network;
+use crate::crypto_utils::BlockHeader;
+use crate::error::WalletError;
+use crate::miner::GENESIS_PREV_HASH;
+use crate::pow::{check_proof_of_work, nbits_from_target, target_from_nbits, U256};
+
+/// a block must be later than the median timestamp of this many blocks before it
+pub const MEDIAN_TIME_SPAN: usize = 11;
+/// how far ahead of the local clock a block's timestamp may be, in seconds
+pub const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+
+/// The chain rules difficulty follows on one network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConsensusParams {
+    /// seconds a block should take on average
+    pub target_block_time: u64,
+    /// blocks between difficulty adjustments
+    pub retarget_interval: u64,
+    /// the most one adjustment may raise or lower the target, as a factor
+    pub max_adjustment_factor: u64,
+    /// the easiest target, as nbits; the first block is mined at it
+    pub pow_limit: u32,
+    /// false keeps every block at the first block's difficulty, for local testing
+    pub retargeting: bool,
+}
+
+impl ConsensusParams {
+    pub fn for_network(network: Network) -> Self {
+        let mainnet = Self {
+            target_block_time: 600,
+            retarget_interval: 2016,
+            max_adjustment_factor: 4,
+            pow_limit: 0x1f00ffff,
+            retargeting: true,
+        };
+        match network {
+            Network::Mainnet | Network::Testnet => mainnet,
+            Network::Regtest => Self {
+                pow_limit: 0x207fffff,
+                retargeting: false,
+                ..mainnet
+            },
+        }
+    }
+
+    /// seconds one retarget interval should take
+    pub fn target_timespan(&self) -> u64 {
+        self.retarget_interval.saturating_mul(self.target_block_time)
+    }
+
+    pub fn pow_limit_target(&self) -> Result<U256, WalletError> {
+        target_from_nbits(self.pow_limit)
+    }
+
+    /// whether the block at `height` gets a new target rather than its parent's
+    pub fn is_retarget_height(&self, height: u64) -> bool {
+        self.retargeting && height > 0 && height % self.retarget_interval.max(1) == 0
+    }
+
+    /// how many of the headers right before the block at `height` `next_required_nbits` reads
+    pub fn headers_needed(&self, height: u64) -> u64 {
+        match height {
+            0 => 0,
+            _ if self.is_retarget_height(height) => self.retarget_interval,
+            _ => 1,
+        }
+    }
+
+    /// how many of the headers right before the block at `height` `check_header` reads
+    pub fn context_needed(&self, height: u64) -> u64 {
+        self.headers_needed(height).max(MEDIAN_TIME_SPAN as u64).min(height)
+    }
+}
+
+/// The nbits the block at `height` must carry, from the `prev_headers` right before it, oldest
+/// first; at least `params.headers_needed(height)` of them. The first block gets the pow limit
+/// and most blocks their parent's nbits. Every `retarget_interval` blocks the target is scaled
+/// by how long the last interval took against `target_timespan`, measured from the interval's
+/// first block to its last as Bitcoin does. The scale is clamped to `max_adjustment_factor`
+/// either way, and the result to the pow limit.
+pub fn next_required_nbits(params: &ConsensusParams, height: u64, prev_headers: &[BlockHeader]) -> Result<u32, WalletError> {
+    let needed = params.headers_needed(height);
+    if (prev_headers.len() as u64) < needed {
+        return Err(WalletError::InvalidBlock(format!("block {} needs the {} headers before it, got {}", height, needed, prev_headers.len())));
+    }
+    let Some(last) = prev_headers.last() else {
+        return Ok(params.pow_limit);
+    };
+    if !params.is_retarget_height(height) {
+        return Ok(last.nbits);
+    }
+
+    let first = &prev_headers[prev_headers.len() - needed as usize];
+    let target_timespan = params.target_timespan().max(1);
+    let factor = params.max_adjustment_factor.max(1);
+    let timespan = last.timestamp.saturating_sub(first.timestamp).clamp(target_timespan / factor, target_timespan.saturating_mul(factor));
+
+    // target * timespan / target_timespan, split so the product cannot overflow
+    let (quotient, remainder) = target_from_nbits(last.nbits)?.div_rem_u64(target_timespan);
+    let carried = (remainder as u128 * timespan as u128 / target_timespan as u128) as u64;
+    let scaled = quotient.checked_mul_u64(timespan).and_then(|scaled| scaled.checked_add(U256::from(carried))).unwrap_or(U256::MAX);
+
+    Ok(nbits_from_target(scaled.min(params.pow_limit_target()?)))
+}
+
+/// fails unless `header`, the block at `height`, carries the nbits `next_required_nbits` gives
+pub fn check_nbits(params: &ConsensusParams, height: u64, prev_headers: &[BlockHeader], header: &BlockHeader) -> Result<(), WalletError> {
+    let required = next_required_nbits(params, height, prev_headers)?;
+    if header.nbits != required {
+        return Err(WalletError::InvalidBlock(format!("block {} has nbits {:#010x}, the rules require {:#010x}", height, header.nbits, required)));
+    }
+    Ok(())
+}
+
+/// the median timestamp of the last `MEDIAN_TIME_SPAN` of `prev_headers`, if there are any
+pub fn median_time_past(prev_headers: &[BlockHeader]) -> Option<u64> {
+    let mut timestamps: Vec<u64> = prev_headers.iter().rev().take(MEDIAN_TIME_SPAN).map(|header| header.timestamp).collect();
+    timestamps.sort_unstable();
+    timestamps.get(timestamps.len() / 2).copied()
+}
+
+/// Fails unless `header` is later than the median time past of the `prev_headers` right before
+/// it and at most `MAX_FUTURE_BLOCK_TIME` ahead of `now`.
+pub fn check_timestamp(prev_headers: &[BlockHeader], header: &BlockHeader, now: u64) -> Result<(), WalletError> {
+    if let Some(median) = median_time_past(prev_headers) {
+        if header.timestamp <= median {
+            return Err(WalletError::InvalidBlock(format!("timestamp {} is not after the median time past {}", header.timestamp, median)));
+        }
+    }
+    if header.timestamp > now.saturating_add(MAX_FUTURE_BLOCK_TIME) {
+        return Err(WalletError::InvalidBlock(format!("timestamp {} is too far in the future", header.timestamp)));
+    }
+    Ok(())
+}
+
+/// Every rule a header alone must follow to be the block at `height`: it links to the last of
+/// `prev_headers`, at least `params.context_needed(height)` of them, meets its target, has a
+/// plausible timestamp and carries the required nbits.
+pub fn check_header(params: &ConsensusParams, height: u64, prev_headers: &[BlockHeader], header: &BlockHeader, now: u64) -> Result<(), WalletError> {
+    let needed = params.context_needed(height);
+    if (prev_headers.len() as u64) < needed {
+        return Err(WalletError::InvalidBlock(format!("block {} needs the {} headers before it, got {}", height, needed, prev_headers.len())));
+    }
+    let prev_hash = match prev_headers.last() {
+        Some(prev) if height > 0 => block_hash(prev)?,
+        _ => GENESIS_PREV_HASH.to_string(),
+    };
+    if header.prev_hash != prev_hash {
+        return Err(WalletError::InvalidBlock(format!("block {} does not follow the block before it", height)));
+    }
+    check_proof_of_work(header)?;
+    check_timestamp(prev_headers, header, now)?;
+    check_nbits(params, height, prev_headers, header)
+}
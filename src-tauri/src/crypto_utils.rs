@@ -1,29 +1,36 @@
-use std::{
-    fs,
-    io,
-    path::Path,
-};
+use std::time::{Duration, Instant};
 
 use aes::Aes256;
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
 use block_modes::{BlockMode, Cbc};
 use block_modes::block_padding::Pkcs7;
 use hex::{decode, encode};
 use k256::{
-	sha2,
     ecdsa::{
         signature::{Signer, Verifier},
         Signature, SigningKey, VerifyingKey,
     },
     SecretKey,
 };
-use pbkdf2::pbkdf2_hmac_array;
+use pbkdf2::{pbkdf2_hmac, pbkdf2_hmac_array};
 use rand::{rngs::OsRng, thread_rng, Rng};
+use ripemd::Ripemd160;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::WalletError;
+use crate::merkle::MerkleTree;
+use crate::secret::SecretBytes;
 
 
 type Aes256Cbc = Cbc<Aes256, Pkcs7>;
 
+// Pre-keystore wallet files, kept so older installs can still be read
 #[derive(Serialize, Deserialize)]
 pub struct AesKeyData {
     pub hmac_array: String,
@@ -33,63 +40,80 @@ pub struct AesKeyData {
 
 #[derive(Serialize, Deserialize)]
 pub struct KeyPairData {
-    encrypted_private_key: String,
-    public_key: String,
-    iv: String,
+    pub encrypted_private_key: String,
+    pub public_key: String,
+    pub iv: String,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Node {
-    ipv4_address: String,
-    port: u16,
+// Network and chain models, shared with the node API
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub ipv4_address: String,
+    pub port: u16,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Input {
-    amount: u64,
+/// Spends output `output_index` of transaction `prev_txid`. `amount` repeats that output's
+/// amount so the signature commits to it; signature and public key are set per input.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Input {
+    pub prev_txid: String,
+    pub output_index: u32,
+    pub amount: u64,
+    pub signature: Option<String>,
+    pub public_key: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Output {
-    amount: u64,
-    address: String,
+impl Input {
+    pub fn unsigned(prev_txid: impl Into<String>, output_index: u32, amount: u64) -> Self {
+        Self {
+            prev_txid: prev_txid.into(),
+            output_index,
+            amount,
+            signature: None,
+            public_key: None,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Transaction {
-    version: u32,
-    inputs: Vec<Input>,
-    outputs: Vec<Output>,
-    signature: Option<String>,
-    txid: Option<String>,
-    public_key: Option<String>,
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Output {
+    pub amount: u64,
+    pub address: String,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct BlockHeader {
-    version: u32,
-    prev_hash: String,
-    merkle_root: String,
-    timestamp: u64,
-    nbits: u32,
-    nonce: u64,
-    hash: Option<String>,
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    pub version: u32,
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<Output>,
+    pub txid: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct Block {
-    header: BlockHeader,
-    txs: Vec<Transaction>,
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub prev_hash: String,
+    pub merkle_root: String,
+    pub timestamp: u64,
+    pub nbits: u32,
+    pub nonce: u64,
+    pub hash: Option<String>,
 }
 
-type KeyData = AesKeyData;
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub txs: Vec<Transaction>,
+}
 
 // Utility
-pub fn hex_to_bytes(hex_str: &str) -> [u8; 32] {
-    decode(hex_str)
-        .expect("Unable to decode hex string.")
-        .try_into()
-        .expect("Expected a 32-byte vector")
+pub fn hex_to_bytes(hex_str: &str) -> Result<[u8; 32], WalletError> {
+    to_array(decode(hex_str)?)
+}
+
+pub fn to_array<const N: usize>(bytes: Vec<u8>) -> Result<[u8; N], WalletError> {
+    let actual = bytes.len();
+    bytes.try_into().map_err(|_| WalletError::KeyLength { expected: N, actual })
 }
 
 fn generate_random_bytes(size: usize) -> Vec<u8> {
@@ -98,184 +122,163 @@ fn generate_random_bytes(size: usize) -> Vec<u8> {
     buffer
 }
 
-fn generate_salt() -> Vec<u8> {
-    generate_random_bytes(8)
-}
-
-fn generate_iv() -> Vec<u8> {
+pub fn generate_salt() -> Vec<u8> {
     generate_random_bytes(16)
 }
 
-fn derive_key(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
-    pbkdf2_hmac_array::<Sha256, 32>(password, salt, iterations)
+fn generate_nonce() -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    thread_rng().fill(&mut nonce);
+    nonce
 }
 
-// Key Generation
-pub fn generate_key(password: &str, iterations: u32) -> ([u8; 32], Vec<u8>, u32) {
-    let password_bytes = password.as_bytes();
-    let salt = generate_salt();
-    let key = derive_key(password_bytes, &salt, iterations);
-    (key, salt, iterations)
+pub fn derive_key(password: &[u8], salt: &[u8], iterations: u32) -> Zeroizing<[u8; 32]> {
+    Zeroizing::new(pbkdf2_hmac_array::<Sha256, 32>(password, salt, iterations))
 }
 
-#[tauri::command]
-pub fn generate_aes_key_to_file(path: &str, password: &str, iterations: u32) -> Result<(), String> {
-    let (key, salt, iterations) = generate_key(password, iterations);
-
-    let data = AesKeyData {
-        hmac_array: encode(key),
-        salt: encode(&salt),
-        iterations,
-    };
-
-    let json = serde_json::to_string_pretty(&data).map_err(|e| e.to_string())?;
-    fs::write(path, json).map_err(|e| e.to_string())?;
-
-    Ok(())
+/// derives 64 bytes of key material: an encryption key followed by a MAC key
+pub fn derive_key_material(password: &[u8], salt: &[u8], iterations: u32) -> Zeroizing<[u8; 64]> {
+    let mut material = Zeroizing::new([0u8; 64]);
+    pbkdf2_hmac::<Sha256>(password, salt, iterations, material.as_mut());
+    material
 }
 
-pub fn load_aes_key_from_file(path: &str) -> io::Result<(String, String, u32)> {
-    let content = fs::read_to_string(path)?;
-    let data: AesKeyData = serde_json::from_str(&content)?;
+pub const ARGON2_DEFAULT_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_MIN_ITERATIONS: u32 = 2;
+const ARGON2_MAX_ITERATIONS: u32 = 64;
 
-    Ok((data.hmac_array, data.salt, data.iterations))
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Argon2Params {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
 }
 
-// AES Encryption
-pub fn aes_encrypt(key: [u8; 32], plain_text: &str) -> (String, String) {
-    let iv = generate_iv();
-    let cipher = Aes256Cbc::new_from_slices(&key, &iv).unwrap();
-    let cipher_text = cipher.encrypt_vec(plain_text.as_bytes());
-
-    (encode(cipher_text), encode(iv))
+/// Argon2id; fails if the parameters or salt are out of range
+pub fn derive_key_argon2id(password: &[u8], salt: &[u8], params: &Argon2Params) -> Result<Zeroizing<[u8; 32]>, WalletError> {
+    let params = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(32))
+        .map_err(|e| WalletError::Crypto(e.to_string()))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    argon2
+        .hash_password_into(password, salt, key.as_mut())
+        .map_err(|e| WalletError::Crypto(e.to_string()))?;
+    Ok(key)
 }
 
-pub fn aes_decrypt(hex_key: &str, hex_iv: &str, cipher_text: Vec<u8>) -> String {
-    let key = hex_to_bytes(hex_key);
-    let iv = hex_to_bytes(hex_iv);
+/// picks the Argon2id iteration count that takes roughly `target` to unlock on this machine
+pub fn calibrate_argon2id(target: Duration, memory_kib: u32) -> Argon2Params {
+    let mut params = Argon2Params {
+        memory_kib,
+        iterations: 1,
+        parallelism: 1,
+    };
 
-    let cipher = Aes256Cbc::new_from_slices(&key, &iv).unwrap();
-    let decrypted = cipher.decrypt_vec(&cipher_text).unwrap();
+    let start = Instant::now();
+    let _ = derive_key_argon2id(b"calibration", &[0u8; 16], &params);
+    let single_pass = start.elapsed().max(Duration::from_micros(1));
 
-    String::from_utf8(decrypted).unwrap()
+    let iterations = (target.as_secs_f64() / single_pass.as_secs_f64()).round() as u32;
+    params.iterations = iterations.clamp(ARGON2_MIN_ITERATIONS, ARGON2_MAX_ITERATIONS);
+    params
 }
 
-#[tauri::command]
-pub fn decrypt_file(aes_key_data_path: String, key_pair_data_path: String, password: Vec<u8>) -> Vec<u8> {
-    // load key data for salt and iterations
-    let key_data_str = fs::read_to_string(&aes_key_data_path).expect("Failed to read key data");
-    let key_data: KeyData = serde_json::from_str(&key_data_str).expect("Failed to parse key data");
-
-    // load KeyPairData for IV
-    let keypair_data_str = fs::read_to_string(key_pair_data_path).expect("Failed to read keypair data");
-    let keypair_data: KeyPairData = serde_json::from_str(&keypair_data_str).expect("Failed to parse keypair data");
-    let hex_iv = &keypair_data.iv;
-
-    // derive key
-    let salt = hex_to_bytes(&key_data.salt);
-    let derived_key = derive_key(&password, &salt, key_data.iterations);
-    let hex_key = encode(derived_key);
+// Legacy AES-CBC
+// Only ever decrypts: CBC carries no MAC, so nothing new is written with it. Kept for
+// version 1 keystores and the pre-keystore files, which are migrated on unlock.
+pub(crate) fn aes_decrypt(key: &[u8; 32], iv: &[u8], cipher_text: &[u8]) -> Result<SecretBytes, WalletError> {
+    let cipher = Aes256Cbc::new_from_slices(key, iv).map_err(|_| WalletError::KeyLength { expected: 16, actual: iv.len() })?;
+    let decrypted = cipher.decrypt_vec(cipher_text).map_err(|_| WalletError::WrongPasswordOrCorrupt)?;
 
-    // reads ciphertext
-    let cipher_text = fs::read(&aes_key_data_path).expect("Failed to read ciphertext file");
-
-    aes_decrypt(&hex_key, hex_iv, cipher_text).into_bytes()
+    Ok(SecretBytes::new(decrypted))
 }
 
-/// generates private and public cryptographic keys
-#[tauri::command]
-pub fn generate_key_pair_to_file(path: &str, hex_key: &str) -> Result<(), String> {
-    let vec_key = decode(hex_key).map_err(|e| e.to_string())?;
-    let key: [u8; 32] = vec_key.try_into().map_err(|_| "Expected a 32-byte vector".to_string())?;
-
-    let (private_key, public_key) = generate_key_pair();
-    let (encrypted_private_key, iv) = aes_encrypt(key, &private_key);
-
-    let data = KeyPairData {
-        encrypted_private_key,
-        public_key,
-        iv,
-    };
-
-    let json = serde_json::to_string_pretty(&data).map_err(|e| e.to_string())?;
-    fs::write(path, json).map_err(|e| e.to_string())?;
-
-    Ok(())
+// Authenticated Encryption
+/// AES-256-GCM; returns (cipher_text, tag, nonce). `aad` is authenticated but not encrypted
+pub fn aead_encrypt(key: &[u8; 32], plain_text: &[u8], aad: &[u8]) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let nonce = generate_nonce();
+    let cipher = Aes256Gcm::new(key.into());
+    let mut sealed = cipher
+        .encrypt(&Nonce::from(nonce), Payload { msg: plain_text, aad })
+        .expect("AES-GCM encryption of an in-memory buffer cannot fail");
+
+    let tag = sealed.split_off(sealed.len() - 16);
+    (sealed, tag, nonce.to_vec())
 }
 
-pub fn load_key_pair_from_file(path: &str) -> io::Result<(Vec<u8>, Vec<u8>, [u8; 32])> {
-    let content = fs::read_to_string(path)?;
-    let data: KeyPairData = serde_json::from_str(&content)?;
+/// fails with `WrongPasswordOrCorrupt` if the key is wrong or any of cipher_text, tag, nonce or aad were altered
+pub fn aead_decrypt(key: &[u8; 32], nonce: &[u8], cipher_text: &[u8], tag: &[u8], aad: &[u8]) -> Result<SecretBytes, WalletError> {
+    let nonce: [u8; 12] = to_array(nonce.to_vec())?;
+    if tag.len() != 16 {
+        return Err(WalletError::KeyLength { expected: 16, actual: tag.len() });
+    }
 
-    let encrypted_private_key = decode(&data.encrypted_private_key).expect("Invalid hex private key");
-    let public_key = decode(&data.public_key).expect("Invalid hex public key");
-    let iv_vec = decode(&data.iv).expect("Invalid hex iv");
-    let iv: [u8; 32] = iv_vec.try_into().expect("Expected a 32-byte vector");
+    let cipher = Aes256Gcm::new(key.into());
+    let sealed = [cipher_text, tag].concat();
 
-    Ok((encrypted_private_key, public_key, iv))
+    cipher
+        .decrypt(&Nonce::from(nonce), Payload { msg: &sealed, aad })
+        .map(SecretBytes::new)
+        .map_err(|_| WalletError::WrongPasswordOrCorrupt)
 }
 
-pub fn generate_key_pair() -> (String, String) {
+/// generates private and public cryptographic keys
+pub fn generate_key_pair() -> (SecretBytes, String) {
     let secret_key = SecretKey::random(&mut OsRng);
     let signing_key = SigningKey::from(&secret_key);
     let verifying_key = signing_key.verifying_key();
 
-    (encode(secret_key.to_bytes()), encode(verifying_key.to_sec1_bytes()))
+    let mut private_bytes: [u8; 32] = secret_key.to_bytes().into();
+    let private_key = SecretBytes::new(private_bytes.to_vec());
+    private_bytes.zeroize();
+
+    (private_key, encode(verifying_key.to_sec1_bytes()))
 }
 
-pub fn sign(private_key_hex: &str, message: &str) -> (String, String) {
-    let private_bytes = decode(private_key_hex).unwrap();
-    let private_array: [u8; 32] = private_bytes.try_into().expect("Expected 32-byte private key");
+fn signing_key_from_secret(private_key: &SecretBytes) -> Result<SigningKey, WalletError> {
+    let private_array = private_key.to_array::<32>()?;
+    Ok(SigningKey::from_slice(private_array.as_slice())?)
+}
 
-    let signing_key = SigningKey::from_bytes(&private_array.into()).unwrap();
-    let signature: Signature = signing_key.sign(message.as_bytes());
+/// recomputes the SEC1 public key for a private key
+pub fn public_key_from_private(private_key: &SecretBytes) -> Result<String, WalletError> {
+    let signing_key = signing_key_from_secret(private_key)?;
+    Ok(encode(signing_key.verifying_key().to_sec1_bytes()))
+}
+
+pub fn sign(private_key: &SecretBytes, message: impl AsRef<[u8]>) -> Result<(String, String), WalletError> {
+    let signing_key = signing_key_from_secret(private_key)?;
+    let signature: Signature = signing_key.sign(message.as_ref());
     let verifying_key = signing_key.verifying_key();
 
-    (encode(signature.to_der()), encode(verifying_key.to_sec1_bytes()))
+    Ok((encode(signature.to_der()), encode(verifying_key.to_sec1_bytes())))
 }
 
-pub fn verify(public_key_hex: &str, message: &str, signature_hex: &str) -> bool {
-    let public_bytes = decode(public_key_hex).unwrap();
-    let signature_bytes = decode(signature_hex).unwrap();
+/// `Ok(false)` means well-formed inputs with a signature that does not match;
+/// malformed hex, keys or DER are reported as errors
+pub fn verify(public_key_hex: &str, message: impl AsRef<[u8]>, signature_hex: &str) -> Result<bool, WalletError> {
+    let public_bytes = decode(public_key_hex)?;
+    let signature_bytes = decode(signature_hex)?;
 
-    let public_key = VerifyingKey::from_sec1_bytes(&public_bytes).unwrap();
-    let signature = Signature::from_der(&signature_bytes).unwrap();
+    let public_key = VerifyingKey::from_sec1_bytes(&public_bytes)?;
+    let signature = Signature::from_der(&signature_bytes)?;
 
-    public_key.verify(message.as_bytes(), &signature).is_ok()
+    Ok(public_key.verify(message.as_ref(), &signature).is_ok())
 }
 
 // Hashing
 pub fn double_sha256(data: &[u8]) -> Vec<u8> {
     let first_hash = Sha256::digest(data);
-    Sha256::digest(&first_hash).to_vec()
+    Sha256::digest(first_hash).to_vec()
 }
 
-pub fn calculate_merkle_root(txs: Vec<Vec<u8>>) -> Vec<u8> {
-    if txs.is_empty() {
-        return vec![0u8; 32];
-    }
-
-    if txs.len() == 1 {
-        return double_sha256(&txs[0]);
-    }
-
-    let mut new_tx_list = Vec::new();
-    let mut i = 0;
-
-    while i < txs.len() {
-        let tx_hash1 = double_sha256(&txs[i]);
-        let tx_hash2 = if i + 1 < txs.len() {
-            double_sha256(&txs[i + 1])
-        } else {
-            tx_hash1.clone()
-        };
-
-        let mut combined = tx_hash1.clone();
-        combined.extend(tx_hash2);
-        new_tx_list.push(combined);
-
-        i += 2;
-    }
+/// RIPEMD160(SHA256(data)), used for key fingerprints and addresses
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(data)).into()
+}
 
-    calculate_merkle_root(new_tx_list)
+/// the root of the `MerkleTree` over `txs`
+pub fn calculate_merkle_root(txs: Vec<Vec<u8>>) -> Vec<u8> {
+    MerkleTree::new(&txs).root()
 }
\ No newline at end of file
@@ -0,0 +1,154 @@
+use std::{fmt, io};
+
+use serde::{Serialize, Serializer};
+
+/// Error type shared by the wallet's crypto and file handling. Serializes to
+/// `{ "kind": ..., "message": ... }` so the frontend can branch on `kind`.
+#[derive(Debug)]
+pub enum WalletError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    Hex(hex::FromHexError),
+    KeyLength { expected: usize, actual: usize },
+    Crypto(String),
+    WrongPasswordOrCorrupt,
+    UnsupportedVersion(u32),
+    KeystoreExists(String),
+    Locked,
+    InvalidMnemonic(String),
+    Derivation(String),
+    InvalidAddress(String),
+    Codec(String),
+    Network(String),
+    InsufficientFunds { needed: u64, available: u64 },
+    InvalidAmount(String),
+    Rejected(String),
+    Config(String),
+    NoQuorum { agreeing: usize, responded: usize, unreachable: usize },
+    InvalidTransaction(String),
+    InvalidBlock(String),
+}
+
+impl WalletError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WalletError::Io(_) => "io",
+            WalletError::Parse(_) => "parse",
+            WalletError::Hex(_) => "hex",
+            WalletError::KeyLength { .. } => "key_length",
+            WalletError::Crypto(_) => "crypto",
+            WalletError::WrongPasswordOrCorrupt => "wrong_password",
+            WalletError::UnsupportedVersion(_) => "unsupported_version",
+            WalletError::KeystoreExists(_) => "keystore_exists",
+            WalletError::Locked => "locked",
+            WalletError::InvalidMnemonic(_) => "invalid_mnemonic",
+            WalletError::Derivation(_) => "derivation",
+            WalletError::InvalidAddress(_) => "invalid_address",
+            WalletError::Codec(_) => "codec",
+            WalletError::Network(_) => "network",
+            WalletError::InsufficientFunds { .. } => "insufficient_funds",
+            WalletError::InvalidAmount(_) => "invalid_amount",
+            WalletError::Rejected(_) => "rejected",
+            WalletError::Config(_) => "config",
+            WalletError::NoQuorum { .. } => "no_quorum",
+            WalletError::InvalidTransaction(_) => "invalid_transaction",
+            WalletError::InvalidBlock(_) => "invalid_block",
+        }
+    }
+
+    /// a peer answered with something that does not decode or breaks the consensus rules, as
+    /// opposed to not answering
+    pub fn is_malformed_response(&self) -> bool {
+        matches!(self, WalletError::Parse(_) | WalletError::Codec(_) | WalletError::InvalidBlock(_))
+    }
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletError::Io(e) => write!(f, "I/O error: {}", e),
+            WalletError::Parse(e) => write!(f, "malformed JSON: {}", e),
+            WalletError::Hex(e) => write!(f, "invalid hex: {}", e),
+            WalletError::KeyLength { expected, actual } => write!(f, "expected {} bytes, got {}", expected, actual),
+            WalletError::Crypto(e) => write!(f, "crypto error: {}", e),
+            WalletError::WrongPasswordOrCorrupt => write!(f, "wrong password or corrupted keystore"),
+            WalletError::UnsupportedVersion(v) => write!(f, "unsupported keystore version {}", v),
+            WalletError::KeystoreExists(path) => write!(f, "a keystore already exists at {}", path),
+            WalletError::Locked => write!(f, "wallet is locked"),
+            WalletError::InvalidMnemonic(e) => write!(f, "invalid recovery phrase: {}", e),
+            WalletError::Derivation(e) => write!(f, "key derivation failed: {}", e),
+            WalletError::InvalidAddress(e) => write!(f, "invalid address: {}", e),
+            WalletError::Codec(e) => write!(f, "malformed encoding: {}", e),
+            WalletError::Network(e) => write!(f, "network error: {}", e),
+            WalletError::InsufficientFunds { needed, available } => write!(f, "insufficient funds: need {}, have {}", needed, available),
+            WalletError::InvalidAmount(e) => write!(f, "invalid amount: {}", e),
+            WalletError::Rejected(e) => write!(f, "rejected: {}", e),
+            WalletError::Config(e) => write!(f, "invalid network config: {}", e),
+            WalletError::NoQuorum { agreeing, responded, unreachable } => write!(
+                f,
+                "nodes did not agree: {} of {} responding nodes matched, {} unreachable",
+                agreeing, responded, unreachable
+            ),
+            WalletError::InvalidTransaction(e) => write!(f, "invalid transaction: {}", e),
+            WalletError::InvalidBlock(e) => write!(f, "invalid block: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WalletError {}
+
+impl Serialize for WalletError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Repr<'a> {
+            kind: &'a str,
+            message: String,
+        }
+
+        Repr {
+            kind: self.kind(),
+            message: self.to_string(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<io::Error> for WalletError {
+    fn from(e: io::Error) -> Self {
+        WalletError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for WalletError {
+    fn from(e: serde_json::Error) -> Self {
+        WalletError::Parse(e)
+    }
+}
+
+impl From<hex::FromHexError> for WalletError {
+    fn from(e: hex::FromHexError) -> Self {
+        WalletError::Hex(e)
+    }
+}
+
+impl From<k256::ecdsa::Error> for WalletError {
+    fn from(e: k256::ecdsa::Error) -> Self {
+        WalletError::Crypto(e.to_string())
+    }
+}
+
+impl From<bip39::Error> for WalletError {
+    fn from(e: bip39::Error) -> Self {
+        WalletError::InvalidMnemonic(e.to_string())
+    }
+}
+
+impl From<reqwest::Error> for WalletError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            WalletError::Codec(e.to_string())
+        } else {
+            WalletError::Network(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+
+use crate::codec::Codec;
+use crate::crypto_utils::Transaction;
+use crate::error::WalletError;
+use crate::transaction::{TransactionBuilder, Utxo};
+
+// Fee rates are in base units per 1000 bytes of the full transaction encoding
+
+/// lowest rate ever suggested, and the rate used when nodes have no data
+pub const MIN_FEE_RATE: u64 = 1000;
+/// recent blocks whose transactions are sampled
+pub const RECENT_BLOCKS: usize = 6;
+/// fees more than this many times the fast estimate get a warning
+pub const HIGH_FEE_FACTOR: u64 = 3;
+
+// size of a DER signature at its longest and of a compressed public key,
+// standing in for the signatures of a transaction that is not signed yet
+const SIGNATURE_PLACEHOLDER_LEN: usize = 72;
+const PUBLIC_KEY_LEN: usize = 33;
+// fee and size depend on each other through input selection; this many rounds settle it
+const SIZE_ROUNDS: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeePriority {
+    Slow,
+    Normal,
+    Fast,
+}
+
+/// Suggested fee rates, slowest first.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeRates {
+    pub slow: u64,
+    pub normal: u64,
+    pub fast: u64,
+}
+
+impl Default for FeeRates {
+    fn default() -> Self {
+        Self {
+            slow: MIN_FEE_RATE,
+            normal: MIN_FEE_RATE,
+            fast: MIN_FEE_RATE,
+        }
+    }
+}
+
+impl FeeRates {
+    /// Slow and normal are the 25th and 50th percentile of what recent blocks paid; fast is the
+    /// 90th percentile of those and the mempool together, so a backed-up mempool raises it.
+    pub fn from_samples(block_rates: &[u64], mempool_rates: &[u64]) -> Self {
+        let mut confirmed = if block_rates.is_empty() { mempool_rates.to_vec() } else { block_rates.to_vec() };
+        confirmed.sort_unstable();
+        let mut all = [block_rates, mempool_rates].concat();
+        all.sort_unstable();
+
+        let slow = percentile(&confirmed, 25).max(MIN_FEE_RATE);
+        let normal = percentile(&confirmed, 50).max(slow);
+        let fast = percentile(&all, 90).max(normal);
+        Self { slow, normal, fast }
+    }
+
+    /// each rate is the median of what the nodes reported, so a minority cannot skew it
+    pub fn median(samples: &[FeeRates]) -> Self {
+        let median_of = |rate: fn(&FeeRates) -> u64| {
+            let mut rates: Vec<u64> = samples.iter().map(rate).collect();
+            rates.sort_unstable();
+            percentile(&rates, 50)
+        };
+        let slow = median_of(|rates| rates.slow).max(MIN_FEE_RATE);
+        let normal = median_of(|rates| rates.normal).max(slow);
+        let fast = median_of(|rates| rates.fast).max(normal);
+        Self { slow, normal, fast }
+    }
+
+    pub fn rate(&self, priority: FeePriority) -> u64 {
+        match priority {
+            FeePriority::Slow => self.slow,
+            FeePriority::Normal => self.normal,
+            FeePriority::Fast => self.fast,
+        }
+    }
+}
+
+// nearest rank at or below `percent` of an ascending list; 0 if it is empty
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    match sorted.len() {
+        0 => 0,
+        len => sorted[(len - 1) * percent / 100],
+    }
+}
+
+/// What a payment would cost at each priority, and the range outside which a fee looks wrong.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeEstimate {
+    /// bytes of the signed transaction
+    pub size: usize,
+    pub rates: FeeRates,
+    pub slow: u64,
+    pub normal: u64,
+    pub fast: u64,
+    /// fees below this may never confirm
+    pub warn_below: u64,
+    /// fees above this overpay
+    pub warn_above: u64,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeWarning {
+    BelowEstimate,
+    AboveEstimate,
+}
+
+impl FeeEstimate {
+    pub fn new(size: usize, rates: FeeRates) -> Self {
+        let slow = fee_for_size(size, rates.slow);
+        let fast = fee_for_size(size, rates.fast);
+        Self {
+            size,
+            rates,
+            slow,
+            normal: fee_for_size(size, rates.normal),
+            fast,
+            warn_below: slow,
+            warn_above: fast.saturating_mul(HIGH_FEE_FACTOR),
+        }
+    }
+
+    pub fn fee(&self, priority: FeePriority) -> u64 {
+        match priority {
+            FeePriority::Slow => self.slow,
+            FeePriority::Normal => self.normal,
+            FeePriority::Fast => self.fast,
+        }
+    }
+
+    pub fn warning(&self, fee: u64) -> Option<FeeWarning> {
+        if fee < self.warn_below {
+            Some(FeeWarning::BelowEstimate)
+        } else if fee > self.warn_above {
+            Some(FeeWarning::AboveEstimate)
+        } else {
+            None
+        }
+    }
+}
+
+/// `rate` applied to `size` bytes, rounded up
+pub fn fee_for_size(size: usize, rate: u64) -> u64 {
+    (size as u64).saturating_mul(rate).saturating_add(999) / 1000
+}
+
+/// What `tx` leaves to the miner. `None` for transactions without inputs, which pay no fee,
+/// and for ones whose outputs exceed their inputs.
+pub fn fee(tx: &Transaction) -> Option<u64> {
+    if tx.inputs.is_empty() {
+        return None;
+    }
+    let spent = tx.inputs.iter().try_fold(0u64, |total, input| total.checked_add(input.amount))?;
+    let paid = tx.outputs.iter().try_fold(0u64, |total, output| total.checked_add(output.amount))?;
+    spent.checked_sub(paid)
+}
+
+/// what `tx` paid per 1000 bytes; `None` where `fee` is
+pub fn fee_rate(tx: &Transaction) -> Option<u64> {
+    let fee = fee(tx)?;
+    let size = tx.encode().ok()?.len() as u64;
+    Some(fee.saturating_mul(1000) / size.max(1))
+}
+
+/// encoded size of `tx` once signed; unsigned inputs count as carrying the longest signature
+pub fn signed_size(tx: &Transaction) -> Result<usize, WalletError> {
+    let mut signed = tx.clone();
+    for input in &mut signed.inputs {
+        input.signature.get_or_insert_with(|| "00".repeat(SIGNATURE_PLACEHOLDER_LEN));
+        input.public_key.get_or_insert_with(|| "00".repeat(PUBLIC_KEY_LEN));
+    }
+    Ok(signed.encode()?.len())
+}
+
+/// Builds the payment the way `builder` would with the fee it ends up paying at `rates.normal`,
+/// and prices its signed size at every priority.
+pub fn estimate(
+    builder: &TransactionBuilder,
+    confirmed: &[Utxo],
+    unconfirmed: &[Utxo],
+    amount: u64,
+    address: &str,
+    own_address: &str,
+    rates: FeeRates,
+) -> Result<FeeEstimate, WalletError> {
+    let mut fee = 0;
+    let mut size = 0;
+    for _ in 0..SIZE_ROUNDS {
+        let tx = match builder.build(confirmed, unconfirmed, amount, address, fee, own_address) {
+            Ok(tx) => tx,
+            // the funds cover the amount but not this fee; price the last transaction that fit
+            Err(WalletError::InsufficientFunds { .. }) if fee > 0 => break,
+            Err(e) => return Err(e),
+        };
+        size = signed_size(&tx)?;
+
+        let next = fee_for_size(size, rates.normal);
+        if next <= fee {
+            break;
+        }
+        fee = next;
+    }
+    Ok(FeeEstimate::new(size, rates))
+}
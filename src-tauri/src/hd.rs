@@ -0,0 +1,389 @@
+use std::{fmt, str::FromStr};
+
+use hex::encode;
+use hmac::{Hmac, Mac};
+use k256::{
+    elliptic_curve::{sec1::ToEncodedPoint, PrimeField},
+    FieldBytes, NonZeroScalar, ProjectivePoint, PublicKey, Scalar, SecretKey,
+};
+use sha2::Sha512;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::crypto_utils::hash160;
+use crate::error::WalletError;
+use crate::secret::SecretBytes;
+
+/// child numbers at or above this use hardened derivation
+pub const HARDENED: u32 = 1 << 31;
+
+/// CJ-Coin's coin type in `m/44'/coin_type'/account'/change/index`
+pub const CJ_COIN_TYPE: u32 = 8133;
+const PURPOSE: u32 = 44;
+
+pub const EXTERNAL_CHAIN: u32 = 0;
+pub const INTERNAL_CHAIN: u32 = 1;
+
+/// consecutive unused keys after which discovery stops
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+const MASTER_KEY_SALT: &[u8] = b"Bitcoin seed";
+const XPRV_VERSION: [u8; 4] = [0x04, 0x88, 0xAD, 0xE4];
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xB2, 0x1E];
+const SERIALIZED_LEN: usize = 78;
+
+/// A list of child numbers from the master key, written `m/44'/8133'/0'/0/5`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// the key at `index` on the external (receive) or internal (change) chain of `account`
+    pub fn cj_coin(account: u32, chain: u32, index: u32) -> Self {
+        let mut path = Self::cj_coin_account(account);
+        path.0.extend([chain, index]);
+        path
+    }
+
+    /// `m/44'/8133'/account'`, the deepest level that needs the private key
+    pub fn cj_coin_account(account: u32) -> Self {
+        Self(vec![PURPOSE | HARDENED, CJ_COIN_TYPE | HARDENED, account | HARDENED])
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = WalletError;
+
+    /// accepts `'` or `h` as the hardened marker
+    fn from_str(path: &str) -> Result<Self, WalletError> {
+        let invalid = || WalletError::Derivation(format!("invalid path {:?}", path));
+
+        let mut parts = path.split('/');
+        if parts.next() != Some("m") {
+            return Err(invalid());
+        }
+
+        parts
+            .map(|part| {
+                let (number, hardened) = match part.strip_suffix('\'').or_else(|| part.strip_suffix('h')) {
+                    Some(number) => (number, HARDENED),
+                    None => (part, 0),
+                };
+                match number.parse::<u32>() {
+                    Ok(index) if index < HARDENED => Ok(index | hardened),
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            if index & HARDENED != 0 {
+                write!(f, "/{}'", index & !HARDENED)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// BIP32 extended private key. Like `SecretBytes` it has no `Debug` and is wiped on drop.
+#[derive(Clone)]
+pub struct ExtendedPrivateKey {
+    secret_key: SecretKey,
+    chain_code: Zeroizing<[u8; 32]>,
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+}
+
+/// BIP32 extended public key; can derive non-hardened children without any secret.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtendedPublicKey {
+    public_key: PublicKey,
+    chain_code: [u8; 32],
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+}
+
+impl ExtendedPrivateKey {
+    /// the master key for a BIP39 (or any 16 to 64 byte) seed
+    pub fn from_seed(seed: &SecretBytes) -> Result<Self, WalletError> {
+        let output = hmac_sha512(MASTER_KEY_SALT, &[seed.expose()]);
+        let secret_key = SecretKey::from_slice(&output[..32]).map_err(|_| WalletError::Derivation("seed gives an invalid master key".to_string()))?;
+
+        let mut chain_code = Zeroizing::new([0u8; 32]);
+        chain_code.copy_from_slice(&output[32..]);
+
+        Ok(Self {
+            secret_key,
+            chain_code,
+            depth: 0,
+            parent_fingerprint: [0; 4],
+            child_number: 0,
+        })
+    }
+
+    pub fn derive_child(&self, index: u32) -> Result<Self, WalletError> {
+        let depth = child_depth(self.depth)?;
+        let public_key = compressed(&self.secret_key.public_key());
+
+        let output = if index & HARDENED != 0 {
+            let mut private_bytes: [u8; 32] = self.secret_key.to_bytes().into();
+            let output = hmac_sha512(self.chain_code.as_slice(), &[&[0], &private_bytes, &index.to_be_bytes()]);
+            private_bytes.zeroize();
+            output
+        } else {
+            hmac_sha512(self.chain_code.as_slice(), &[&public_key, &index.to_be_bytes()])
+        };
+
+        let tweak = parse_tweak(&output[..32], index)?;
+        let child = Option::<NonZeroScalar>::from(NonZeroScalar::new(tweak + self.secret_key.to_nonzero_scalar().as_ref()))
+            .ok_or_else(|| invalid_child(index))?;
+
+        let mut chain_code = Zeroizing::new([0u8; 32]);
+        chain_code.copy_from_slice(&output[32..]);
+
+        Ok(Self {
+            secret_key: SecretKey::from(child),
+            chain_code,
+            depth,
+            parent_fingerprint: fingerprint(&public_key),
+            child_number: index,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, WalletError> {
+        path.indices().iter().try_fold(self.clone(), |key, &index| key.derive_child(index))
+    }
+
+    pub fn public(&self) -> ExtendedPublicKey {
+        ExtendedPublicKey {
+            public_key: self.secret_key.public_key(),
+            chain_code: *self.chain_code,
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+        }
+    }
+
+    /// the raw 32-byte key, as used by `crypto_utils::sign`
+    pub fn private_key(&self) -> SecretBytes {
+        let mut private_bytes: [u8; 32] = self.secret_key.to_bytes().into();
+        let private_key = SecretBytes::new(private_bytes.to_vec());
+        private_bytes.zeroize();
+        private_key
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        encode(compressed(&self.secret_key.public_key()))
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn child_number(&self) -> u32 {
+        self.child_number
+    }
+
+    /// `xprv...` Base58Check encoding
+    pub fn to_base58(&self) -> Zeroizing<String> {
+        let mut key = Zeroizing::new([0u8; 33]);
+        key[1..].copy_from_slice(&self.secret_key.to_bytes());
+
+        let payload = serialize(XPRV_VERSION, self.depth, self.parent_fingerprint, self.child_number, &self.chain_code, &key);
+        Zeroizing::new(bs58::encode(payload.as_slice()).with_check().into_string())
+    }
+
+    pub fn from_base58(encoded: &str) -> Result<Self, WalletError> {
+        let payload = deserialize(encoded, XPRV_VERSION)?;
+        if payload[45] != 0 {
+            return Err(WalletError::Derivation("extended private key must start with a zero byte".to_string()));
+        }
+
+        let secret_key = SecretKey::from_slice(&payload[46..]).map_err(|_| WalletError::Derivation("invalid private key".to_string()))?;
+        let mut chain_code = Zeroizing::new([0u8; 32]);
+        chain_code.copy_from_slice(&payload[13..45]);
+
+        Ok(Self {
+            secret_key,
+            chain_code,
+            depth: payload[4],
+            parent_fingerprint: payload[5..9].try_into().expect("4 bytes"),
+            child_number: u32::from_be_bytes(payload[9..13].try_into().expect("4 bytes")),
+        })
+    }
+}
+
+impl ExtendedPublicKey {
+    /// fails for hardened indices, which need the private key
+    pub fn derive_child(&self, index: u32) -> Result<Self, WalletError> {
+        if index & HARDENED != 0 {
+            return Err(WalletError::Derivation("hardened keys cannot be derived from a public key".to_string()));
+        }
+        let depth = child_depth(self.depth)?;
+        let public_key = compressed(&self.public_key);
+
+        let output = hmac_sha512(&self.chain_code, &[&public_key, &index.to_be_bytes()]);
+        let tweak = parse_tweak(&output[..32], index)?;
+
+        let point = ProjectivePoint::GENERATOR * tweak + self.public_key.to_projective();
+        let child = PublicKey::from_affine(point.to_affine()).map_err(|_| invalid_child(index))?;
+
+        Ok(Self {
+            public_key: child,
+            chain_code: output[32..].try_into().expect("32 bytes"),
+            depth,
+            parent_fingerprint: fingerprint(&public_key),
+            child_number: index,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, WalletError> {
+        path.indices().iter().try_fold(self.clone(), |key, &index| key.derive_child(index))
+    }
+
+    /// compressed SEC1 hex, the same format `generate_key_pair` returns
+    pub fn public_key_hex(&self) -> String {
+        encode(compressed(&self.public_key))
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn child_number(&self) -> u32 {
+        self.child_number
+    }
+
+    /// `xpub...` Base58Check encoding
+    pub fn to_base58(&self) -> String {
+        let payload = serialize(XPUB_VERSION, self.depth, self.parent_fingerprint, self.child_number, &self.chain_code, &compressed(&self.public_key));
+        bs58::encode(payload.as_slice()).with_check().into_string()
+    }
+
+    pub fn from_base58(encoded: &str) -> Result<Self, WalletError> {
+        let payload = deserialize(encoded, XPUB_VERSION)?;
+        let public_key = PublicKey::from_sec1_bytes(&payload[45..]).map_err(|_| WalletError::Derivation("invalid public key".to_string()))?;
+
+        Ok(Self {
+            public_key,
+            chain_code: payload[13..45].try_into().expect("32 bytes"),
+            depth: payload[4],
+            parent_fingerprint: payload[5..9].try_into().expect("4 bytes"),
+            child_number: u32::from_be_bytes(payload[9..13].try_into().expect("4 bytes")),
+        })
+    }
+}
+
+fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> Zeroizing<[u8; 64]> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    Zeroizing::new(mac.finalize().into_bytes().into())
+}
+
+fn compressed(public_key: &PublicKey) -> [u8; 33] {
+    public_key.to_encoded_point(true).as_bytes().try_into().expect("compressed points are 33 bytes")
+}
+
+// first four bytes of HASH160 of the parent's public key
+fn fingerprint(public_key: &[u8; 33]) -> [u8; 4] {
+    hash160(public_key)[..4].try_into().expect("4 bytes")
+}
+
+fn child_depth(depth: u8) -> Result<u8, WalletError> {
+    depth.checked_add(1).ok_or_else(|| WalletError::Derivation("maximum depth reached".to_string()))
+}
+
+// BIP32: a tweak >= n (probability below 2^-127) makes the index invalid; callers move on to the next one
+fn parse_tweak(bytes: &[u8], index: u32) -> Result<Scalar, WalletError> {
+    let bytes: [u8; 32] = bytes.try_into().expect("32 bytes");
+    Option::from(Scalar::from_repr(FieldBytes::from(bytes))).ok_or_else(|| invalid_child(index))
+}
+
+fn invalid_child(index: u32) -> WalletError {
+    WalletError::Derivation(format!("child {} is invalid, use the next index", index))
+}
+
+fn serialize(version: [u8; 4], depth: u8, parent_fingerprint: [u8; 4], child_number: u32, chain_code: &[u8; 32], key: &[u8; 33]) -> Zeroizing<Vec<u8>> {
+    let mut payload = Zeroizing::new(Vec::with_capacity(SERIALIZED_LEN));
+    payload.extend_from_slice(&version);
+    payload.push(depth);
+    payload.extend_from_slice(&parent_fingerprint);
+    payload.extend_from_slice(&child_number.to_be_bytes());
+    payload.extend_from_slice(chain_code);
+    payload.extend_from_slice(key);
+    payload
+}
+
+fn deserialize(encoded: &str, version: [u8; 4]) -> Result<Zeroizing<Vec<u8>>, WalletError> {
+    let payload = Zeroizing::new(
+        bs58::decode(encoded)
+            .with_check(None)
+            .into_vec()
+            .map_err(|e| WalletError::Derivation(e.to_string()))?,
+    );
+
+    if payload.len() != SERIALIZED_LEN {
+        return Err(WalletError::KeyLength {
+            expected: SERIALIZED_LEN,
+            actual: payload.len(),
+        });
+    }
+    if payload[..4] != version {
+        return Err(WalletError::Derivation("unexpected extended key version".to_string()));
+    }
+    Ok(payload)
+}
+
+/// a key with on-chain history found by `discover_keys`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredKey {
+    pub index: u32,
+    pub public_key: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Discovery {
+    pub used: Vec<DiscoveredKey>,
+    /// first index after the last used key, i.e. the next fresh receive key
+    pub next_index: u32,
+}
+
+/// Scans the children of `chain` (an account's external or internal chain) in order
+/// and stops once `gap_limit` consecutive keys have no history according to `is_used`.
+pub fn discover_keys<F>(chain: &ExtendedPublicKey, gap_limit: u32, mut is_used: F) -> Result<Discovery, WalletError>
+where
+    F: FnMut(&str) -> Result<bool, WalletError>,
+{
+    let mut discovery = Discovery::default();
+    let mut gap = 0;
+    let mut index = 0;
+
+    while gap < gap_limit && index < HARDENED {
+        let public_key = chain.derive_child(index)?.public_key_hex();
+
+        if is_used(&public_key)? {
+            discovery.used.push(DiscoveredKey { index, public_key });
+            discovery.next_index = index + 1;
+            gap = 0;
+        } else {
+            gap += 1;
+        }
+        index += 1;
+    }
+
+    Ok(discovery)
+}
@@ -0,0 +1,386 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+use hex::{decode, encode};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::crypto_utils::{
+    aead_decrypt, aead_encrypt, aes_decrypt, calibrate_argon2id, derive_key, derive_key_argon2id, derive_key_material,
+    generate_key_pair, generate_salt, public_key_from_private, AesKeyData, Argon2Params, KeyPairData,
+    ARGON2_DEFAULT_MEMORY_KIB,
+};
+use crate::error::WalletError;
+use crate::mnemonic::{mnemonic_to_seed, private_key_from_seed};
+use crate::secret::{Password, SecretBytes, SecretPhrase};
+
+pub const KEYSTORE_VERSION: u32 = 3;
+
+// version 1 used PBKDF2 and AES-256-CBC with a separate HMAC-SHA256, version 2 PBKDF2 and
+// AES-256-GCM; version 3 added Argon2id
+const MIN_KEYSTORE_VERSION: u32 = 1;
+
+pub const DEFAULT_UNLOCK_TIME: Duration = Duration::from_millis(750);
+
+type HmacSha256 = Hmac<Sha256>;
+type DerivedKey = Zeroizing<[u8; 32]>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "algorithm")]
+pub enum KdfParams {
+    #[serde(rename = "argon2id")]
+    Argon2id {
+        salt: String,
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+    #[serde(rename = "pbkdf2-sha256")]
+    Pbkdf2Sha256 { salt: String, iterations: u32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "algorithm")]
+pub enum CipherParams {
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm { nonce: String },
+    #[serde(rename = "aes-256-cbc")]
+    Aes256Cbc { iv: String },
+}
+
+impl CipherParams {
+    fn algorithm(&self) -> &'static str {
+        match self {
+            CipherParams::Aes256Gcm { .. } => "aes-256-gcm",
+            CipherParams::Aes256Cbc { .. } => "aes-256-cbc",
+        }
+    }
+}
+
+/// Self-describing wallet file: everything needed to unlock the private key
+/// except the password. The derived key itself is never written to disk.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Keystore {
+    pub version: u32,
+    pub public_key: String,
+    pub kdf: KdfParams,
+    pub cipher: CipherParams,
+    pub ciphertext: String,
+    pub mac: String,
+}
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: u32,
+}
+
+impl KdfParams {
+    /// Argon2id with a fresh random salt
+    pub fn argon2id(params: Argon2Params) -> Self {
+        KdfParams::Argon2id {
+            salt: encode(generate_salt()),
+            memory_kib: params.memory_kib,
+            iterations: params.iterations,
+            parallelism: params.parallelism,
+        }
+    }
+
+    /// PBKDF2-HMAC-SHA256 with a fresh random salt, only kept for compatibility
+    pub fn pbkdf2_sha256(iterations: u32) -> Self {
+        KdfParams::Pbkdf2Sha256 {
+            salt: encode(generate_salt()),
+            iterations,
+        }
+    }
+
+    fn derive(&self, password: &Password) -> Result<DerivedKey, WalletError> {
+        match self {
+            KdfParams::Argon2id {
+                salt,
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params = Argon2Params {
+                    memory_kib: *memory_kib,
+                    iterations: *iterations,
+                    parallelism: *parallelism,
+                };
+                derive_key_argon2id(password.as_bytes(), &decode(salt)?, &params)
+            }
+            KdfParams::Pbkdf2Sha256 { salt, iterations } => Ok(derive_key(password.as_bytes(), &decode(salt)?, *iterations)),
+        }
+    }
+
+    fn algorithm(&self) -> &'static str {
+        match self {
+            KdfParams::Argon2id { .. } => "argon2id",
+            KdfParams::Pbkdf2Sha256 { .. } => "pbkdf2-sha256",
+        }
+    }
+
+    // version 1 split 64 bytes of key material into an encryption key and a MAC key
+    fn derive_legacy(&self, password: &Password) -> Result<(DerivedKey, DerivedKey), WalletError> {
+        match self {
+            KdfParams::Pbkdf2Sha256 { salt, iterations } => {
+                let material = derive_key_material(password.as_bytes(), &decode(salt)?, *iterations);
+
+                let mut enc_key = Zeroizing::new([0u8; 32]);
+                let mut mac_key = Zeroizing::new([0u8; 32]);
+                enc_key.copy_from_slice(&material[..32]);
+                mac_key.copy_from_slice(&material[32..]);
+
+                Ok((enc_key, mac_key))
+            }
+            KdfParams::Argon2id { .. } => Err(WalletError::Crypto("version 1 keystores only use PBKDF2".to_string())),
+        }
+    }
+}
+
+impl Keystore {
+    /// encrypts `private_key` under `password` into a new keystore document.
+    /// The public key is bound in as associated data, so it cannot be swapped out.
+    pub fn encrypt(private_key: &SecretBytes, public_key_hex: &str, password: &Password, kdf: KdfParams) -> Result<Self, WalletError> {
+        let key = kdf.derive(password)?;
+
+        let (cipher_text, tag, nonce) = aead_encrypt(&key, private_key.expose(), public_key_hex.as_bytes());
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            public_key: public_key_hex.to_string(),
+            kdf,
+            cipher: CipherParams::Aes256Gcm { nonce: encode(nonce) },
+            ciphertext: encode(cipher_text),
+            mac: encode(tag),
+        })
+    }
+
+    /// returns the private key, or `WrongPasswordOrCorrupt` if it cannot be authenticated
+    pub fn decrypt(&self, password: &Password) -> Result<SecretBytes, WalletError> {
+        let cipher_text = decode(&self.ciphertext)?;
+        let mac = decode(&self.mac)?;
+
+        let private_key = match &self.cipher {
+            CipherParams::Aes256Gcm { nonce } => {
+                let key = self.kdf.derive(password)?;
+                aead_decrypt(&key, &decode(nonce)?, &cipher_text, &mac, self.public_key.as_bytes())?
+            }
+            CipherParams::Aes256Cbc { iv } => {
+                let (enc_key, mac_key) = self.kdf.derive_legacy(password)?;
+                let iv = decode(iv)?;
+
+                compute_mac(mac_key.as_slice(), &iv, &cipher_text)
+                    .verify_slice(&mac)
+                    .map_err(|_| WalletError::WrongPasswordOrCorrupt)?;
+
+                // version 1 encrypted the hex encoding of the key
+                secret_from_hex_plaintext(aes_decrypt(&enc_key, &iv, &cipher_text)?)?
+            }
+        };
+
+        check_key_pair(&private_key, &self.public_key)?;
+        Ok(private_key)
+    }
+
+    /// parses a keystore document, rejecting versions this wallet does not know and KDF or
+    /// cipher choices its version never wrote
+    pub fn from_json(json: &str) -> Result<Self, WalletError> {
+        let probe: VersionProbe = serde_json::from_str(json)?;
+        if !(MIN_KEYSTORE_VERSION..=KEYSTORE_VERSION).contains(&probe.version) {
+            return Err(WalletError::UnsupportedVersion(probe.version));
+        }
+
+        let keystore: Self = serde_json::from_str(json)?;
+        keystore.check_algorithms()?;
+        Ok(keystore)
+    }
+
+    fn check_algorithms(&self) -> Result<(), WalletError> {
+        let allowed = match self.version {
+            1 => matches!((&self.kdf, &self.cipher), (KdfParams::Pbkdf2Sha256 { .. }, CipherParams::Aes256Cbc { .. })),
+            2 => matches!((&self.kdf, &self.cipher), (KdfParams::Pbkdf2Sha256 { .. }, CipherParams::Aes256Gcm { .. })),
+            // PBKDF2 stays available for compatibility
+            _ => matches!(self.cipher, CipherParams::Aes256Gcm { .. }),
+        };
+        if !allowed {
+            return Err(WalletError::Crypto(format!(
+                "version {} keystores do not use {} with {}",
+                self.version,
+                self.kdf.algorithm(),
+                self.cipher.algorithm()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self, WalletError> {
+        let content = fs::read_to_string(path)?;
+        Self::from_json(&content)
+    }
+
+    /// writes the keystore to a new file at `path`; a keystore already there is never replaced
+    pub fn save(&self, path: &str) -> Result<(), WalletError> {
+        let json = serde_json::to_string_pretty(self)?;
+        let mut file = OpenOptions::new().write(true).create_new(true).open(path).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => WalletError::KeystoreExists(path.to_string()),
+            _ => e.into(),
+        })?;
+        // a half-written keystore would hide that the key was never saved
+        if let Err(e) = file.write_all(json.as_bytes()).and_then(|_| file.sync_all()) {
+            let _ = fs::remove_file(path);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Writes the keystore to `path` in place of any keystore there. It is written beside the
+    /// old one first, so a failed write leaves the old one as it was.
+    pub fn replace(&self, path: &str) -> Result<(), WalletError> {
+        let staged = format!("{}.new", path);
+        // left behind by a replace that was interrupted before the rename
+        let _ = fs::remove_file(&staged);
+        self.save(&staged)?;
+        fs::rename(&staged, path)?;
+        Ok(())
+    }
+}
+
+fn compute_mac(mac_key: &[u8], iv: &[u8], cipher_text: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(mac_key).expect("HMAC accepts keys of any length");
+    mac.update(iv);
+    mac.update(cipher_text);
+    mac
+}
+
+fn secret_from_hex_plaintext(plain_text: SecretBytes) -> Result<SecretBytes, WalletError> {
+    let hex_str = std::str::from_utf8(plain_text.expose()).map_err(|_| WalletError::WrongPasswordOrCorrupt)?;
+    SecretBytes::from_hex(hex_str).map_err(|_| WalletError::WrongPasswordOrCorrupt)
+}
+
+fn check_key_pair(private_key: &SecretBytes, public_key_hex: &str) -> Result<(), WalletError> {
+    match public_key_from_private(private_key) {
+        Ok(derived) if derived.eq_ignore_ascii_case(public_key_hex) => Ok(()),
+        _ => Err(WalletError::WrongPasswordOrCorrupt),
+    }
+}
+
+/// Legacy decode path for the pre-keystore `aes_key_data.json` / `key_pair_data.json` pair.
+/// CBC has no MAC, so a wrong password is detected by checking the recovered key
+/// against the stored public key.
+pub fn decrypt_legacy_files(aes_key_data_path: &str, key_pair_data_path: &str, password: &Password) -> Result<SecretBytes, WalletError> {
+    let key_data: AesKeyData = serde_json::from_str(&fs::read_to_string(aes_key_data_path)?)?;
+    let key_pair_data: KeyPairData = serde_json::from_str(&fs::read_to_string(key_pair_data_path)?)?;
+
+    let key = derive_key(password.as_bytes(), &decode(&key_data.salt)?, key_data.iterations);
+    let iv = decode(&key_pair_data.iv)?;
+    let cipher_text = decode(&key_pair_data.encrypted_private_key)?;
+
+    let private_key = secret_from_hex_plaintext(aes_decrypt(&key, &iv, &cipher_text)?)?;
+    check_key_pair(&private_key, &key_pair_data.public_key)?;
+
+    Ok(private_key)
+}
+
+/// decrypts the legacy file pair with `password` and writes the key to `path` as a current
+/// keystore under the same password, returning the public key. The legacy files are left as they
+/// are, and so is any keystore already at `path`.
+pub fn migrate_legacy_files(aes_key_data_path: &str, key_pair_data_path: &str, path: &str, password: &Password, kdf: KdfParams) -> Result<String, WalletError> {
+    let private_key = decrypt_legacy_files(aes_key_data_path, key_pair_data_path, password)?;
+    let public_key = public_key_from_private(&private_key)?;
+
+    let keystore = Keystore::encrypt(&private_key, &public_key, password, kdf)?;
+    keystore.save(path)?;
+
+    Ok(public_key)
+}
+
+/// generates a new key pair and writes it to `path` as an encrypted keystore,
+/// returning the public key; fails if a keystore is already there
+pub fn create_keystore_file(path: &str, password: &Password, kdf: KdfParams) -> Result<String, WalletError> {
+    let (private_key, public_key) = generate_key_pair();
+
+    let keystore = Keystore::encrypt(&private_key, &public_key, password, kdf)?;
+    keystore.save(path)?;
+
+    Ok(public_key)
+}
+
+/// derives the wallet key from a recovery phrase and writes it to `path` as an
+/// encrypted keystore, returning the public key. The same phrase and passphrase
+/// always give the same key. Fails if a keystore is already there.
+pub fn create_keystore_from_mnemonic(
+    path: &str,
+    phrase: &str,
+    passphrase: &Password,
+    password: &Password,
+    kdf: KdfParams,
+) -> Result<String, WalletError> {
+    let (keystore, public_key) = keystore_from_mnemonic(phrase, passphrase, password, kdf)?;
+    keystore.save(path)?;
+    Ok(public_key)
+}
+
+/// `create_keystore_from_mnemonic`, replacing any keystore at `path`. The key it held is lost
+/// unless its own recovery phrase was kept.
+pub fn replace_keystore_from_mnemonic(
+    path: &str,
+    phrase: &str,
+    passphrase: &Password,
+    password: &Password,
+    kdf: KdfParams,
+) -> Result<String, WalletError> {
+    let (keystore, public_key) = keystore_from_mnemonic(phrase, passphrase, password, kdf)?;
+    keystore.replace(path)?;
+    Ok(public_key)
+}
+
+fn keystore_from_mnemonic(phrase: &str, passphrase: &Password, password: &Password, kdf: KdfParams) -> Result<(Keystore, String), WalletError> {
+    let seed = mnemonic_to_seed(phrase, passphrase)?;
+    let private_key = private_key_from_seed(&seed)?;
+    let public_key = public_key_from_private(&private_key)?;
+
+    let keystore = Keystore::encrypt(&private_key, &public_key, password, kdf)?;
+    Ok((keystore, public_key))
+}
+
+fn calibrated_kdf() -> KdfParams {
+    KdfParams::argon2id(calibrate_argon2id(DEFAULT_UNLOCK_TIME, ARGON2_DEFAULT_MEMORY_KIB))
+}
+
+/// whether a keystore is already at `path`, in which case it is unlocked rather than set up again
+#[tauri::command]
+pub fn keystore_exists(path: &str) -> bool {
+    Path::new(path).is_file()
+}
+
+/// `create_keystore_file` with KDF cost calibrated to this machine
+#[tauri::command]
+pub fn generate_keystore_to_file(path: &str, password: Password) -> Result<String, WalletError> {
+    create_keystore_file(path, &password, calibrated_kdf())
+}
+
+/// recreates the keystore at `path` from a recovery phrase; also used right after
+/// `generate_mnemonic` when setting up a new wallet. Fails if a keystore is already there.
+#[tauri::command]
+pub fn restore_from_mnemonic(path: &str, mnemonic: SecretPhrase, passphrase: Password, password: Password) -> Result<String, WalletError> {
+    create_keystore_from_mnemonic(path, mnemonic.as_str(), &passphrase, &password, calibrated_kdf())
+}
+
+/// `restore_from_mnemonic` over the keystore at `path`, once the user has confirmed its key
+/// may be discarded
+#[tauri::command]
+pub fn replace_wallet_from_mnemonic(path: &str, mnemonic: SecretPhrase, passphrase: Password, password: Password) -> Result<String, WalletError> {
+    replace_keystore_from_mnemonic(path, mnemonic.as_str(), &passphrase, &password, calibrated_kdf())
+}
+
+/// `migrate_legacy_files` with KDF cost calibrated to this machine
+#[tauri::command]
+pub fn migrate_legacy_wallet(aes_key_data_path: &str, key_pair_data_path: &str, path: &str, password: Password) -> Result<String, WalletError> {
+    migrate_legacy_files(aes_key_data_path, key_pair_data_path, path, &password, calibrated_kdf())
+}
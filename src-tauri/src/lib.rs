@@ -1,2 +1,21 @@
+pub mod address;
+pub mod api;
+pub mod chain;
+pub mod codec;
+pub mod config;
+pub mod consensus;
 pub mod crypto_utils;
+pub mod error;
+pub mod fees;
+pub mod hd;
+pub mod keystore;
+pub mod merkle;
+pub mod miner;
+pub mod mnemonic;
+pub mod peers;
+pub mod pow;
+pub mod quorum;
+pub mod secret;
+pub mod session;
+pub mod transaction;
 pub mod utils;
\ No newline at end of file
@@ -38,20 +38,74 @@ fn unit_tests() {
 }
 */
 
-mod crypto_utils;
-use crypto_utils::decrypt_file;
-use crypto_utils::generate_aes_key_to_file;
-use crypto_utils::generate_key_pair_to_file;
+use std::sync::Mutex;
+
+use tauri::Manager;
+
+use app::address;
+use app::api::{self, NodeClient};
+use app::chain::{self, ChainState};
+use app::config::{self, ConfigLoadError, NetworkConfig};
+use app::keystore;
+use app::miner::{self, Miner};
+use app::mnemonic;
+use app::session::{self, WalletSession};
 
 fn main() {
     let context = tauri::generate_context!();
 
     tauri::Builder::default()
         .menu(tauri::Menu::os_default(&context.package_info().name))
+        .manage(Mutex::new(WalletSession::default()))
+        .manage(Miner::default())
+        .setup(|app| {
+            let (network_config, config_error) = match config::config_path(&app.handle()) {
+                Ok(path) => NetworkConfig::load_or_default(&path),
+                Err(e) => (NetworkConfig::default(), Some(e.to_string())),
+            };
+            let data_dir = app.path_resolver().app_data_dir();
+            let mut nodes = NodeClient::new(network_config.selected_profile());
+            if let Some(dir) = data_dir.clone() {
+                nodes = nodes.with_peer_dir(dir);
+            }
+            app.manage(nodes);
+            app.manage(ChainState::new(network_config.selected_profile().network, data_dir));
+            app.manage(Mutex::new(network_config));
+            app.manage(ConfigLoadError(config_error));
+
+            session::spawn_auto_lock(app.handle());
+            chain::spawn_header_sync(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
-			crypto_utils::generate_aes_key_to_file,
-			crypto_utils::generate_key_pair_to_file,
-			crypto_utils::decrypt_file,
+			keystore::keystore_exists,
+			keystore::generate_keystore_to_file,
+			keystore::restore_from_mnemonic,
+			keystore::replace_wallet_from_mnemonic,
+			keystore::migrate_legacy_wallet,
+			mnemonic::generate_mnemonic,
+			session::unlock_wallet,
+			session::lock_wallet,
+			session::is_unlocked,
+			session::set_auto_lock_timeout,
+			session::sign_message,
+			address::get_address,
+			address::validate_address,
+			api::get_balance,
+			api::get_utxos,
+			api::send_transaction,
+			api::estimate_fee,
+			api::get_peers,
+			api::get_peer_store_error,
+			api::unban_peer,
+			miner::start_mining,
+			miner::stop_mining,
+			miner::is_mining,
+			chain::get_chain_tip,
+			chain::sync_headers_now,
+			config::get_network_config,
+			config::select_network,
+			config::set_seed_nodes,
         ])
         .run(context)
         .expect("error while running tauri application");
@@ -0,0 +1,125 @@
+// Merkle trees over a block's txids, built the way `calculate_merkle_root` always has: every
+// txid is double-SHA256 hashed into a leaf, every pair of nodes into their parent, and a level
+// of odd length pairs its last node with itself. An inclusion proof is the sibling of each node
+// on the path from a leaf to the root, enough to check a transaction against a header alone.
+
+use hex::{decode, encode};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto_utils::double_sha256;
+use crate::error::WalletError;
+
+/// The path from one transaction up to the merkle root of its block.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    /// position of the transaction in the block
+    pub index: u64,
+    /// transactions in the block, which fixes the shape of the tree
+    pub leaf_count: u64,
+    /// hex hash of the node beside the path at each level, from the leaves up
+    pub siblings: Vec<String>,
+}
+
+/// Every level of the merkle tree over a block's txids, from the leaves up to the root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleTree {
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+impl MerkleTree {
+    pub fn new<T: AsRef<[u8]>>(txids: &[T]) -> Self {
+        let mut levels = vec![txids.iter().map(|txid| double_sha256(txid.as_ref())).collect::<Vec<_>>()];
+        while levels[levels.len() - 1].len() > 1 {
+            let parents = levels[levels.len() - 1].chunks(2).map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0]))).collect();
+            levels.push(parents);
+        }
+        Self { levels }
+    }
+
+    /// the root, or all zeros for a tree without transactions
+    pub fn root(&self) -> Vec<u8> {
+        self.levels[self.levels.len() - 1].first().cloned().unwrap_or_else(|| vec![0u8; 32])
+    }
+
+    /// the hashes of each level, leaves first
+    pub fn levels(&self) -> &[Vec<Vec<u8>>] {
+        &self.levels
+    }
+
+    /// transactions in the tree
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the proof for the transaction at `index`, if there is one
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut position = index;
+        let siblings = self.levels[..self.levels.len() - 1]
+            .iter()
+            .map(|level| {
+                // the last node of an odd level is its own sibling
+                let sibling = level.get(position ^ 1).unwrap_or(&level[position]);
+                position /= 2;
+                encode(sibling)
+            })
+            .collect();
+        Some(MerkleProof {
+            index: index as u64,
+            leaf_count: self.len() as u64,
+            siblings,
+        })
+    }
+
+    /// the proof for the first transaction with `txid`, if the tree has one
+    pub fn proof_for(&self, txid: &[u8]) -> Option<MerkleProof> {
+        let leaf = double_sha256(txid);
+        self.proof(self.levels[0].iter().position(|hash| *hash == leaf)?)
+    }
+}
+
+fn hash_pair(left: &[u8], right: &[u8]) -> Vec<u8> {
+    double_sha256(&[left, right].concat())
+}
+
+// nodes in the level above one of `width` nodes
+fn parent_width(width: u64) -> u64 {
+    width / 2 + width % 2
+}
+
+/// Whether `proof` leads from the transaction `txid` to the merkle root `root`, both hex. The
+/// index must fall inside the block, the path must be as long as the tree is deep, and a node
+/// may only be its own sibling where it is the last of an odd level, so duplicating the last
+/// transaction cannot prove a position past it.
+pub fn verify_merkle_proof(txid: &str, proof: &MerkleProof, root: &str) -> Result<bool, WalletError> {
+    let mut depth = 0;
+    let mut width = proof.leaf_count;
+    while width > 1 {
+        width = parent_width(width);
+        depth += 1;
+    }
+    if proof.index >= proof.leaf_count || proof.siblings.len() != depth {
+        return Ok(false);
+    }
+
+    let mut hash = double_sha256(&decode(txid)?);
+    let mut position = proof.index;
+    let mut width = proof.leaf_count;
+    for sibling in &proof.siblings {
+        let sibling = decode(sibling)?;
+        let duplicated = position % 2 == 0 && position == width - 1;
+        if duplicated != (sibling == hash) {
+            return Ok(false);
+        }
+        hash = if position % 2 == 0 { hash_pair(&hash, &sibling) } else { hash_pair(&sibling, &hash) };
+        position /= 2;
+        width = parent_width(width);
+    }
+    Ok(hash == decode(root)?)
+}
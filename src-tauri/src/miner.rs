@@ -0,0 +1,407 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use hex::encode;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use crate::api::{own_address, unwrap_quorum, NodeClient};
+use crate::codec::{block_hash, txid, Codec};
+use crate::config::{Network, NetworkConfig};
+use crate::consensus::median_time_past;
+use crate::crypto_utils::{calculate_merkle_root, double_sha256, hex_to_bytes, to_array, Block, BlockHeader, Input, Output, Transaction};
+use crate::error::WalletError;
+use crate::pow::{target_from_nbits, U256};
+use crate::quorum::QuorumResult;
+use crate::session::WalletSession;
+use crate::transaction::{verify_transaction, UtxoSet, VERSION};
+
+/// new coins paid to the miner of each block, on top of its transactions' fees
+pub const BLOCK_REWARD: u64 = 5_000_000_000;
+/// `prev_hash` of the first block
+pub const GENESIS_PREV_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+/// most threads one miner runs
+pub const MAX_MINING_THREADS: usize = 64;
+/// `output_index` of a coinbase's only input, which spends no output
+pub const COINBASE_OUTPUT_INDEX: u32 = u32::MAX;
+
+/// event emitted every second while mining, with a `MiningProgress`
+pub const MINING_PROGRESS_EVENT: &str = "mining-progress";
+/// event emitted for every block the nodes accept
+pub const BLOCK_MINED_EVENT: &str = "block-mined";
+/// event emitted once mining ends, with the error that ended it if any
+pub const MINING_STOPPED_EVENT: &str = "mining-stopped";
+
+const BLOCK_VERSION: u32 = 1;
+// the nonce is the last field of the header encoding
+const NONCE_OFFSET: usize = 80;
+// nonces a thread tries between looking at the stop flag and the clock
+const NONCES_PER_CHECK: u64 = 4096;
+// a template is rebuilt this often to pick up a new tip, new transactions and the time
+const TEMPLATE_LIFETIME: Duration = Duration::from_secs(30);
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shared by the threads mining a block and whoever stops them or watches their progress.
+#[derive(Clone, Debug, Default)]
+pub struct MiningControl {
+    stop: Arc<AtomicBool>,
+    hashes: Arc<AtomicU64>,
+}
+
+impl MiningControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// headers hashed so far, across every template
+    pub fn hashes(&self) -> u64 {
+        self.hashes.load(Ordering::Relaxed)
+    }
+}
+
+/// A transaction paying `amount` new coins to `address`. Its only input spends nothing: the
+/// outpoint holds the block `height` and an `extra_nonce` instead, so no two coinbases share a
+/// txid even when they pay the same address the same amount.
+pub fn coinbase(address: &str, amount: u64, height: u64, extra_nonce: u64) -> Result<Transaction, WalletError> {
+    let mut outpoint = [0u8; 32];
+    outpoint[..8].copy_from_slice(&height.to_le_bytes());
+    outpoint[8..16].copy_from_slice(&extra_nonce.to_le_bytes());
+    let mut tx = Transaction {
+        version: VERSION,
+        inputs: vec![Input::unsigned(encode(outpoint), COINBASE_OUTPUT_INDEX, 0)],
+        outputs: vec![Output {
+            amount,
+            address: address.to_string(),
+        }],
+        txid: None,
+    };
+    tx.txid = Some(txid(&tx)?);
+    Ok(tx)
+}
+
+/// the block height `tx` commits to if it is a coinbase
+pub fn coinbase_height(tx: &Transaction) -> Option<u64> {
+    match tx.inputs.as_slice() {
+        [input] if input.output_index == COINBASE_OUTPUT_INDEX && input.amount == 0 => {
+            let outpoint = hex_to_bytes(&input.prev_txid).ok()?;
+            Some(u64::from_le_bytes(outpoint[..8].try_into().expect("8 bytes")))
+        }
+        _ => None,
+    }
+}
+
+/// The block at `height` to mine at `nbits` on top of the last of `prev_headers`, or the first
+/// block if there are none: a coinbase paying `address` the reward plus fees, then the mempool
+/// transactions that `select_transactions` keeps against `utxos`. The timestamp is later than
+/// both the tip's and the median time past of `prev_headers`. The nonce is left for `grind`, and
+/// `extra_nonce` goes into the coinbase, so each template of a height gets a new merkle root.
+#[allow(clippy::too_many_arguments)]
+pub fn block_template(
+    prev_headers: &[BlockHeader],
+    height: u64,
+    nbits: u32,
+    mempool: Vec<Transaction>,
+    utxos: UtxoSet,
+    network: Network,
+    address: &str,
+    timestamp: u64,
+    extra_nonce: u64,
+) -> Result<Block, WalletError> {
+    let (prev_hash, timestamp) = match prev_headers.last() {
+        Some(tip) => {
+            let median = median_time_past(prev_headers).unwrap_or(tip.timestamp);
+            (block_hash(tip)?, timestamp.max(tip.timestamp.saturating_add(1)).max(median.saturating_add(1)))
+        }
+        None => (GENESIS_PREV_HASH.to_string(), timestamp),
+    };
+
+    let (paying, fees) = select_transactions(mempool, utxos, network)?;
+    let mut txs = vec![coinbase(address, BLOCK_REWARD.saturating_add(fees), height, extra_nonce)?];
+    txs.extend(paying);
+    let txids = txs.iter().map(|tx| Ok(hex_to_bytes(&txid(tx)?)?.to_vec())).collect::<Result<Vec<_>, WalletError>>()?;
+
+    Ok(Block {
+        header: BlockHeader {
+            version: BLOCK_VERSION,
+            prev_hash,
+            merkle_root: encode(calculate_merkle_root(txids)),
+            timestamp,
+            nbits,
+            nonce: 0,
+            hash: None,
+        },
+        txs,
+    })
+}
+
+/// The `mempool` transactions, in order, that `verify_transaction` accepts against `utxos` as
+/// the ones before them leave it, and the fees they pay. Fees are what the spent outputs hold,
+/// not what the inputs claim. A transaction spending an output an earlier one spent is left
+/// out, as are coinbases and any fee that would take the total past a u64.
+pub fn select_transactions(mempool: Vec<Transaction>, mut utxos: UtxoSet, network: Network) -> Result<(Vec<Transaction>, u64), WalletError> {
+    let mut fees = 0u64;
+    let mut selected = Vec::new();
+    for tx in mempool {
+        if coinbase_height(&tx).is_some() {
+            continue;
+        }
+        let Some(total) = verify_transaction(&tx, &utxos, network).ok().and_then(|fee| fees.checked_add(fee)) else {
+            continue;
+        };
+        fees = total;
+
+        for input in &tx.inputs {
+            utxos.remove(input);
+        }
+        let id = txid(&tx)?;
+        for (index, output) in tx.outputs.iter().enumerate() {
+            utxos.insert(&id, index as u32, output.clone());
+        }
+        selected.push(tx);
+    }
+    Ok((selected, fees))
+}
+
+/// Tries nonces for `header` on `threads` threads until one hashes to at most the target of its
+/// `nbits`. Returns the header with that nonce and its hash, or `None` once `control` is stopped
+/// or `deadline` passes.
+pub fn grind(header: &BlockHeader, threads: usize, control: &MiningControl, deadline: Instant) -> Result<Option<BlockHeader>, WalletError> {
+    let encoded = header.encode()?;
+    let target = target_from_nbits(header.nbits)?;
+    let threads = threads.clamp(1, MAX_MINING_THREADS) as u64;
+    let found = Arc::new(Mutex::new(None));
+    // set by whichever thread finds the nonce or runs out of time, so the others stop too
+    let done = Arc::new(AtomicBool::new(false));
+
+    let workers: Vec<_> = (0..threads)
+        .map(|first_nonce| {
+            let mut encoded = encoded.clone();
+            let (control, found, done) = (control.clone(), Arc::clone(&found), Arc::clone(&done));
+            thread::spawn(move || {
+                // each thread takes every `threads`th nonce, so no two try the same one
+                let mut nonce = first_nonce;
+                loop {
+                    for tried in 1..=NONCES_PER_CHECK {
+                        encoded[NONCE_OFFSET..].copy_from_slice(&nonce.to_le_bytes());
+                        if U256::from_be_bytes(to_array(double_sha256(&encoded)).expect("SHA-256 is 32 bytes")) <= target {
+                            control.hashes.fetch_add(tried, Ordering::Relaxed);
+                            *found.lock().unwrap_or_else(PoisonError::into_inner) = Some(nonce);
+                            done.store(true, Ordering::Relaxed);
+                            return;
+                        }
+                        nonce = nonce.wrapping_add(threads);
+                    }
+                    control.hashes.fetch_add(NONCES_PER_CHECK, Ordering::Relaxed);
+                    if done.load(Ordering::Relaxed) || control.is_stopped() || Instant::now() >= deadline {
+                        done.store(true, Ordering::Relaxed);
+                        return;
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    let nonce = *found.lock().unwrap_or_else(PoisonError::into_inner);
+    match nonce {
+        Some(nonce) => {
+            let mut mined = BlockHeader { nonce, ..header.clone() };
+            mined.hash = Some(block_hash(&mined)?);
+            Ok(Some(mined))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Mines a block paying `address` on `threads` threads and submits it to the nodes, rebuilding
+/// the template with the next extra nonce every `TEMPLATE_LIFETIME`. Returns the block once the
+/// quorum accepts it, or `None` if `control` is stopped first.
+pub async fn mine_block(nodes: &NodeClient, address: &str, threads: usize, control: &MiningControl) -> Result<Option<QuorumResult<Block>>, WalletError> {
+    let mut extra_nonce = 0;
+    while !control.is_stopped() {
+        let mut block = nodes.block_template(address, extra_nonce).await?;
+        extra_nonce += 1;
+
+        // grinding blocks, so it gets threads of its own rather than the async runtime's
+        let (sender, receiver) = oneshot::channel();
+        let (header, worker_control) = (block.header.clone(), control.clone());
+        thread::spawn(move || {
+            let _ = sender.send(grind(&header, threads, &worker_control, Instant::now() + TEMPLATE_LIFETIME));
+        });
+        // the sender is only dropped unsent if `grind` panicked
+        let mined = receiver.await.map_err(|_| WalletError::Io(io::Error::new(io::ErrorKind::Other, "the mining thread ended without a result")))??;
+
+        if let Some(header) = mined {
+            block.header = header;
+            let submitted = nodes.submit_block(&block).await?;
+            if !submitted.value {
+                return Err(WalletError::Rejected("nodes did not accept the block".to_string()));
+            }
+            return Ok(Some(submitted.map(|_| block)));
+        }
+    }
+    Ok(None)
+}
+
+// a mining run and the signal that its task has ended
+struct MiningRun {
+    control: MiningControl,
+    finished: oneshot::Receiver<()>,
+}
+
+/// The latest mining run, until it has finished.
+#[derive(Default)]
+pub struct Miner {
+    // held across waiting for a stopped run, so two starts cannot both go ahead
+    running: tokio::sync::Mutex<Option<MiningRun>>,
+}
+
+impl Miner {
+    /// Spawns the future `mine` builds from a new `MiningControl` unless a run is going, and
+    /// returns whether it did. A run that was stopped is waited for until its task has ended
+    /// first, so an old run never grinds next to a new one.
+    pub async fn start<F, Fut>(&self, mine: F) -> bool
+    where
+        F: FnOnce(MiningControl) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut running = self.running.lock().await;
+        if let Some(run) = running.take() {
+            if !run.control.is_stopped() {
+                *running = Some(run);
+                return false;
+            }
+            // the sender is dropped unsent if the task panicked, which ends it just the same
+            let _ = run.finished.await;
+        }
+
+        let control = MiningControl::new();
+        let (sender, finished) = oneshot::channel();
+        let run = mine(control.clone());
+        tauri::async_runtime::spawn(async move {
+            run.await;
+            let _ = sender.send(());
+        });
+        *running = Some(MiningRun { control, finished });
+        true
+    }
+
+    /// stops the current run; it ends once its threads notice
+    pub async fn stop(&self) {
+        if let Some(run) = &*self.running.lock().await {
+            run.control.stop();
+        }
+    }
+
+    pub async fn is_running(&self) -> bool {
+        matches!(&*self.running.lock().await, Some(run) if !run.control.is_stopped())
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MiningProgress {
+    pub threads: usize,
+    /// hashes since mining started
+    pub hashes: u64,
+    /// hashes per second over the last interval
+    pub hashrate: u64,
+}
+
+#[derive(Serialize, Clone)]
+struct BlockMined {
+    hash: Option<String>,
+    transactions: usize,
+    reward: u64,
+}
+
+#[derive(Serialize, Clone)]
+struct MiningStopped {
+    error: Option<String>,
+}
+
+// emits the hashrate every PROGRESS_INTERVAL until mining stops
+fn spawn_progress_reporter(app: AppHandle, control: MiningControl, threads: usize) {
+    thread::spawn(move || {
+        let mut last = control.hashes();
+        while !control.is_stopped() {
+            thread::sleep(PROGRESS_INTERVAL);
+            let hashes = control.hashes();
+            let hashrate = (hashes - last) / PROGRESS_INTERVAL.as_secs();
+            let _ = app.emit_all(MINING_PROGRESS_EVENT, MiningProgress { threads, hashes, hashrate });
+            last = hashes;
+        }
+    });
+}
+
+async fn mine_until_stopped(app: &AppHandle, address: &str, threads: usize, control: &MiningControl) -> Result<(), WalletError> {
+    let nodes = app.state::<NodeClient>();
+    while let Some(mined) = mine_block(&nodes, address, threads, control).await? {
+        let block = unwrap_quorum(app, "mine_block", mined);
+        let reward = block
+            .txs
+            .first()
+            .and_then(|coinbase| coinbase.outputs.first())
+            .ok_or_else(|| WalletError::InvalidBlock("mined block has no coinbase output".to_string()))?
+            .amount;
+        let mined = BlockMined {
+            hash: block.header.hash,
+            transactions: block.txs.len(),
+            reward,
+        };
+        let _ = app.emit_all(BLOCK_MINED_EVENT, mined);
+    }
+    Ok(())
+}
+
+/// Mines blocks paying the unlocked wallet on `threads` threads until `stop_mining`, emitting
+/// progress and every block found. Does nothing if mining is already running, and waits for a
+/// run that is still stopping.
+#[tauri::command]
+pub async fn start_mining(
+    threads: usize,
+    app: AppHandle,
+    session: State<'_, Mutex<WalletSession>>,
+    config: State<'_, Mutex<NetworkConfig>>,
+    miner: State<'_, Miner>,
+) -> Result<(), WalletError> {
+    let address = own_address(&session, &config)?;
+    let threads = threads.clamp(1, MAX_MINING_THREADS);
+
+    miner
+        .start(|control| {
+            spawn_progress_reporter(app.clone(), control.clone(), threads);
+            async move {
+                let result = mine_until_stopped(&app, &address, threads, &control).await;
+                control.stop();
+                let _ = app.emit_all(MINING_STOPPED_EVENT, MiningStopped { error: result.err().map(|e| e.to_string()) });
+            }
+        })
+        .await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_mining(miner: State<'_, Miner>) -> Result<(), WalletError> {
+    miner.stop().await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn is_mining(miner: State<'_, Miner>) -> Result<bool, WalletError> {
+    Ok(miner.is_running().await)
+}
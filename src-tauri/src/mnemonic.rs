@@ -0,0 +1,61 @@
+use bip39::Mnemonic;
+use rand::{rngs::OsRng, RngCore};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::WalletError;
+use crate::hd::{DerivationPath, ExtendedPrivateKey, EXTERNAL_CHAIN};
+use crate::secret::{Password, SecretBytes};
+
+pub const MNEMONIC_WORD_COUNTS: [usize; 2] = [12, 24];
+
+/// a new English recovery phrase of 12 or 24 words
+pub fn generate_mnemonic_phrase(word_count: usize) -> Result<Zeroizing<String>, WalletError> {
+    if !MNEMONIC_WORD_COUNTS.contains(&word_count) {
+        return Err(WalletError::InvalidMnemonic(format!("expected 12 or 24 words, got {}", word_count)));
+    }
+
+    // 32 bits of entropy per 3 words
+    let mut entropy = Zeroizing::new([0u8; 32]);
+    let entropy = &mut entropy[..word_count / 3 * 4];
+    OsRng.fill_bytes(entropy);
+
+    mnemonic_from_entropy(entropy)
+}
+
+pub fn mnemonic_from_entropy(entropy: &[u8]) -> Result<Zeroizing<String>, WalletError> {
+    Ok(Zeroizing::new(Mnemonic::from_entropy(entropy)?.to_string()))
+}
+
+/// checks the word list and checksum, ignoring case and extra whitespace
+pub fn parse_mnemonic(phrase: &str) -> Result<Mnemonic, WalletError> {
+    let phrase = Zeroizing::new(phrase.to_lowercase());
+    let mnemonic = Mnemonic::parse(phrase.as_str())?;
+
+    if !MNEMONIC_WORD_COUNTS.contains(&mnemonic.word_count()) {
+        return Err(WalletError::InvalidMnemonic(format!("expected 12 or 24 words, got {}", mnemonic.word_count())));
+    }
+    Ok(mnemonic)
+}
+
+/// the 64-byte BIP39 seed for `phrase`, salted with the optional `passphrase`
+pub fn mnemonic_to_seed(phrase: &str, passphrase: &Password) -> Result<SecretBytes, WalletError> {
+    let mnemonic = parse_mnemonic(phrase)?;
+
+    let mut seed = mnemonic.to_seed(passphrase.as_str());
+    let secret = SecretBytes::new(seed.to_vec());
+    seed.zeroize();
+
+    Ok(secret)
+}
+
+/// the wallet key for `seed`: the first receive key of account 0, `m/44'/8133'/0'/0/0`
+pub fn private_key_from_seed(seed: &SecretBytes) -> Result<SecretBytes, WalletError> {
+    let master = ExtendedPrivateKey::from_seed(seed)?;
+    Ok(master.derive_path(&DerivationPath::cj_coin(0, EXTERNAL_CHAIN, 0))?.private_key())
+}
+
+/// returns a fresh recovery phrase for the installation pages to show once
+#[tauri::command]
+pub fn generate_mnemonic(word_count: usize) -> Result<String, WalletError> {
+    Ok(generate_mnemonic_phrase(word_count)?.to_string())
+}
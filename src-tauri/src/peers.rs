@@ -0,0 +1,210 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Network;
+use crate::crypto_utils::Node;
+use crate::error::WalletError;
+
+/// minority or malformed answers in a row before a peer is banned
+pub const BAN_STRIKES: u32 = 3;
+pub const BAN_DURATION_SECS: u64 = 24 * 60 * 60;
+/// most peers kept besides banned ones; the lowest ranked are forgotten first
+pub const MAX_PEERS: usize = 256;
+
+// weight of the latency average given to the newest sample, in percent
+const LATENCY_SMOOTHING: u64 = 30;
+
+/// What the wallet has learned about one node.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PeerRecord {
+    pub node: Node,
+    /// unix seconds of the last answer, if it ever answered
+    pub last_seen: Option<u64>,
+    /// smoothed round-trip time of successful requests
+    pub latency_ms: Option<u64>,
+    pub successes: u32,
+    pub failures: u32,
+    /// answers that lost a quorum vote
+    pub disagreements: u32,
+    /// answers that could not be decoded
+    pub malformed: u32,
+    /// minority or malformed answers since the last agreeing one
+    pub strikes: u32,
+    pub banned_until: Option<u64>,
+}
+
+impl PeerRecord {
+    pub fn new(node: Node) -> Self {
+        Self {
+            node,
+            last_seen: None,
+            latency_ms: None,
+            successes: 0,
+            failures: 0,
+            disagreements: 0,
+            malformed: 0,
+            strikes: 0,
+            banned_until: None,
+        }
+    }
+
+    pub fn is_banned(&self, now: u64) -> bool {
+        matches!(self.banned_until, Some(until) if until > now)
+    }
+
+    /// Higher is better. Unknown peers score 0; answers raise it, while failures and
+    /// especially bad answers lower it. Every 100ms of latency costs a point.
+    pub fn score(&self) -> i64 {
+        let latency_penalty = self.latency_ms.map_or(0, |ms| ms as i64 / 100);
+        self.successes as i64 - 2 * self.failures as i64 - 5 * (self.disagreements + self.malformed) as i64 - latency_penalty
+    }
+
+    fn strike(&mut self, now: u64) {
+        self.strikes += 1;
+        if self.strikes >= BAN_STRIKES {
+            self.banned_until = Some(now + BAN_DURATION_SECS);
+            self.strikes = 0;
+        }
+    }
+}
+
+/// Known peers of one network with their health, optionally persisted as JSON.
+#[derive(Debug, Default)]
+pub struct PeerStore {
+    path: Option<PathBuf>,
+    peers: Vec<PeerRecord>,
+}
+
+impl PeerStore {
+    /// a store that is never written to disk
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// an empty store that will be saved to `path`
+    pub fn at(path: &Path) -> Self {
+        Self {
+            path: Some(path.to_path_buf()),
+            peers: Vec::new(),
+        }
+    }
+
+    /// reads `path`, starting empty if it does not exist yet
+    pub fn load(path: &Path) -> Result<Self, WalletError> {
+        let peers = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            peers,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), WalletError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&self.peers)?)?;
+        Ok(())
+    }
+
+    pub fn records(&self) -> &[PeerRecord] {
+        &self.peers
+    }
+
+    pub fn get(&self, node: &Node) -> Option<&PeerRecord> {
+        self.peers.iter().find(|record| record.node == *node)
+    }
+
+    fn entry(&mut self, node: &Node) -> &mut PeerRecord {
+        match self.peers.iter().position(|record| record.node == *node) {
+            Some(index) => &mut self.peers[index],
+            None => {
+                self.peers.push(PeerRecord::new(node.clone()));
+                self.peers.last_mut().expect("just pushed")
+            }
+        }
+    }
+
+    /// Remembers newly discovered peers, keeping what is known about the others. Past
+    /// `MAX_PEERS` the lowest ranked are forgotten, but never a banned one: forgetting it would
+    /// let the next discovery bring it back with a clean record.
+    pub fn add_all(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.entry(node);
+        }
+        if self.peers.len() > MAX_PEERS {
+            let (mut kept, mut ranked): (Vec<_>, Vec<_>) = std::mem::take(&mut self.peers).into_iter().partition(|record| record.banned_until.is_some());
+            ranked.sort_by_key(|record| std::cmp::Reverse(record.score()));
+            ranked.truncate(MAX_PEERS.saturating_sub(kept.len()));
+            kept.extend(ranked);
+            self.peers = kept;
+        }
+    }
+
+    /// peers that are not banned, best first; ties keep the order they were learned in
+    pub fn ranked(&self, now: u64) -> Vec<Node> {
+        let mut usable: Vec<&PeerRecord> = self.peers.iter().filter(|record| !record.is_banned(now)).collect();
+        usable.sort_by_key(|record| std::cmp::Reverse(record.score()));
+        usable.into_iter().map(|record| record.node.clone()).collect()
+    }
+
+    pub fn record_success(&mut self, node: &Node, latency_ms: u64, now: u64) {
+        let record = self.entry(node);
+        record.successes += 1;
+        record.last_seen = Some(now);
+        record.latency_ms = Some(match record.latency_ms {
+            Some(average) => (average * (100 - LATENCY_SMOOTHING) + latency_ms * LATENCY_SMOOTHING) / 100,
+            None => latency_ms,
+        });
+    }
+
+    /// a request that failed; `malformed` if the node answered with something undecodable
+    pub fn record_failure(&mut self, node: &Node, malformed: bool, now: u64) {
+        let record = self.entry(node);
+        record.failures += 1;
+        if malformed {
+            record.last_seen = Some(now);
+            record.malformed += 1;
+            record.strike(now);
+        }
+    }
+
+    /// the node's answer matched the quorum
+    pub fn record_agreement(&mut self, node: &Node) {
+        self.entry(node).strikes = 0;
+    }
+
+    /// the node's answer lost the quorum vote
+    pub fn record_disagreement(&mut self, node: &Node, now: u64) {
+        let record = self.entry(node);
+        record.disagreements += 1;
+        record.strike(now);
+    }
+
+    /// lifts the node's ban and clears its strikes; a node never seen is left unknown
+    pub fn unban(&mut self, node: &Node) {
+        if let Some(record) = self.peers.iter_mut().find(|record| record.node == *node) {
+            record.banned_until = None;
+            record.strikes = 0;
+        }
+    }
+}
+
+/// `peers-<network>.json`, next to the rest of the app data
+pub fn peer_store_file(network: Network) -> String {
+    format!("peers-{}.json", network.name())
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,270 @@
+// Proof of work: the compact `nbits` encoding of targets, chain work and the check that a header
+// hashes to at most its target. A hash is read as a big-endian number, so a hash meeting a low
+// target starts with zeros when printed.
+
+use std::cmp::Ordering;
+use std::ops::{Div, Not, Shl, Shr};
+
+use crate::codec::block_hash;
+use crate::crypto_utils::{hex_to_bytes, BlockHeader};
+use crate::error::WalletError;
+
+// the sign bit of the compact mantissa
+const NBITS_SIGN: u32 = 0x0080_0000;
+const NBITS_MANTISSA: u32 = 0x007f_ffff;
+
+/// A 256-bit unsigned integer, for targets and chain work.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct U256 {
+    // least significant first
+    limbs: [u64; 4],
+}
+
+impl U256 {
+    pub const ZERO: U256 = U256 { limbs: [0; 4] };
+    pub const ONE: U256 = U256 { limbs: [1, 0, 0, 0] };
+    pub const MAX: U256 = U256 { limbs: [u64::MAX; 4] };
+
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, chunk) in bytes.chunks(8).enumerate() {
+            let mut limb = [0u8; 8];
+            limb.copy_from_slice(chunk);
+            limbs[3 - i] = u64::from_be_bytes(limb);
+        }
+        Self { limbs }
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.limbs.iter().rev().enumerate() {
+            bytes[i * 8..i * 8 + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+
+    /// the lowest 64 bits
+    pub fn low_u64(&self) -> u64 {
+        self.limbs[0]
+    }
+
+    /// position of the highest set bit plus one; 0 for zero
+    pub fn bits(&self) -> u32 {
+        for (i, limb) in self.limbs.iter().enumerate().rev() {
+            if *limb != 0 {
+                return 64 * i as u32 + 64 - limb.leading_zeros();
+            }
+        }
+        0
+    }
+
+    pub fn checked_add(self, rhs: U256) -> Option<U256> {
+        let mut limbs = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (sum, first) = self.limbs[i].overflowing_add(rhs.limbs[i]);
+            let (sum, second) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = first || second;
+        }
+        (!carry).then(|| U256 { limbs })
+    }
+
+    pub fn checked_sub(self, rhs: U256) -> Option<U256> {
+        let mut limbs = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (difference, first) = self.limbs[i].overflowing_sub(rhs.limbs[i]);
+            let (difference, second) = difference.overflowing_sub(borrow as u64);
+            *limb = difference;
+            borrow = first || second;
+        }
+        (!borrow).then(|| U256 { limbs })
+    }
+
+    pub fn saturating_add(self, rhs: U256) -> U256 {
+        self.checked_add(rhs).unwrap_or(U256::MAX)
+    }
+
+    pub fn checked_mul_u64(self, rhs: u64) -> Option<U256> {
+        let mut limbs = [0u64; 4];
+        let mut carry = 0u128;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let product = self.limbs[i] as u128 * rhs as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        (carry == 0).then(|| U256 { limbs })
+    }
+
+    /// quotient and remainder; panics if `rhs` is zero
+    pub fn div_rem_u64(self, rhs: u64) -> (U256, u64) {
+        let mut limbs = [0u64; 4];
+        let mut remainder = 0u128;
+        for (i, limb) in limbs.iter_mut().enumerate().rev() {
+            let dividend = remainder << 64 | self.limbs[i] as u128;
+            *limb = (dividend / rhs as u128) as u64;
+            remainder = dividend % rhs as u128;
+        }
+        (U256 { limbs }, remainder as u64)
+    }
+
+    /// `None` if `rhs` is zero
+    pub fn checked_div(self, rhs: U256) -> Option<U256> {
+        if rhs.is_zero() {
+            return None;
+        }
+        // shift-and-subtract, one quotient bit per round
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for bit in (0..self.bits()).rev() {
+            remainder = remainder << 1;
+            remainder.limbs[0] |= (self >> bit).limbs[0] & 1;
+            if remainder >= rhs {
+                remainder = remainder.checked_sub(rhs).expect("remainder is at least rhs");
+                quotient.limbs[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+        Some(quotient)
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        U256 { limbs: [value, 0, 0, 0] }
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.limbs.iter().rev().cmp(other.limbs.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Not for U256 {
+    type Output = U256;
+
+    fn not(self) -> U256 {
+        U256 { limbs: self.limbs.map(|limb| !limb) }
+    }
+}
+
+/// bits shifted past the top are dropped
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> U256 {
+        let mut limbs = [0u64; 4];
+        let (words, bits) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in limbs.iter_mut().enumerate().skip(words) {
+            *limb = self.limbs[i - words] << bits;
+            if bits > 0 && i > words {
+                *limb |= self.limbs[i - words - 1] >> (64 - bits);
+            }
+        }
+        U256 { limbs }
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> U256 {
+        let mut limbs = [0u64; 4];
+        let (words, bits) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in limbs.iter_mut().enumerate().take(4usize.saturating_sub(words)) {
+            *limb = self.limbs[i + words] >> bits;
+            if bits > 0 && i + words + 1 < 4 {
+                *limb |= self.limbs[i + words + 1] << (64 - bits);
+            }
+        }
+        U256 { limbs }
+    }
+}
+
+/// panics on division by zero, like the integer types
+impl Div for U256 {
+    type Output = U256;
+
+    fn div(self, rhs: U256) -> U256 {
+        self.checked_div(rhs).expect("division by zero")
+    }
+}
+
+/// Expands compact `nbits` to the target it encodes: the low 23 bits are a mantissa shifted
+/// left by `exponent - 3` bytes, the exponent being the high byte, as in Bitcoin. A set sign
+/// bit on a non-zero mantissa and targets past 256 bits are errors.
+pub fn target_from_nbits(nbits: u32) -> Result<U256, WalletError> {
+    let exponent = nbits >> 24;
+    let mut mantissa = nbits & NBITS_MANTISSA;
+    if exponent <= 3 {
+        mantissa >>= 8 * (3 - exponent);
+    }
+    if mantissa == 0 {
+        return Ok(U256::ZERO);
+    }
+    if nbits & NBITS_SIGN != 0 {
+        return Err(WalletError::InvalidBlock(format!("nbits {:#010x} encodes a negative target", nbits)));
+    }
+    if exponent > 34 || (mantissa > 0xff && exponent > 33) || (mantissa > 0xffff && exponent > 32) {
+        return Err(WalletError::InvalidBlock(format!("nbits {:#010x} encodes a target past 256 bits", nbits)));
+    }
+    Ok(U256::from(mantissa as u64) << (8 * exponent.saturating_sub(3)))
+}
+
+/// The canonical compact encoding of `target`. Precision beyond the 3 most significant bytes
+/// is dropped, so only targets with at most 3 significant bytes survive the round trip.
+pub fn nbits_from_target(target: U256) -> u32 {
+    let mut size = (target.bits() + 7) / 8;
+    let mut mantissa = if size <= 3 {
+        (target.low_u64() << (8 * (3 - size))) as u32
+    } else {
+        (target >> (8 * (size - 3))).low_u64() as u32
+    };
+    // a mantissa with its top bit set would read as negative
+    if mantissa & NBITS_SIGN != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    mantissa | size << 24
+}
+
+/// The work a block at `nbits` proves: the expected number of hashes to meet its target,
+/// `2^256 / (target + 1)`. Chains are compared by the sum of their blocks' work.
+pub fn block_work(nbits: u32) -> Result<U256, WalletError> {
+    let target = target_from_nbits(nbits)?;
+    if target.is_zero() {
+        return Err(WalletError::InvalidBlock(format!("nbits {:#010x} encodes a zero target", nbits)));
+    }
+    // 2^256 does not fit, but (2^256 - target - 1) / (target + 1) + 1 is the same
+    let divisor = target.checked_add(U256::ONE).unwrap_or(U256::MAX);
+    Ok((!target / divisor).saturating_add(U256::ONE))
+}
+
+/// whether `hash`, 32 bytes of hex, is at most `target`
+pub fn hash_meets_target(hash: &str, target: U256) -> Result<bool, WalletError> {
+    Ok(U256::from_be_bytes(hex_to_bytes(hash)?) <= target)
+}
+
+/// Checks that `header` hashes to at most the target of its `nbits`, and that the hash it
+/// claims, if any, is its own.
+pub fn check_proof_of_work(header: &BlockHeader) -> Result<(), WalletError> {
+    let hash = block_hash(header)?;
+    if matches!(&header.hash, Some(claimed) if *claimed != hash) {
+        return Err(WalletError::InvalidBlock("hash does not match the header".to_string()));
+    }
+    if !hash_meets_target(&hash, target_from_nbits(header.nbits)?)? {
+        return Err(WalletError::InvalidBlock(format!("hash {} is above the target of nbits {:#010x}", hash, header.nbits)));
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+
+use crate::crypto_utils::Node;
+use crate::error::WalletError;
+
+/// How much agreement a query needs before its answer is trusted.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuorumPolicy {
+    /// share of the responding nodes that must return the same answer, in percent
+    pub threshold_percent: u32,
+    /// fewest nodes that must agree, however many respond
+    pub min_agreeing: usize,
+    /// most nodes queried at the same time
+    pub max_concurrent: usize,
+}
+
+impl Default for QuorumPolicy {
+    /// a strict majority of whoever answered, and at least one node
+    fn default() -> Self {
+        Self {
+            threshold_percent: 51,
+            min_agreeing: 1,
+            max_concurrent: 8,
+        }
+    }
+}
+
+impl QuorumPolicy {
+    pub fn new(threshold_percent: u32, min_agreeing: usize) -> Self {
+        Self {
+            threshold_percent,
+            min_agreeing,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = max_concurrent;
+        self
+    }
+
+    fn is_met(&self, agreeing: usize, responded: usize) -> bool {
+        agreeing >= self.min_agreeing.max(1) && agreeing as u64 * 100 >= self.threshold_percent as u64 * responded as u64
+    }
+
+    // true once the leading answer wins however the `pending` nodes still answer:
+    // it stays ahead of the runner-up and still meets the threshold if they all dissent
+    fn is_decided(&self, largest: usize, runner_up: usize, responded: usize, pending: usize) -> bool {
+        largest > runner_up + pending && self.is_met(largest, responded + pending)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct NodeFailure {
+    pub node: Node,
+    pub error: String,
+    /// the node answered, but with something that could not be decoded
+    pub malformed: bool,
+}
+
+/// Which nodes backed the accepted answer, which answered differently and which did
+/// not answer at all.
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct QuorumReport {
+    pub agreeing: Vec<Node>,
+    pub dissenting: Vec<Node>,
+    pub unreachable: Vec<NodeFailure>,
+    /// nodes not asked, or cancelled, because the quorum was already reached
+    pub skipped: Vec<Node>,
+}
+
+impl QuorumReport {
+    /// every node that was asked answered and they all agreed
+    pub fn is_unanimous(&self) -> bool {
+        self.dissenting.is_empty() && self.unreachable.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct QuorumResult<T> {
+    pub value: T,
+    pub report: QuorumReport,
+}
+
+impl<T> QuorumResult<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> QuorumResult<U> {
+        QuorumResult {
+            value: f(self.value),
+            report: self.report,
+        }
+    }
+}
+
+/// Runs `query` against the nodes, at most `policy.max_concurrent` at a time, and returns the
+/// answer that meets `policy`. A node that errors counts as unreachable rather than failing the
+/// whole query. Queries still running once the outcome is decided are dropped, which cancels them.
+pub async fn quorum_query<T, F, Fut>(nodes: &[Node], policy: &QuorumPolicy, query: F) -> Result<QuorumResult<T>, WalletError>
+where
+    T: Serialize,
+    F: Fn(Node) -> Fut,
+    Fut: Future<Output = Result<T, WalletError>>,
+{
+    // owned nodes keep the stream's closure free of borrowed arguments, so the future stays `Send`
+    let mut pending = stream::iter(nodes.iter().cloned().enumerate())
+        .map(|(index, node)| {
+            let response = query(node);
+            async move { (index, response.await) }
+        })
+        .buffer_unordered(policy.max_concurrent.max(1));
+
+    let mut running = Tally::default();
+    let mut answered = vec![false; nodes.len()];
+    let mut responses = Vec::new();
+
+    while let Some((index, response)) = pending.next().await {
+        answered[index] = true;
+        if let Ok(value) = &response {
+            running.add(serde_json::to_string(value)?);
+        }
+        responses.push((index, response));
+
+        if running.is_decided(policy, nodes.len() - responses.len()) {
+            break;
+        }
+    }
+    drop(pending);
+
+    // report nodes in the order they were given, not the order they answered in
+    responses.sort_by_key(|(index, _)| *index);
+    let responses = responses.into_iter().map(|(index, response)| (nodes[index].clone(), response)).collect();
+    let skipped = nodes.iter().zip(answered).filter(|(_, answered)| !answered).map(|(node, _)| node.clone()).collect();
+
+    let mut result = tally(responses, policy)?;
+    result.report.skipped = skipped;
+    Ok(result)
+}
+
+// running count of identical answers, keyed by their JSON
+#[derive(Default)]
+struct Tally {
+    counts: HashMap<String, usize>,
+}
+
+impl Tally {
+    fn add(&mut self, key: String) {
+        *self.counts.entry(key).or_insert(0) += 1;
+    }
+
+    fn is_decided(&self, policy: &QuorumPolicy, pending: usize) -> bool {
+        let mut counts: Vec<usize> = self.counts.values().copied().collect();
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+
+        let largest = counts.first().copied().unwrap_or(0);
+        let runner_up = counts.get(1).copied().unwrap_or(0);
+        policy.is_decided(largest, runner_up, counts.iter().sum(), pending)
+    }
+}
+
+/// Groups the per-node responses by their JSON and checks the largest group against `policy`.
+/// Ties between the largest groups never reach quorum.
+pub fn tally<T: Serialize>(responses: Vec<(Node, Result<T, WalletError>)>, policy: &QuorumPolicy) -> Result<QuorumResult<T>, WalletError> {
+    let mut groups: Vec<(T, Vec<Node>)> = Vec::new();
+    let mut group_by_key: HashMap<String, usize> = HashMap::new();
+    let mut unreachable = Vec::new();
+
+    for (node, response) in responses {
+        let value = match response {
+            Ok(value) => value,
+            Err(e) => {
+                unreachable.push(NodeFailure {
+                    node,
+                    malformed: e.is_malformed_response(),
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let key = serde_json::to_string(&value)?;
+        match group_by_key.get(&key) {
+            Some(&index) => groups[index].1.push(node),
+            None => {
+                group_by_key.insert(key, groups.len());
+                groups.push((value, vec![node]));
+            }
+        }
+    }
+
+    let responded: usize = groups.iter().map(|(_, nodes)| nodes.len()).sum();
+    let largest = groups.iter().map(|(_, nodes)| nodes.len()).max().unwrap_or(0);
+    let tied = groups.iter().filter(|(_, nodes)| nodes.len() == largest).count() > 1;
+
+    if responded == 0 || tied || !policy.is_met(largest, responded) {
+        return Err(WalletError::NoQuorum {
+            agreeing: if tied { 0 } else { largest },
+            responded,
+            unreachable: unreachable.len(),
+        });
+    }
+
+    let winner = groups.iter().position(|(_, nodes)| nodes.len() == largest).expect("largest group exists");
+    let (value, agreeing) = groups.swap_remove(winner);
+    let dissenting = groups.into_iter().flat_map(|(_, nodes)| nodes).collect();
+
+    Ok(QuorumResult {
+        value,
+        report: QuorumReport {
+            agreeing,
+            dissenting,
+            unreachable,
+            skipped: Vec::new(),
+        },
+    })
+}
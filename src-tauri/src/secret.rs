@@ -0,0 +1,111 @@
+use hex::decode;
+use serde::{Deserialize, Deserializer};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::WalletError;
+
+/// Secret key material that is wiped when dropped. Deliberately has no `Debug`,
+/// `Display` or `Serialize` so it cannot end up in logs or IPC responses.
+pub struct SecretBytes(Vec<u8>);
+
+impl SecretBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    pub fn from_hex(hex_str: &str) -> Result<Self, WalletError> {
+        Ok(Self(decode(hex_str)?))
+    }
+
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// copies into a fixed-size array that is also wiped on drop
+    pub fn to_array<const N: usize>(&self) -> Result<Zeroizing<[u8; N]>, WalletError> {
+        if self.0.len() != N {
+            return Err(WalletError::KeyLength {
+                expected: N,
+                actual: self.0.len(),
+            });
+        }
+
+        let mut array = Zeroizing::new([0u8; N]);
+        array.copy_from_slice(&self.0);
+        Ok(array)
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// A user password, wiped when dropped. Can be received from the frontend
+/// but never serialized back.
+pub struct Password(Zeroizing<String>);
+
+impl Password {
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Password {
+    fn from(password: String) -> Self {
+        Self(Zeroizing::new(password))
+    }
+}
+
+impl From<&str> for Password {
+    fn from(password: &str) -> Self {
+        Self(Zeroizing::new(password.to_string()))
+    }
+}
+
+impl<'de> Deserialize<'de> for Password {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Password::from)
+    }
+}
+
+/// A recovery phrase typed in by the user, wiped when dropped. Like `Password`
+/// it can only travel from the frontend to Rust.
+pub struct SecretPhrase(Zeroizing<String>);
+
+impl SecretPhrase {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretPhrase {
+    fn from(phrase: String) -> Self {
+        Self(Zeroizing::new(phrase))
+    }
+}
+
+impl From<&str> for SecretPhrase {
+    fn from(phrase: &str) -> Self {
+        Self(Zeroizing::new(phrase.to_string()))
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretPhrase {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(SecretPhrase::from)
+    }
+}
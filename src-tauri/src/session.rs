@@ -0,0 +1,164 @@
+use std::{
+    sync::{Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use hex::encode;
+use rand::{thread_rng, Rng};
+use tauri::{AppHandle, Manager, State};
+
+use crate::codec::message_hash;
+use crate::crypto_utils::sign;
+use crate::error::WalletError;
+use crate::keystore::Keystore;
+use crate::secret::{Password, SecretBytes};
+
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const AUTO_LOCK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+struct UnlockedWallet {
+    handle: String,
+    public_key: String,
+    private_key: SecretBytes,
+    last_activity: Instant,
+}
+
+/// Holds the decrypted key after `unlock_wallet` so it never has to cross into the webview.
+/// The key is dropped on `lock` or once the wallet has been idle for `idle_timeout`.
+pub struct WalletSession {
+    unlocked: Option<UnlockedWallet>,
+    idle_timeout: Duration,
+}
+
+impl Default for WalletSession {
+    fn default() -> Self {
+        Self::new(DEFAULT_IDLE_TIMEOUT)
+    }
+}
+
+impl WalletSession {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            unlocked: None,
+            idle_timeout,
+        }
+    }
+
+    /// decrypts the keystore at `path` and returns an opaque handle for the session. Whatever
+    /// was unlocked before is locked first, so a failed unlock leaves nothing unlocked.
+    pub fn unlock(&mut self, path: &str, password: &Password) -> Result<String, WalletError> {
+        self.lock();
+        let keystore = Keystore::load(path)?;
+        let private_key = keystore.decrypt(password)?;
+
+        let mut handle = [0u8; 16];
+        thread_rng().fill(&mut handle);
+        let handle = encode(handle);
+
+        self.unlocked = Some(UnlockedWallet {
+            handle: handle.clone(),
+            public_key: keystore.public_key,
+            private_key,
+            last_activity: Instant::now(),
+        });
+
+        Ok(handle)
+    }
+
+    pub fn lock(&mut self) {
+        self.unlocked = None;
+    }
+
+    pub fn is_unlocked(&mut self) -> bool {
+        self.expire_if_idle();
+        self.unlocked.is_some()
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// locks the wallet if it has been idle too long; returns true if this call locked it
+    pub fn expire_if_idle(&mut self) -> bool {
+        match &self.unlocked {
+            Some(wallet) if wallet.last_activity.elapsed() >= self.idle_timeout => {
+                self.lock();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn handle(&mut self) -> Option<&str> {
+        self.expire_if_idle();
+        self.unlocked.as_ref().map(|wallet| wallet.handle.as_str())
+    }
+
+    pub fn public_key(&mut self) -> Option<&str> {
+        self.expire_if_idle();
+        self.unlocked.as_ref().map(|wallet| wallet.public_key.as_str())
+    }
+
+    /// signs with the unlocked key, returning (signature, public key), if `handle` is the one
+    /// its unlock returned; a handle from an earlier unlock counts as locked. Counts as activity.
+    pub fn sign(&mut self, handle: &str, message: impl AsRef<[u8]>) -> Result<(String, String), WalletError> {
+        self.expire_if_idle();
+        let wallet = self.unlocked.as_mut().filter(|wallet| wallet.handle == handle).ok_or(WalletError::Locked)?;
+        wallet.last_activity = Instant::now();
+
+        sign(&wallet.private_key, message)
+    }
+
+    /// `sign` over the `message_hash` of `message`, never `message` itself, so text from the
+    /// webview cannot be passed off as a transaction sighash
+    pub fn sign_message(&mut self, handle: &str, message: &str) -> Result<(String, String), WalletError> {
+        self.sign(handle, message_hash(message))
+    }
+}
+
+/// polls the managed session and emits `wallet-locked` when it auto-locks
+pub fn spawn_auto_lock(app: AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(AUTO_LOCK_POLL_INTERVAL);
+
+        let session = app.state::<Mutex<WalletSession>>();
+        let locked = session.lock().unwrap_or_else(PoisonError::into_inner).expire_if_idle();
+        if locked {
+            let _ = app.emit_all("wallet-locked", ());
+        }
+    });
+}
+
+#[tauri::command]
+pub fn unlock_wallet(path: &str, password: Password, session: State<'_, Mutex<WalletSession>>) -> Result<String, WalletError> {
+    session.lock().unwrap_or_else(PoisonError::into_inner).unlock(path, &password)
+}
+
+#[tauri::command]
+pub fn lock_wallet(session: State<'_, Mutex<WalletSession>>) {
+    session.lock().unwrap_or_else(PoisonError::into_inner).lock();
+}
+
+#[tauri::command]
+pub fn is_unlocked(session: State<'_, Mutex<WalletSession>>) -> bool {
+    session.lock().unwrap_or_else(PoisonError::into_inner).is_unlocked()
+}
+
+#[tauri::command]
+pub fn set_auto_lock_timeout(seconds: u64, session: State<'_, Mutex<WalletSession>>) {
+    session
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .set_idle_timeout(Duration::from_secs(seconds));
+}
+
+/// signs the `message_hash` of `message` with the wallet `handle` unlocked, returning (signature, public key)
+#[tauri::command]
+pub fn sign_message(handle: &str, message: &str, session: State<'_, Mutex<WalletSession>>) -> Result<(String, String), WalletError> {
+    session.lock().unwrap_or_else(PoisonError::into_inner).sign_message(handle, message)
+}
@@ -0,0 +1,361 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::address::{address_from_public_key, parse_address};
+use crate::codec::{sighash, txid};
+use crate::config::Network;
+use crate::crypto_utils::{hex_to_bytes, verify, Input, Output, Transaction};
+use crate::error::WalletError;
+
+pub(crate) const VERSION: u32 = 1;
+
+/// change below this is not worth an output of its own and goes to the fee instead
+pub const DEFAULT_DUST_THRESHOLD: u64 = 10;
+// branch-and-bound gives up after visiting this many selections
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// an unspent output as the nodes report it; spending it makes it an `Input`
+pub type Utxo = Input;
+
+/// How inputs are picked from the wallet's UTXOs. Every strategy is deterministic:
+/// the same UTXOs and amount always select the same inputs.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CoinSelection {
+    /// in the order the nodes list them, confirmed before unconfirmed
+    #[default]
+    OldestFirst,
+    /// fewest inputs
+    LargestFirst,
+    /// consolidates small UTXOs
+    SmallestFirst,
+    /// an exact match, up to dust, so no change output is needed; falls back to
+    /// largest-first if there is none
+    BranchAndBound,
+}
+
+/// Picks inputs for a payment and builds the unsigned transaction around them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransactionBuilder {
+    strategy: CoinSelection,
+    include_unconfirmed: bool,
+    dust_threshold: u64,
+}
+
+impl Default for TransactionBuilder {
+    fn default() -> Self {
+        Self {
+            strategy: CoinSelection::default(),
+            include_unconfirmed: true,
+            dust_threshold: DEFAULT_DUST_THRESHOLD,
+        }
+    }
+}
+
+impl TransactionBuilder {
+    pub fn new(strategy: CoinSelection) -> Self {
+        Self {
+            strategy,
+            ..Self::default()
+        }
+    }
+
+    /// whether mempool UTXOs may be spent; they are only ever used after confirmed ones
+    /// of the same amount
+    pub fn with_unconfirmed(mut self, include_unconfirmed: bool) -> Self {
+        self.include_unconfirmed = include_unconfirmed;
+        self
+    }
+
+    pub fn with_dust_threshold(mut self, dust_threshold: u64) -> Self {
+        self.dust_threshold = dust_threshold;
+        self
+    }
+
+    /// the inputs covering `needed`, chosen by the strategy
+    pub fn select(&self, confirmed: &[Utxo], unconfirmed: &[Utxo], needed: u64) -> Result<Vec<Utxo>, WalletError> {
+        let mut candidates: Vec<&Utxo> = confirmed.iter().collect();
+        if self.include_unconfirmed {
+            candidates.extend(unconfirmed);
+        }
+
+        let available = candidates.iter().fold(0u64, |total, utxo| total.saturating_add(utxo.amount));
+        if available < needed {
+            return Err(WalletError::InsufficientFunds { needed, available });
+        }
+
+        // stable sorts, so equal amounts keep their oldest-first order
+        match self.strategy {
+            CoinSelection::OldestFirst => {}
+            CoinSelection::LargestFirst => candidates.sort_by_key(|utxo| std::cmp::Reverse(utxo.amount)),
+            CoinSelection::SmallestFirst => candidates.sort_by_key(|utxo| utxo.amount),
+            CoinSelection::BranchAndBound => {
+                candidates.sort_by_key(|utxo| std::cmp::Reverse(utxo.amount));
+                let max_total = needed.saturating_add(self.dust_threshold.saturating_sub(1));
+                if let Some(selected) = branch_and_bound(&candidates, needed, max_total) {
+                    return Ok(selected.into_iter().map(|index| candidates[index].clone()).collect());
+                }
+            }
+        }
+
+        let mut total = 0u64;
+        Ok(candidates
+            .into_iter()
+            .take_while(|utxo| {
+                let covered = total >= needed;
+                total = total.saturating_add(utxo.amount);
+                !covered
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Selects inputs for `amount + fee` and returns the unsigned transaction, with change back
+    /// to `own_address` unless it is below the dust threshold, in which case it adds to the fee.
+    pub fn build(&self, confirmed: &[Utxo], unconfirmed: &[Utxo], amount: u64, address: &str, fee: u64, own_address: &str) -> Result<Transaction, WalletError> {
+        let needed = amount_plus_fee(amount, fee)?;
+        let inputs = self.select(confirmed, unconfirmed, needed)?;
+
+        let available = total_amount(&inputs)?;
+        let change = available.checked_sub(needed).ok_or(WalletError::InsufficientFunds { needed, available })?;
+        // at most what the inputs hold less the amount, so this cannot overflow either
+        let fee = if change < self.dust_threshold { fee + change } else { fee };
+        let outputs = generate_outputs(&inputs, amount, address, fee, own_address)?;
+
+        Ok(Transaction {
+            version: VERSION,
+            inputs,
+            outputs,
+            txid: None,
+        })
+    }
+}
+
+/// the payment output plus change to `own_address`, if there is any
+pub fn generate_outputs(inputs: &[Input], amount: u64, address: &str, fee: u64, own_address: &str) -> Result<Vec<Output>, WalletError> {
+    let available = total_amount(inputs)?;
+    let needed = amount_plus_fee(amount, fee)?;
+    let change = available.checked_sub(needed).ok_or(WalletError::InsufficientFunds { needed, available })?;
+    let mut outputs = vec![Output {
+        amount,
+        address: address.to_string(),
+    }];
+
+    if change > 0 {
+        outputs.push(Output {
+            amount: change,
+            address: own_address.to_string(),
+        });
+    }
+
+    Ok(outputs)
+}
+
+// what `inputs` hold together, which nodes could report more than a u64 of
+fn total_amount(inputs: &[Input]) -> Result<u64, WalletError> {
+    inputs
+        .iter()
+        .try_fold(0u64, |total, input| total.checked_add(input.amount))
+        .ok_or_else(|| WalletError::InvalidAmount("inputs add up to more than a u64 holds".to_string()))
+}
+
+fn amount_plus_fee(amount: u64, fee: u64) -> Result<u64, WalletError> {
+    amount.checked_add(fee).ok_or_else(|| WalletError::InvalidAmount("amount plus fee overflows".to_string()))
+}
+
+// Depth-first search over `coins`, largest first, for the selection totalling between
+// `target` and `max_total` with the least excess. Returns indices into `coins`.
+fn branch_and_bound(coins: &[&Utxo], target: u64, max_total: u64) -> Option<Vec<usize>> {
+    let amounts: Vec<u64> = coins.iter().map(|coin| coin.amount).collect();
+
+    // remaining[i] is what coins i.. add up to, to prune branches that can no longer reach the target
+    let mut remaining = vec![0u64; amounts.len()];
+    let mut sum = 0u64;
+    for (index, amount) in amounts.iter().enumerate().rev() {
+        sum = sum.saturating_add(*amount);
+        remaining[index] = sum;
+    }
+
+    let mut search = Search {
+        amounts,
+        remaining,
+        target,
+        max_total,
+        tries: BNB_MAX_TRIES,
+        current: Vec::new(),
+        best: None,
+    };
+    search.explore(0, 0);
+    search.best.map(|(_, selected)| selected)
+}
+
+struct Search {
+    amounts: Vec<u64>,
+    remaining: Vec<u64>,
+    target: u64,
+    max_total: u64,
+    tries: usize,
+    current: Vec<usize>,
+    best: Option<(u64, Vec<usize>)>,
+}
+
+impl Search {
+    fn explore(&mut self, index: usize, total: u64) {
+        if self.tries == 0 || total > self.max_total || matches!(self.best, Some((0, _))) {
+            return;
+        }
+        self.tries -= 1;
+
+        if total >= self.target {
+            // adding more coins would only add excess
+            let excess = total - self.target;
+            if !matches!(self.best, Some((best, _)) if best <= excess) {
+                self.best = Some((excess, self.current.clone()));
+            }
+            return;
+        }
+        if index == self.amounts.len() || total.saturating_add(self.remaining[index]) < self.target {
+            return;
+        }
+
+        self.current.push(index);
+        self.explore(index + 1, total.saturating_add(self.amounts[index]));
+        self.current.pop();
+
+        // with this coin left out, trying an equal one in its place would only repeat the branch above
+        let mut next = index + 1;
+        while next < self.amounts.len() && self.amounts[next] == self.amounts[index] {
+            next += 1;
+        }
+        self.explore(next, total);
+    }
+}
+
+/// The outputs a transaction may spend, keyed by `(txid, output index)`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UtxoSet {
+    outputs: HashMap<(String, u32), Output>,
+}
+
+impl UtxoSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, prev_txid: &str, output_index: u32, output: Output) {
+        self.outputs.insert((prev_txid.to_string(), output_index), output);
+    }
+
+    /// adds UTXOs as the nodes list them for `address`, which they all pay
+    pub fn add_owned(&mut self, address: &str, utxos: &[Utxo]) {
+        for utxo in utxos {
+            let output = Output {
+                amount: utxo.amount,
+                address: address.to_string(),
+            };
+            self.insert(&utxo.prev_txid, utxo.output_index, output);
+        }
+    }
+
+    /// the output `input` spends, if it is unspent
+    pub fn get(&self, input: &Input) -> Option<&Output> {
+        self.outputs.get(&(input.prev_txid.clone(), input.output_index))
+    }
+
+    pub fn remove(&mut self, input: &Input) -> Option<Output> {
+        self.outputs.remove(&(input.prev_txid.clone(), input.output_index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+}
+
+/// Has `sign_sighash` sign every input's `network` sighash, attaching the signature and public key it
+/// returns to that input, then fills in the txid.
+pub fn sign_transaction<F>(tx: &mut Transaction, network: Network, mut sign_sighash: F) -> Result<(), WalletError>
+where
+    F: FnMut(&[u8]) -> Result<(String, String), WalletError>,
+{
+    // every sighash commits to the whole preimage, so none change as signatures are attached
+    let sighashes = (0..tx.inputs.len() as u32).map(|index| sighash(tx, index, network)).collect::<Result<Vec<_>, _>>()?;
+    for (input, sighash) in tx.inputs.iter_mut().zip(sighashes) {
+        let (signature, public_key) = sign_sighash(&sighash)?;
+        input.signature = Some(signature);
+        input.public_key = Some(public_key);
+    }
+    tx.txid = Some(txid(tx)?);
+    Ok(())
+}
+
+/// Checks `tx` the way a node would before accepting it: its structure, that every input
+/// spends an output in `utxos` with a public key hashing to that output's address and a valid
+/// signature, that it pays valid `network` addresses and that outputs do not exceed inputs.
+/// Returns the fee.
+pub fn verify_transaction(tx: &Transaction, utxos: &UtxoSet, network: Network) -> Result<u64, WalletError> {
+    let invalid = WalletError::InvalidTransaction;
+
+    if tx.version != VERSION {
+        return Err(invalid(format!("unsupported version {}", tx.version)));
+    }
+    if tx.inputs.is_empty() || tx.outputs.is_empty() {
+        return Err(invalid("needs at least one input and one output".to_string()));
+    }
+    let expected_txid = txid(tx).map_err(|e| invalid(e.to_string()))?;
+    if matches!(&tx.txid, Some(claimed) if *claimed != expected_txid) {
+        return Err(invalid("txid does not match its contents".to_string()));
+    }
+
+    let mut spent = HashSet::new();
+    let mut total_in = 0u64;
+    for (index, input) in tx.inputs.iter().enumerate() {
+        let input_error = |message: &str| invalid(format!("input {}: {}", index, message));
+
+        if !spent.insert((&input.prev_txid, input.output_index)) {
+            return Err(input_error("spends an output twice"));
+        }
+        let output = utxos.get(input).ok_or_else(|| input_error(&format!("{}:{} is not unspent", input.prev_txid, input.output_index)))?;
+        if input.amount != output.amount {
+            return Err(input_error(&format!("claims {} but the output holds {}", input.amount, output.amount)));
+        }
+
+        let (Some(signature), Some(public_key)) = (&input.signature, &input.public_key) else {
+            return Err(input_error("unsigned"));
+        };
+        let owner = address_from_public_key(public_key, network).map_err(|e| input_error(&e.to_string()))?;
+        if owner != output.address {
+            return Err(input_error(&format!("public key does not belong to {}", output.address)));
+        }
+        if !verify(public_key, sighash(tx, index as u32, network)?, signature).map_err(|e| input_error(&e.to_string()))? {
+            return Err(input_error("bad signature"));
+        }
+
+        total_in = total_in.checked_add(input.amount).ok_or_else(|| invalid("inputs overflow".to_string()))?;
+    }
+
+    let mut total_out = 0u64;
+    for (index, output) in tx.outputs.iter().enumerate() {
+        if output.amount == 0 {
+            return Err(invalid(format!("output {} pays nothing", index)));
+        }
+        parse_address(&output.address, network).map_err(|e| invalid(format!("output {}: {}", index, e)))?;
+        total_out = total_out.checked_add(output.amount).ok_or_else(|| invalid("outputs overflow".to_string()))?;
+    }
+
+    total_in.checked_sub(total_out).ok_or_else(|| invalid(format!("outputs of {} exceed inputs of {}", total_out, total_in)))
+}
+
+/// rejects UTXOs whose txid is not 32 bytes of hex, which no node should send
+pub fn check_outpoints(utxos: &[Utxo]) -> Result<(), WalletError> {
+    for utxo in utxos {
+        if hex_to_bytes(&utxo.prev_txid).is_err() {
+            return Err(WalletError::Codec(format!("UTXO has a malformed txid \"{}\"", utxo.prev_txid)));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use app::address::address_from_public_key;
+use app::address::is_valid_address;
+use app::address::parse_address;
+use app::config::Network;
+use app::crypto_utils::generate_key_pair;
+use app::crypto_utils::hash160;
+use app::error::WalletError;
+
+use hex::decode;
+use hex::encode;
+
+// public key of the private key 1, i.e. the generator point
+const GENERATOR_COMPRESSED: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+const GENERATOR_UNCOMPRESSED: &str = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+const GENERATOR_HASH160: &str = "751e76e8199196d454941c45d1b3a323f1433bd6";
+const GENERATOR_ADDRESS: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+const GENERATOR_TESTNET_ADDRESS: &str = "tcj1w508d6qejxtdg4y5r3zarvary0c5xw7kh6r3n4";
+const GENERATOR_REGTEST_ADDRESS: &str = "cjrt1w508d6qejxtdg4y5r3zarvary0c5xw7ktcm604";
+
+#[test]
+fn test_address_vector() {
+    assert_eq!(encode(hash160(&decode(GENERATOR_COMPRESSED).unwrap())), GENERATOR_HASH160);
+    assert_eq!(address_from_public_key(GENERATOR_COMPRESSED, Network::Mainnet).unwrap(), GENERATOR_ADDRESS);
+    assert_eq!(encode(parse_address(GENERATOR_ADDRESS, Network::Mainnet).unwrap()), GENERATOR_HASH160);
+}
+
+#[test]
+fn test_address_uses_compressed_key() {
+    assert_eq!(address_from_public_key(GENERATOR_UNCOMPRESSED, Network::Mainnet).unwrap(), GENERATOR_ADDRESS);
+}
+
+#[test]
+fn test_generated_key_address_round_trip() {
+    let (_, public_key_hex) = generate_key_pair();
+    let address = address_from_public_key(&public_key_hex, Network::Mainnet).unwrap();
+
+    assert!(address.starts_with("cj1"));
+    assert_eq!(parse_address(&address, Network::Mainnet).unwrap(), hash160(&decode(&public_key_hex).unwrap()));
+    assert_eq!(parse_address(&address.to_uppercase(), Network::Mainnet).unwrap(), parse_address(&address, Network::Mainnet).unwrap());
+}
+
+#[test]
+fn test_parse_address_rejects_bad_checksum() {
+    let mut address = GENERATOR_ADDRESS.to_string();
+    address.replace_range(10..11, "q");
+
+    assert!(matches!(parse_address(&address, Network::Mainnet), Err(WalletError::InvalidAddress(_))));
+}
+
+#[test]
+fn test_parse_address_rejects_bech32_checksum() {
+    // same payload with the original bech32 constant
+    assert!(!is_valid_address("cj1w508d6qejxtdg4y5r3zarvary0c5xw7kake5mp", Network::Mainnet));
+}
+
+#[test]
+fn test_parse_address_rejects_wrong_prefix() {
+    assert!(matches!(parse_address("bc1w508d6qejxtdg4y5r3zarvary0c5xw7k8zcwmq", Network::Mainnet), Err(WalletError::InvalidAddress(_))));
+}
+
+#[test]
+fn test_parse_address_rejects_wrong_length() {
+    // valid bech32m but only 19 bytes
+    assert!(matches!(parse_address("cj1w508d6qejxtdg4y5r3zarvary0c5xwclw9sd0", Network::Mainnet), Err(WalletError::InvalidAddress(_))));
+}
+
+#[test]
+fn test_parse_address_rejects_garbage() {
+    for address in ["", "cj1", "hello", "cj1qf4v2s8g3nxt4rghjw9vqps9lz5u6dhv95z8j2c", "Cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r"] {
+        assert!(!is_valid_address(address, Network::Mainnet), "{:?} should be rejected", address);
+    }
+}
+
+#[test]
+fn test_address_from_invalid_public_key() {
+    assert!(address_from_public_key("02deadbeef", Network::Mainnet).is_err());
+    assert!(address_from_public_key("zz", Network::Mainnet).is_err());
+}
+
+#[test]
+fn test_address_prefix_follows_network() {
+    assert_eq!(address_from_public_key(GENERATOR_COMPRESSED, Network::Testnet).unwrap(), GENERATOR_TESTNET_ADDRESS);
+    assert_eq!(address_from_public_key(GENERATOR_COMPRESSED, Network::Regtest).unwrap(), GENERATOR_REGTEST_ADDRESS);
+    assert_eq!(encode(parse_address(GENERATOR_TESTNET_ADDRESS, Network::Testnet).unwrap()), GENERATOR_HASH160);
+}
+
+#[test]
+fn test_parse_address_rejects_other_network() {
+    for (address, network) in [
+        (GENERATOR_TESTNET_ADDRESS, Network::Mainnet),
+        (GENERATOR_REGTEST_ADDRESS, Network::Mainnet),
+        (GENERATOR_ADDRESS, Network::Testnet),
+        (GENERATOR_ADDRESS, Network::Regtest),
+    ] {
+        let error = parse_address(address, network).unwrap_err();
+        assert!(error.to_string().contains("cannot be used on"), "{}", error);
+    }
+}
@@ -0,0 +1,57 @@
+mod support;
+
+use app::crypto_utils::Output;
+use app::error::WalletError;
+use app::transaction::generate_outputs;
+use app::transaction::CoinSelection;
+use app::transaction::TransactionBuilder;
+
+use support::fixtures::utxos;
+
+const OWN_ADDRESS: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+const OTHER_ADDRESS: &str = "cj1recipient";
+
+#[test]
+fn test_generate_outputs_with_change() {
+    let outputs = generate_outputs(&utxos(&[60, 50]), 80, OTHER_ADDRESS, 5, OWN_ADDRESS).unwrap();
+
+    assert_eq!(
+        outputs,
+        vec![
+            Output { amount: 80, address: OTHER_ADDRESS.to_string() },
+            Output { amount: 25, address: OWN_ADDRESS.to_string() },
+        ]
+    );
+}
+
+#[test]
+fn test_generate_outputs_exact_amount_has_no_change() {
+    let outputs = generate_outputs(&utxos(&[60, 25]), 80, OTHER_ADDRESS, 5, OWN_ADDRESS).unwrap();
+    assert_eq!(outputs.len(), 1);
+}
+
+#[test]
+fn test_generate_outputs_insufficient_funds() {
+    let result = generate_outputs(&utxos(&[10, 20]), 80, OTHER_ADDRESS, 5, OWN_ADDRESS);
+    assert!(matches!(result, Err(WalletError::InsufficientFunds { needed: 85, available: 30 })));
+}
+
+#[test]
+fn test_build_stops_selecting_once_covered() {
+    let builder = TransactionBuilder::new(CoinSelection::OldestFirst).with_dust_threshold(0);
+    let tx = builder.build(&utxos(&[50, 40, 30, 20]), &[], 70, OTHER_ADDRESS, 10, OWN_ADDRESS).unwrap();
+
+    assert_eq!(tx.inputs, utxos(&[50, 40]));
+    assert_eq!(tx.outputs.iter().map(|o| o.amount).sum::<u64>() + 10, 90);
+    assert!(tx.inputs.iter().all(|input| input.signature.is_none()));
+    assert_eq!(tx.txid, None);
+}
+
+#[test]
+fn test_build_rejects_overflow() {
+    let result = TransactionBuilder::new(CoinSelection::OldestFirst).build(&utxos(&[50]), &[], u64::MAX, OTHER_ADDRESS, 1, OWN_ADDRESS);
+    assert!(matches!(result, Err(WalletError::InvalidAmount(_))));
+
+    let result = generate_outputs(&utxos(&[u64::MAX, 1]), 10, OTHER_ADDRESS, 1, OWN_ADDRESS);
+    assert!(matches!(result, Err(WalletError::InvalidAmount(_))));
+}
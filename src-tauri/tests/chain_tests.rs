@@ -0,0 +1,435 @@
+mod support;
+
+use std::env::temp_dir;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::Duration;
+use std::time::Instant;
+
+use app::api::NodeClient;
+use app::chain::header_store_file;
+use app::chain::sync_headers;
+use app::chain::ChainState;
+use app::chain::ChainUpdate;
+use app::chain::HeaderChain;
+use app::chain::SyncProgress;
+use app::codec::block_hash;
+use app::codec::Codec;
+use app::config::Network;
+use app::consensus::median_time_past;
+use app::consensus::MAX_FUTURE_BLOCK_TIME;
+use app::crypto_utils::BlockHeader;
+use app::error::WalletError;
+use app::miner::coinbase;
+use app::miner::grind;
+use app::miner::MiningControl;
+use app::miner::BLOCK_REWARD;
+use app::miner::GENESIS_PREV_HASH;
+use app::pow::U256;
+
+use support::mock_node::client_for;
+use support::mock_node::MockNode;
+
+const T0: u64 = 1_700_000_000;
+// well after every test header, so none is in the future
+const NOW: u64 = 1_800_000_000;
+// the regtest pow limit, which about every other hash meets
+const REGTEST_NBITS: u32 = 0x207fffff;
+const MINER: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+
+fn mine(prev: Option<&BlockHeader>, timestamp: u64, nbits: u32, branch: u8) -> BlockHeader {
+    let header = BlockHeader {
+        version: 1,
+        prev_hash: prev.map_or(GENESIS_PREV_HASH.to_string(), |prev| block_hash(prev).unwrap()),
+        merkle_root: format!("{:02x}", branch).repeat(32),
+        timestamp,
+        nbits,
+        nonce: 0,
+        hash: None,
+    };
+    grind(&header, 1, &MiningControl::new(), Instant::now() + Duration::from_secs(60)).unwrap().unwrap()
+}
+
+// `count` headers after `prev`, ten minutes apart; `branch` keeps forks apart
+fn extend(prev: Option<&BlockHeader>, count: usize, branch: u8) -> Vec<BlockHeader> {
+    let mut headers: Vec<BlockHeader> = Vec::new();
+    for _ in 0..count {
+        let last = headers.last().or(prev);
+        let timestamp = last.map_or(T0, |last| last.timestamp + 600);
+        headers.push(mine(last, timestamp, REGTEST_NBITS, branch));
+    }
+    headers
+}
+
+fn hashes(headers: &[BlockHeader]) -> Vec<String> {
+    headers.iter().map(|header| block_hash(header).unwrap()).collect()
+}
+
+// mines `count` blocks on `node`; `branch` goes in as the extra nonce so forks differ
+fn mine_blocks(node: &MockNode, count: u64, branch: u64) {
+    for _ in 0..count {
+        let height = node.blocks().len() as u64;
+        node.mine(vec![coinbase(MINER, BLOCK_REWARD, height, branch).unwrap()]);
+    }
+}
+
+fn node_hashes(node: &MockNode) -> Vec<String> {
+    node.blocks().iter().map(|block| block.header.hash.clone().unwrap()).collect()
+}
+
+#[test]
+fn test_connect_extends_the_chain() {
+    let mut chain = HeaderChain::new(Network::Regtest);
+    let headers = extend(None, 5, 0);
+
+    assert_eq!(chain.connect(0, &headers[..3], NOW).unwrap(), ChainUpdate::Extended { added: 3 });
+    assert_eq!(chain.connect(3, &headers[3..], NOW).unwrap(), ChainUpdate::Extended { added: 2 });
+
+    assert_eq!(chain.height(), 5);
+    assert_eq!(hashes(chain.headers()), hashes(&headers));
+    assert_eq!(chain.hash_at(4), Some(block_hash(&headers[4]).unwrap().as_str()));
+    // every regtest block proves 2 hashes of work
+    assert_eq!(chain.chain_work(), U256::from(10));
+    assert_eq!(chain.chain_tip().height, 5);
+    assert_eq!(chain.chain_tip().hash, Some(block_hash(&headers[4]).unwrap()));
+}
+
+#[test]
+fn test_connect_skips_known_headers() {
+    let mut chain = HeaderChain::new(Network::Regtest);
+    let headers = extend(None, 4, 0);
+    chain.connect(0, &headers[..3], NOW).unwrap();
+
+    assert_eq!(chain.connect(0, &headers[..3], NOW).unwrap(), ChainUpdate::Unchanged);
+    assert_eq!(chain.connect(1, &headers[1..], NOW).unwrap(), ChainUpdate::Extended { added: 1 });
+    assert_eq!(chain.height(), 4);
+}
+
+#[test]
+fn test_connect_rejects_invalid_headers() {
+    let mut chain = HeaderChain::new(Network::Regtest);
+    let headers = extend(None, 12, 0);
+    chain.connect(0, &headers[..11], NOW).unwrap();
+    let tip = &headers[10];
+
+    let gap = chain.connect(12, &headers[11..], NOW).unwrap_err();
+    assert!(gap.to_string().contains("gap"), "{}", gap);
+
+    let unlinked = mine(Some(&headers[9]), tip.timestamp + 600, REGTEST_NBITS, 0);
+    let error = chain.connect(11, &[unlinked], NOW).unwrap_err();
+    assert!(error.to_string().contains("does not follow"), "{}", error);
+
+    let mut unmined = headers[11].clone();
+    unmined.hash = None;
+    unmined.nonce += 1;
+    unmined.nbits = 0x1d00ffff;
+    assert!(matches!(chain.connect(11, &[unmined], NOW), Err(WalletError::InvalidBlock(_))));
+
+    // about one hash in 256 meets 0x2000ffff, so this one is mined fine but too hard for the rules
+    let wrong_nbits = mine(Some(tip), tip.timestamp + 600, 0x2000ffff, 0);
+    let error = chain.connect(11, &[wrong_nbits], NOW).unwrap_err();
+    assert!(error.to_string().contains("the rules require"), "{}", error);
+
+    let median = median_time_past(&headers[..11]).unwrap();
+    let too_old = mine(Some(tip), median, REGTEST_NBITS, 0);
+    let error = chain.connect(11, &[too_old], NOW).unwrap_err();
+    assert!(error.to_string().contains("median time past"), "{}", error);
+    // later than the median is enough, even if earlier than the tip
+    chain.connect(11, &[mine(Some(tip), median + 1, REGTEST_NBITS, 0)], NOW).unwrap();
+
+    let mut chain = HeaderChain::new(Network::Regtest);
+    chain.connect(0, &headers[..11], NOW).unwrap();
+    let future = mine(Some(tip), NOW + MAX_FUTURE_BLOCK_TIME + 1, REGTEST_NBITS, 0);
+    let error = chain.connect(11, &[future], NOW).unwrap_err();
+    assert!(error.to_string().contains("future"), "{}", error);
+    assert_eq!(chain.height(), 11);
+}
+
+#[test]
+fn test_first_header_must_start_the_chain() {
+    let headers = extend(None, 2, 0);
+    let mut chain = HeaderChain::new(Network::Regtest);
+
+    assert!(chain.connect(0, &headers[1..], NOW).is_err());
+    assert_eq!(chain.height(), 0);
+}
+
+#[test]
+fn test_invalid_header_keeps_the_ones_before_it() {
+    let mut chain = HeaderChain::new(Network::Regtest);
+    let main = extend(None, 5, 0);
+    chain.connect(0, &main[..2], NOW).unwrap();
+
+    let mut batch = main[2..].to_vec();
+    batch[2].timestamp = 0;
+    assert!(chain.connect(2, &batch, NOW).is_err());
+    assert_eq!(hashes(chain.headers()), hashes(&main[..4]));
+
+    // a branch cut short is still only taken if what is left is heavier
+    let mut branch = extend(Some(&main[1]), 4, 1);
+    branch[2].timestamp = 0;
+    assert!(chain.connect(2, &branch, NOW).is_err());
+    assert_eq!(hashes(chain.headers()), hashes(&main[..4]));
+    branch = extend(Some(&main[1]), 4, 1);
+    branch[3].timestamp = 0;
+    assert!(chain.connect(2, &branch, NOW).is_err());
+    assert_eq!(hashes(&chain.headers()[2..]), hashes(&branch[..3]));
+}
+
+#[test]
+fn test_heavier_branch_reorganizes() {
+    let mut chain = HeaderChain::new(Network::Regtest);
+    let main = extend(None, 5, 0);
+    chain.connect(0, &main, NOW).unwrap();
+
+    let branch = extend(Some(&main[2]), 3, 1);
+    assert_eq!(
+        chain.connect(3, &branch, NOW).unwrap(),
+        ChainUpdate::Reorganized {
+            fork_height: 3,
+            disconnected: 2,
+            added: 3,
+        }
+    );
+    assert_eq!(hashes(chain.headers()), hashes(&[&main[..3], &branch[..]].concat()));
+    assert_eq!(chain.chain_work(), U256::from(12));
+}
+
+#[test]
+fn test_lighter_or_equal_branch_is_ignored() {
+    let mut chain = HeaderChain::new(Network::Regtest);
+    let main = extend(None, 5, 0);
+    chain.connect(0, &main, NOW).unwrap();
+
+    // the same work as the chain seen first is not enough
+    assert_eq!(chain.connect(3, &extend(Some(&main[2]), 2, 1), NOW).unwrap(), ChainUpdate::Unchanged);
+    assert_eq!(chain.connect(1, &extend(Some(&main[0]), 2, 2), NOW).unwrap(), ChainUpdate::Unchanged);
+    assert_eq!(hashes(chain.headers()), hashes(&main));
+
+    // a branch starting with the chain's own headers forks where they stop matching
+    let branch = [&main[2..4], &extend(Some(&main[3]), 2, 3)[..]].concat();
+    assert_eq!(
+        chain.connect(2, &branch, NOW).unwrap(),
+        ChainUpdate::Reorganized {
+            fork_height: 4,
+            disconnected: 1,
+            added: 2,
+        }
+    );
+}
+
+#[test]
+fn test_store_round_trip() {
+    let path = temp_dir().join(format!("cj-headers-round-trip-{}", std::process::id())).join(header_store_file(Network::Regtest));
+    let mut chain = HeaderChain::load(&path, Network::Regtest).unwrap();
+    assert_eq!(chain.height(), 0);
+
+    chain.connect(0, &extend(None, 4, 0), NOW).unwrap();
+    chain.save().unwrap();
+    let loaded = HeaderChain::load(&path, Network::Regtest).unwrap();
+    assert_eq!(loaded.headers(), chain.headers());
+    assert_eq!(loaded.chain_work(), chain.chain_work());
+
+    // a tampered file is checked like headers from a node
+    let mut tampered = chain.headers().to_vec();
+    tampered[2].timestamp += 1;
+    fs::write(&path, tampered.iter().flat_map(|header| header.encode().unwrap()).collect::<Vec<u8>>()).unwrap();
+    let result = HeaderChain::load(&path, Network::Regtest);
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    assert!(matches!(result, Err(WalletError::InvalidBlock(_))));
+}
+
+#[test]
+fn test_store_keeps_up_with_extensions_and_reorganizations() {
+    let path = temp_dir().join(format!("cj-headers-suffix-{}", std::process::id())).join(header_store_file(Network::Regtest));
+    let reloaded = |chain: &HeaderChain| hashes(HeaderChain::load(&path, Network::Regtest).unwrap().headers()) == hashes(chain.headers());
+
+    let mut chain = HeaderChain::at(&path, Network::Regtest);
+    let main = extend(None, 5, 0);
+    chain.connect(0, &main[..3], NOW).unwrap();
+    chain.save().unwrap();
+    chain.connect(3, &main[3..], NOW).unwrap();
+    chain.save().unwrap();
+    assert!(reloaded(&chain));
+    assert_eq!(fs::metadata(&path).unwrap().len(), 5 * 88);
+
+    // a branch that fails its checks leaves the chain, and so the file, as it was
+    let mut invalid = extend(Some(&main[2]), 3, 1);
+    invalid[1].timestamp = 0;
+    assert!(chain.connect(3, &invalid, NOW).is_err());
+    assert_eq!(hashes(chain.headers()), hashes(&main));
+
+    let branch = extend(Some(&main[2]), 3, 2);
+    chain.connect(3, &branch, NOW).unwrap();
+    chain.save().unwrap();
+    assert!(reloaded(&chain));
+    assert_eq!(fs::metadata(&path).unwrap().len(), 6 * 88);
+
+    // an interrupted save leaves part of a record behind, which loading drops
+    OpenOptions::new().append(true).open(&path).unwrap().write_all(&[0u8; 40]).unwrap();
+    let mut loaded = HeaderChain::load(&path, Network::Regtest).unwrap();
+    assert_eq!(loaded.height(), 6);
+    loaded.connect(6, &extend(branch.last(), 1, 2), NOW).unwrap();
+    loaded.save().unwrap();
+    let result = HeaderChain::load(&path, Network::Regtest).map(|chain| chain.height());
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    assert_eq!(result.unwrap(), 7);
+}
+
+#[tokio::test]
+async fn test_sync_downloads_the_chain() {
+    let node = MockNode::spawn_on(Network::Regtest).await;
+    mine_blocks(&node, 5, 0);
+    let mut chain = HeaderChain::new(Network::Regtest);
+    let mut progress = Vec::new();
+
+    let update = sync_headers(&client_for(std::slice::from_ref(&node)), &mut chain, |p| progress.push(p)).await.unwrap();
+
+    assert_eq!(update, ChainUpdate::Extended { added: 5 });
+    assert_eq!(hashes(chain.headers()), node_hashes(&node));
+    assert_eq!(progress.last(), Some(&SyncProgress { height: 5, network_height: 5, error: None }));
+
+    // nothing new: one look at the tip and one empty batch
+    assert_eq!(sync_headers(&client_for(std::slice::from_ref(&node)), &mut chain, |_| {}).await.unwrap(), ChainUpdate::Unchanged);
+    mine_blocks(&node, 2, 1);
+    assert_eq!(sync_headers(&client_for(std::slice::from_ref(&node)), &mut chain, |_| {}).await.unwrap(), ChainUpdate::Extended { added: 2 });
+    assert_eq!(hashes(chain.headers()), node_hashes(&node));
+}
+
+#[tokio::test]
+async fn test_sync_follows_a_reorg() {
+    let node = MockNode::spawn_on(Network::Regtest).await;
+    mine_blocks(&node, 6, 0);
+    let mut chain = HeaderChain::new(Network::Regtest);
+    sync_headers(&client_for(std::slice::from_ref(&node)), &mut chain, |_| {}).await.unwrap();
+
+    node.rewind(3);
+    mine_blocks(&node, 4, 1);
+    let update = sync_headers(&client_for(std::slice::from_ref(&node)), &mut chain, |_| {}).await.unwrap();
+
+    assert_eq!(
+        update,
+        ChainUpdate::Reorganized {
+            fork_height: 3,
+            disconnected: 3,
+            added: 4,
+        }
+    );
+    assert_eq!(hashes(chain.headers()), node_hashes(&node));
+}
+
+#[tokio::test]
+async fn test_sync_keeps_the_heavier_chain() {
+    let node = MockNode::spawn_on(Network::Regtest).await;
+    mine_blocks(&node, 5, 0);
+    let mut chain = HeaderChain::new(Network::Regtest);
+    sync_headers(&client_for(std::slice::from_ref(&node)), &mut chain, |_| {}).await.unwrap();
+    let synced = hashes(chain.headers());
+
+    node.rewind(2);
+    mine_blocks(&node, 1, 1);
+
+    assert_eq!(sync_headers(&client_for(std::slice::from_ref(&node)), &mut chain, |_| {}).await.unwrap(), ChainUpdate::Unchanged);
+    assert_eq!(hashes(chain.headers()), synced);
+}
+
+#[tokio::test]
+async fn test_sync_rejects_headers_breaking_the_rules() {
+    // the mock mines at the regtest limit, far easier than mainnet allows
+    let node = MockNode::spawn().await;
+    node.mine(Vec::new());
+    let mut chain = HeaderChain::new(Network::Mainnet);
+    let client = NodeClient::with_nodes(Network::Mainnet, vec![node.node()]);
+
+    let result = sync_headers(&client, &mut chain, |_| {}).await;
+
+    assert!(matches!(result, Err(WalletError::NoQuorum { unreachable: 1, .. })));
+    assert_eq!(chain.height(), 0);
+    assert_eq!(client.peer_records()[0].malformed, 1);
+}
+
+#[tokio::test]
+async fn test_sync_keeps_the_headers_before_a_bad_one() {
+    // each header is mined and links up, so only the chain can tell the last one is too old
+    let mut served = extend(None, 4, 0);
+    served.push(mine(served.last(), T0, REGTEST_NBITS, 0));
+    let nodes = [MockNode::spawn_on(Network::Regtest).await, MockNode::spawn_on(Network::Regtest).await];
+    for node in &nodes {
+        node.respond("/blocks/count", 5).respond("/headers/0", served.clone());
+    }
+    let client = client_for(&nodes);
+    let mut chain = HeaderChain::new(Network::Regtest);
+
+    let result = sync_headers(&client, &mut chain, |_| {}).await;
+
+    assert!(matches!(result, Err(WalletError::InvalidBlock(_))));
+    assert_eq!(hashes(chain.headers()), hashes(&served[..4]));
+    assert!(client.peer_records().iter().all(|record| record.malformed == 1));
+}
+
+#[tokio::test]
+async fn test_sync_strikes_peers_serving_the_wrong_difficulty() {
+    let honest = MockNode::spawn_on(Network::Regtest).await;
+    mine_blocks(&honest, 3, 0);
+    // mined, linked and harder than needed, but regtest never moves off its pow limit
+    let liar = MockNode::spawn_on(Network::Regtest).await;
+    let genesis = mine(None, T0, REGTEST_NBITS, 1);
+    let wrong = mine(Some(&genesis), T0 + 600, 0x2000ffff, 1);
+    liar.respond("/blocks/count", 3).respond("/headers/0", vec![genesis, wrong]);
+    let nodes = [honest, liar];
+    let client = client_for(&nodes);
+    let mut chain = HeaderChain::new(Network::Regtest);
+
+    sync_headers(&client, &mut chain, |_| {}).await.unwrap();
+
+    assert_eq!(chain.height(), 3);
+    let malformed = |node: &MockNode| client.peer_records().into_iter().find(|record| record.node == node.node()).unwrap().malformed;
+    assert_eq!((malformed(&nodes[0]), malformed(&nodes[1])), (0, 1));
+}
+
+#[tokio::test]
+async fn test_unreadable_headers_are_reported_by_the_next_sync() {
+    let dir = temp_dir().join(format!("cj-headers-unreadable-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(header_store_file(Network::Regtest)), [0xffu8; 88]).unwrap();
+    let node = MockNode::spawn_on(Network::Regtest).await;
+    mine_blocks(&node, 2, 0);
+
+    let state = ChainState::new(Network::Regtest, Some(dir.clone()));
+    let mut progress = Vec::new();
+    state.sync(&client_for(std::slice::from_ref(&node)), |p| progress.push(p)).await.unwrap();
+    assert!(progress[0].error.as_ref().unwrap().contains("syncing the headers from scratch"));
+    assert_eq!(progress.last().unwrap().error, None);
+    assert_eq!(state.tip().height, 2);
+
+    // reported once, not on every sync
+    let mut progress = Vec::new();
+    state.sync(&client_for(std::slice::from_ref(&node)), |p| progress.push(p)).await.unwrap();
+    assert!(progress.iter().all(|p| p.error.is_none()));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_chain_state_persists_each_network() {
+    let dir = temp_dir().join(format!("cj-headers-state-{}", std::process::id()));
+    let regtest = MockNode::spawn_on(Network::Regtest).await;
+    mine_blocks(&regtest, 3, 0);
+
+    let state = ChainState::new(Network::Regtest, Some(dir.clone()));
+    state.sync(&client_for(std::slice::from_ref(&regtest)), |_| {}).await.unwrap();
+    assert_eq!(state.tip().height, 3);
+    assert!(dir.join("headers-regtest.dat").exists());
+
+    // next start: the headers are loaded rather than downloaded again
+    let restarted = ChainState::new(Network::Regtest, Some(dir.clone()));
+    assert_eq!(restarted.tip(), state.tip());
+
+    // switching networks switches chains
+    let mainnet = MockNode::spawn().await;
+    restarted.sync(&NodeClient::with_nodes(Network::Mainnet, vec![mainnet.node()]), |_| {}).await.unwrap();
+    assert_eq!((restarted.tip().network, restarted.tip().height), (Network::Mainnet, 0));
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,232 @@
+use app::codec::block_hash;
+use app::codec::sighash;
+use app::codec::sighash_preimage;
+use app::codec::txid;
+use app::codec::write_compact_size;
+use app::codec::Codec;
+use app::codec::Reader;
+use app::config::Network;
+use app::crypto_utils::generate_key_pair;
+use app::crypto_utils::sign;
+use app::crypto_utils::verify;
+use app::crypto_utils::BlockHeader;
+use app::crypto_utils::Input;
+use app::crypto_utils::Output;
+use app::crypto_utils::Transaction;
+use app::error::WalletError;
+
+use hex::decode;
+use hex::encode;
+
+const ADDRESS: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+
+// Golden vectors: changing any of these changes every txid and signature
+const TX_PREIMAGE: &str = "010000000211111111111111111111111111111111111111111111111111111111111111110000000050c3000000000000222222222222222222222222222222222222222222222222222222222222222201000000a86100000000000002701101000000000029636a3177353038643671656a7874646734793572337a6172766172793063357877376b673266633772a00f00000000000029636a3177353038643671656a7874646734793572337a6172766172793063357877376b673266633772";
+const TX_SIGNED: &str = "010000000211111111111111111111111111111111111111111111111111111111111111110000000050c30000000000000104deadbeef0121021111111111111111111111111111111111111111111111111111111111111111222222222222222222222222222222222222222222222222222222222222222201000000a8610000000000000104deadbeef012102111111111111111111111111111111111111111111111111111111111111111102701101000000000029636a3177353038643671656a7874646734793572337a6172766172793063357877376b673266633772a00f00000000000029636a3177353038643671656a7874646734793572337a6172766172793063357877376b673266633772";
+const TXID: &str = "08c3b4a1329a04ef2545cac48e843ff30d5180f8e4122968ef9c4e462bb20d25";
+const INPUT_0_SIGHASH: &str = "2670b4c9180ec578f3aff17838d3be664684a863371493ea2daadb13829d74e0";
+const HEADER: &str = "010000000000000000000000000000000000000000000000000000000000000000000000b767a3a12f5f8bb1949d163c51f9a42e6bda8dcd02d50353717f73d4338b1bf000f1536500000000ffff001d2a00000000000000";
+const HEADER_HASH: &str = "d2c67e71cc123af66bfcbd8633866b7984f795522f67c7ef0773d41ed35f2966";
+
+fn sample_tx() -> Transaction {
+    Transaction {
+        version: 1,
+        inputs: vec![Input::unsigned("11".repeat(32), 0, 50_000), Input::unsigned("22".repeat(32), 1, 25_000)],
+        outputs: vec![
+            Output { amount: 70_000, address: ADDRESS.to_string() },
+            Output { amount: 4_000, address: ADDRESS.to_string() },
+        ],
+        txid: None,
+    }
+}
+
+fn signed_tx() -> Transaction {
+    let mut tx = sample_tx();
+    for input in &mut tx.inputs {
+        input.signature = Some("deadbeef".to_string());
+        input.public_key = Some(format!("02{}", "11".repeat(32)));
+    }
+    tx
+}
+
+fn sample_header() -> BlockHeader {
+    BlockHeader {
+        version: 1,
+        prev_hash: "00".repeat(32),
+        merkle_root: "b767a3a12f5f8bb1949d163c51f9a42e6bda8dcd02d50353717f73d4338b1bf0".to_string(),
+        timestamp: 1_700_000_000,
+        nbits: 0x1d00ffff,
+        nonce: 42,
+        hash: None,
+    }
+}
+
+#[test]
+fn test_transaction_golden_vector() {
+    assert_eq!(encode(sighash_preimage(&sample_tx()).unwrap()), TX_PREIMAGE);
+    assert_eq!(encode(signed_tx().encode().unwrap()), TX_SIGNED);
+    assert_eq!(txid(&sample_tx()).unwrap(), TXID);
+    assert_eq!(encode(sighash(&sample_tx(), 0, Network::Mainnet).unwrap()), INPUT_0_SIGHASH);
+}
+
+#[test]
+fn test_block_header_golden_vector() {
+    let encoded = sample_header().encode().unwrap();
+
+    assert_eq!(encoded.len(), 88);
+    assert_eq!(encode(encoded), HEADER);
+    assert_eq!(block_hash(&sample_header()).unwrap(), HEADER_HASH);
+}
+
+#[test]
+fn test_signature_fields_do_not_change_sighash() {
+    let mut tx = signed_tx();
+    tx.txid = Some("ff".repeat(32));
+
+    assert_eq!(sighash(&tx, 0, Network::Mainnet).unwrap(), sighash(&sample_tx(), 0, Network::Mainnet).unwrap());
+    assert_eq!(txid(&tx).unwrap(), TXID);
+}
+
+#[test]
+fn test_sighash_commits_to_input_index() {
+    let tx = sample_tx();
+    assert_ne!(sighash(&tx, 0, Network::Mainnet).unwrap(), sighash(&tx, 1, Network::Mainnet).unwrap());
+}
+
+#[test]
+fn test_sighash_commits_to_network() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let (signature, _) = sign(&private_key, sighash(&sample_tx(), 0, Network::Mainnet).unwrap()).unwrap();
+
+    assert!(verify(&public_key_hex, sighash(&sample_tx(), 0, Network::Mainnet).unwrap(), &signature).unwrap());
+    assert!(!verify(&public_key_hex, sighash(&sample_tx(), 0, Network::Testnet).unwrap(), &signature).unwrap());
+    assert!(!verify(&public_key_hex, sighash(&sample_tx(), 0, Network::Regtest).unwrap(), &signature).unwrap());
+}
+
+#[test]
+fn test_sighash_commits_to_outputs_and_outpoints() {
+    let mut tx = sample_tx();
+    tx.outputs[1].amount += 1;
+    assert_ne!(txid(&tx).unwrap(), TXID);
+
+    let mut tx = sample_tx();
+    tx.inputs[1].output_index = 0;
+    assert_ne!(txid(&tx).unwrap(), TXID);
+}
+
+#[test]
+fn test_transaction_round_trip() {
+    let decoded = Transaction::decode(&decode(TX_SIGNED).unwrap()).unwrap();
+
+    let mut expected = signed_tx();
+    expected.txid = Some(TXID.to_string());
+    assert_eq!(decoded, expected);
+    assert_eq!(encode(decoded.encode().unwrap()), TX_SIGNED);
+}
+
+#[test]
+fn test_unsigned_transaction_round_trip() {
+    let encoded = sample_tx().encode().unwrap();
+    let decoded = Transaction::decode(&encoded).unwrap();
+
+    assert_eq!(decoded.txid.as_deref(), Some(TXID));
+    assert_eq!(Transaction { txid: None, ..decoded }, sample_tx());
+}
+
+#[test]
+fn test_block_header_round_trip() {
+    let decoded = BlockHeader::decode(&decode(HEADER).unwrap()).unwrap();
+
+    assert_eq!(decoded.hash.as_deref(), Some(HEADER_HASH));
+    assert_eq!(BlockHeader { hash: None, ..decoded }, sample_header());
+}
+
+#[test]
+fn test_input_and_output_round_trip() {
+    let input = Input::unsigned("ab".repeat(32), u32::MAX, u64::MAX);
+    let signed = signed_tx().inputs.remove(0);
+    let output = Output { amount: 1, address: ADDRESS.to_string() };
+
+    assert_eq!(Input::decode(&input.encode().unwrap()).unwrap(), input);
+    assert_eq!(Input::decode(&signed.encode().unwrap()).unwrap(), signed);
+    assert_eq!(Output::decode(&output.encode().unwrap()).unwrap(), output);
+}
+
+#[test]
+fn test_sign_and_verify_sighash() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let (signature, _) = sign(&private_key, sighash(&sample_tx(), 0, Network::Mainnet).unwrap()).unwrap();
+
+    let mut tampered = sample_tx();
+    tampered.outputs[0].address = "cj1somebodyelse".to_string();
+
+    assert!(verify(&public_key_hex, sighash(&sample_tx(), 0, Network::Mainnet).unwrap(), &signature).unwrap());
+    assert!(!verify(&public_key_hex, sighash(&tampered, 0, Network::Mainnet).unwrap(), &signature).unwrap());
+}
+
+#[test]
+fn test_compact_size_boundaries() {
+    for (value, expected) in [
+        (0u64, "00"),
+        (0xfc, "fc"),
+        (0xfd, "fdfd00"),
+        (0xffff, "fdffff"),
+        (0x1_0000, "fe00000100"),
+        (0xffff_ffff, "feffffffff"),
+        (0x1_0000_0000, "ff0000000001000000"),
+    ] {
+        let mut out = Vec::new();
+        write_compact_size(&mut out, value);
+        assert_eq!(encode(&out), expected);
+        assert_eq!(Reader::new(&out).read_compact_size().unwrap(), value);
+    }
+}
+
+#[test]
+fn test_decode_rejects_non_canonical_length() {
+    // 2 inputs encoded as fd0200 instead of 02
+    let mut bytes = sample_tx().encode().unwrap();
+    bytes.splice(4..5, [0xfd, 0x02, 0x00]);
+
+    assert!(matches!(Transaction::decode(&bytes), Err(WalletError::Codec(_))));
+}
+
+#[test]
+fn test_decode_rejects_truncated_and_trailing_data() {
+    let bytes = decode(TX_SIGNED).unwrap();
+
+    assert!(matches!(Transaction::decode(&bytes[..bytes.len() - 1]), Err(WalletError::Codec(_))));
+    assert!(matches!(Transaction::decode(&[bytes.as_slice(), &[0]].concat()), Err(WalletError::Codec(_))));
+    assert!(matches!(BlockHeader::decode(&decode(HEADER).unwrap()[..87]), Err(WalletError::Codec(_))));
+}
+
+#[test]
+fn test_decode_rejects_oversized_length_prefix() {
+    // claims 2^32 inputs in a 9-byte buffer
+    let bytes = decode("01000000fe00000000ff").unwrap();
+    assert!(matches!(Transaction::decode(&bytes), Err(WalletError::Codec(_))));
+}
+
+#[test]
+fn test_decode_rejects_bad_presence_flag() {
+    // the first input's signature flag follows its 44-byte outpoint and amount
+    let mut bytes = sample_tx().encode().unwrap();
+    bytes[5 + 44] = 2;
+
+    assert!(matches!(Transaction::decode(&bytes), Err(WalletError::Codec(_))));
+}
+
+#[test]
+fn test_encode_rejects_bad_hex() {
+    let mut header = sample_header();
+    header.prev_hash = "abcd".to_string();
+    assert!(header.encode().is_err());
+
+    let mut tx = sample_tx();
+    tx.inputs[0].signature = Some("xyz".to_string());
+    assert!(matches!(tx.encode(), Err(WalletError::Hex(_))));
+
+    let mut tx = sample_tx();
+    tx.inputs[0].prev_txid = "abcd".to_string();
+    assert!(matches!(txid(&tx), Err(WalletError::KeyLength { expected: 32, actual: 2 })));
+}
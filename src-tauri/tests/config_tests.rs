@@ -0,0 +1,95 @@
+use app::config::Network;
+use app::config::NetworkConfig;
+use app::error::WalletError;
+
+use std::env::temp_dir;
+use std::fs;
+
+#[test]
+fn test_missing_config_uses_defaults() {
+    let path = temp_dir().join("cj-network-missing.json");
+    let _ = fs::remove_file(&path);
+
+    let config = NetworkConfig::load(&path).unwrap();
+    assert_eq!(config, NetworkConfig::default());
+    assert_eq!(config.selected, Network::Mainnet);
+    assert_eq!(config.seed_nodes(Network::Mainnet), vec!["192.168.1.117:8133"]);
+}
+
+#[test]
+fn test_config_round_trip() {
+    let path = temp_dir().join("cj-network-round-trip").join("network.json");
+
+    let mut config = NetworkConfig {
+        selected: Network::Testnet,
+        ..Default::default()
+    };
+    config.set_seed_nodes(Network::Testnet, vec!["10.0.0.5:18133".to_string(), " seed.example.org:18133 ".to_string()]).unwrap();
+    config.save(&path).unwrap();
+
+    let loaded = NetworkConfig::load(&path).unwrap();
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+    assert_eq!(loaded, config);
+    assert_eq!(loaded.selected_profile().seed_nodes, vec!["10.0.0.5:18133", "seed.example.org:18133"]);
+    assert_eq!(loaded.selected_profile().address_prefix, "tcj");
+}
+
+#[test]
+fn test_unreadable_config_is_kept_and_reported() {
+    let dir = temp_dir().join(format!("cj-network-unreadable-{}", std::process::id()));
+    let path = dir.join("network.json");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&path, "{ \"selected\": \"testnet\",").unwrap();
+
+    // nothing may replace a file the user still has to fix
+    assert!(matches!(NetworkConfig::default().save(&path), Err(WalletError::Config(_))));
+
+    let (config, error) = NetworkConfig::load_or_default(&path);
+    assert_eq!(config, NetworkConfig::default());
+    assert!(error.unwrap().contains("network.json.invalid"));
+    config.save(&path).unwrap();
+
+    let kept = fs::read_to_string(dir.join("network.json.invalid")).unwrap();
+    let reloaded = NetworkConfig::load_or_default(&path);
+    fs::remove_dir_all(dir).unwrap();
+    assert_eq!(kept, "{ \"selected\": \"testnet\",");
+    assert_eq!(reloaded, (NetworkConfig::default(), None));
+}
+
+#[test]
+fn test_empty_seed_nodes_restore_defaults() {
+    let mut config = NetworkConfig::default();
+    config.set_seed_nodes(Network::Regtest, vec!["127.0.0.1:9000".to_string()]).unwrap();
+    config.set_seed_nodes(Network::Regtest, vec![" ".to_string()]).unwrap();
+
+    assert_eq!(config.seed_nodes(Network::Regtest), Network::Regtest.default_seed_nodes());
+}
+
+#[test]
+fn test_invalid_seed_nodes_rejected() {
+    let mut config = NetworkConfig::default();
+    for node in ["192.168.1.117", ":8133", "host:0", "host:99999", "http://host:8133"] {
+        let result = config.set_seed_nodes(Network::Mainnet, vec![node.to_string()]);
+        assert!(matches!(result, Err(WalletError::Config(_))), "{:?} should be rejected", node);
+    }
+    assert_eq!(config, NetworkConfig::default());
+}
+
+#[test]
+fn test_networks_have_distinct_prefixes_and_magic() {
+    for a in Network::ALL {
+        assert_eq!(Network::from_hrp(&a.hrp()), Some(a));
+        for b in Network::ALL.into_iter().filter(|&b| b != a) {
+            assert_ne!(a.hrp(), b.hrp());
+            assert_ne!(a.magic(), b.magic());
+        }
+    }
+}
+
+#[test]
+fn test_profile_names_parse() {
+    let config: NetworkConfig = serde_json::from_str(r#"{"selected": "local"}"#).unwrap();
+    assert_eq!(config.selected, Network::Regtest);
+    assert!(serde_json::from_str::<NetworkConfig>(r#"{"selected": "moonnet"}"#).is_err());
+}
@@ -0,0 +1,209 @@
+use app::config::Network;
+use app::consensus::check_nbits;
+use app::consensus::median_time_past;
+use app::consensus::next_required_nbits;
+use app::consensus::ConsensusParams;
+use app::crypto_utils::BlockHeader;
+use app::error::WalletError;
+use app::miner::GENESIS_PREV_HASH;
+use app::pow::nbits_from_target;
+use app::pow::target_from_nbits;
+use app::pow::U256;
+
+const INTERVAL: u64 = 10;
+const T0: u64 = 1_700_000_000;
+
+// ten-block intervals of ten-minute blocks, so a whole interval should take 6000 seconds
+fn params() -> ConsensusParams {
+    ConsensusParams {
+        target_block_time: 600,
+        retarget_interval: INTERVAL,
+        max_adjustment_factor: 4,
+        pow_limit: 0x1f00ffff,
+        retargeting: true,
+    }
+}
+
+fn header(nbits: u32, timestamp: u64) -> BlockHeader {
+    BlockHeader {
+        version: 1,
+        prev_hash: GENESIS_PREV_HASH.to_string(),
+        merkle_root: "ab".repeat(32),
+        timestamp,
+        nbits,
+        nonce: 0,
+        hash: None,
+    }
+}
+
+// one interval at `nbits` whose first and last blocks are `timespan` seconds apart
+fn interval(nbits: u32, timespan: u64) -> Vec<BlockHeader> {
+    (0..INTERVAL).map(|i| header(nbits, T0 + timespan * i / (INTERVAL - 1))).collect()
+}
+
+fn retarget(nbits: u32, timespan: u64) -> u32 {
+    next_required_nbits(&params(), INTERVAL, &interval(nbits, timespan)).unwrap()
+}
+
+fn target(nbits: u32) -> U256 {
+    target_from_nbits(nbits).unwrap()
+}
+
+// Extends `chain` to `height` blocks, each taking as long to mine as the miners' hash power
+// needs for its difficulty: `spacing_at_limit` seconds at the pow limit, twice that at half the target.
+fn simulate(mut chain: Vec<BlockHeader>, height: u64, spacing_at_limit: u64) -> Vec<BlockHeader> {
+    let limit = target(params().pow_limit);
+    while (chain.len() as u64) < height {
+        let nbits = next_required_nbits(&params(), chain.len() as u64, &chain).unwrap();
+        let spacing = (limit.checked_mul_u64(spacing_at_limit).unwrap() / target(nbits)).low_u64();
+        let timestamp = chain.last().map_or(T0, |last| last.timestamp + spacing);
+        chain.push(header(nbits, timestamp));
+    }
+    chain
+}
+
+// average seconds between the blocks of the last interval
+fn last_spacing(chain: &[BlockHeader]) -> u64 {
+    let window = &chain[chain.len() - INTERVAL as usize..];
+    (window[window.len() - 1].timestamp - window[0].timestamp) / (INTERVAL - 1)
+}
+
+#[test]
+fn test_network_params() {
+    let mainnet = ConsensusParams::for_network(Network::Mainnet);
+    assert_eq!(mainnet.target_timespan(), 2016 * 600);
+    assert!(mainnet.retargeting);
+    assert_eq!(ConsensusParams::for_network(Network::Testnet), mainnet);
+
+    let regtest = ConsensusParams::for_network(Network::Regtest);
+    assert_eq!(regtest.pow_limit, 0x207fffff);
+    assert!(!regtest.retargeting);
+    // every pow limit is a valid target
+    for network in Network::ALL {
+        assert!(!ConsensusParams::for_network(network).pow_limit_target().unwrap().is_zero());
+    }
+}
+
+#[test]
+fn test_headers_needed() {
+    let params = params();
+    assert_eq!(params.headers_needed(0), 0);
+    assert_eq!(params.headers_needed(1), 1);
+    assert_eq!(params.headers_needed(9), 1);
+    assert_eq!(params.headers_needed(10), INTERVAL);
+    assert_eq!(params.headers_needed(20), INTERVAL);
+    assert_eq!(ConsensusParams { retargeting: false, ..params }.headers_needed(10), 1);
+}
+
+#[test]
+fn test_first_block_gets_the_pow_limit() {
+    assert_eq!(next_required_nbits(&params(), 0, &[]).unwrap(), 0x1f00ffff);
+}
+
+#[test]
+fn test_between_retargets_nbits_carry_over() {
+    let prev = interval(0x1d00ffff, 60);
+    for height in [1, 5, 11, 19] {
+        assert_eq!(next_required_nbits(&params(), height, &prev).unwrap(), 0x1d00ffff);
+    }
+}
+
+#[test]
+fn test_retarget_scales_with_the_timespan() {
+    assert_eq!(retarget(0x1d00ffff, 6000), 0x1d00ffff);
+    // twice as fast halves the target, twice as slow doubles it
+    assert_eq!(retarget(0x1d00ffff, 3000), nbits_from_target(target(0x1d00ffff) >> 1));
+    assert_eq!(retarget(0x1d00ffff, 12000), nbits_from_target(target(0x1d00ffff) << 1));
+    assert_eq!(retarget(0x1d00ffff, 4500), nbits_from_target((target(0x1d00ffff) >> 2).checked_mul_u64(3).unwrap()));
+}
+
+#[test]
+fn test_retarget_is_clamped() {
+    let quarter = nbits_from_target(target(0x1d00ffff) >> 2);
+    let quadruple = nbits_from_target(target(0x1d00ffff) << 2);
+
+    assert_eq!(retarget(0x1d00ffff, 1500), quarter);
+    assert_eq!(retarget(0x1d00ffff, 10), quarter);
+    assert_eq!(retarget(0x1d00ffff, 24000), quadruple);
+    assert_eq!(retarget(0x1d00ffff, 1_000_000), quadruple);
+
+    // timestamps running backwards count as the fastest interval allowed
+    let mut backwards = interval(0x1d00ffff, 6000);
+    backwards.reverse();
+    assert_eq!(next_required_nbits(&params(), INTERVAL, &backwards).unwrap(), quarter);
+}
+
+#[test]
+fn test_retarget_never_passes_the_pow_limit() {
+    assert_eq!(retarget(0x1f00ffff, 24000), 0x1f00ffff);
+    assert_eq!(retarget(0x1f007fff, 24000), 0x1f00ffff);
+    assert_eq!(retarget(0x1f007fff, 12000), 0x1f00fffe);
+}
+
+#[test]
+fn test_retarget_handles_the_easiest_targets() {
+    // a target near 2^256 times the longest timespan would overflow if multiplied first
+    let params = ConsensusParams { pow_limit: 0x2100ffff, ..params() };
+    let prev = interval(0x2000ffff, 24000);
+    assert_eq!(next_required_nbits(&params, INTERVAL, &prev).unwrap(), nbits_from_target(target(0x2000ffff) << 2));
+}
+
+#[test]
+fn test_no_retargeting_keeps_the_difficulty() {
+    let params = ConsensusParams { retargeting: false, ..params() };
+    assert_eq!(next_required_nbits(&params, INTERVAL, &interval(0x1d00ffff, 10)).unwrap(), 0x1d00ffff);
+}
+
+#[test]
+fn test_retarget_needs_the_whole_interval() {
+    let result = next_required_nbits(&params(), INTERVAL, &interval(0x1d00ffff, 6000)[1..]);
+    assert!(matches!(result, Err(WalletError::InvalidBlock(_))));
+    assert!(next_required_nbits(&params(), 1, &[]).is_err());
+}
+
+#[test]
+fn test_fast_miners_are_slowed_down() {
+    // miners finding blocks every 75 seconds at the pow limit, eight times too fast
+    let chain = simulate(Vec::new(), 5 * INTERVAL, 75);
+
+    // the first adjustment is capped at a quarter of the target
+    assert_eq!(chain[INTERVAL as usize].nbits, nbits_from_target(target(0x1f00ffff) >> 2));
+    let targets: Vec<U256> = chain.iter().step_by(INTERVAL as usize).map(|header| target(header.nbits)).collect();
+    assert!(targets[1] < targets[0] && targets[2] < targets[1]);
+    assert!((500..=700).contains(&last_spacing(&chain)), "{}", last_spacing(&chain));
+}
+
+#[test]
+fn test_slow_miners_are_sped_up() {
+    // an interval at 1/256 of the pow limit, which these miners take 4800 seconds a block for
+    let hard = nbits_from_target(target(0x1f00ffff) >> 8);
+    let history: Vec<BlockHeader> = (0..INTERVAL).map(|i| header(hard, T0 + i * 4800)).collect();
+    let chain = simulate(history, 5 * INTERVAL, 4800 / 256);
+
+    // the first adjustment is capped at four times the target
+    assert_eq!(chain[INTERVAL as usize].nbits, nbits_from_target(target(hard) << 2));
+    assert!(target(chain[2 * INTERVAL as usize].nbits) > target(chain[INTERVAL as usize].nbits));
+    assert!((500..=700).contains(&last_spacing(&chain)), "{}", last_spacing(&chain));
+}
+
+#[test]
+fn test_check_nbits() {
+    let prev = interval(0x1d00ffff, 3000);
+    let required = retarget(0x1d00ffff, 3000);
+
+    check_nbits(&params(), INTERVAL, &prev, &header(required, T0 + 3600)).unwrap();
+    let error = check_nbits(&params(), INTERVAL, &prev, &header(0x1d00ffff, T0 + 3600)).unwrap_err();
+    assert!(error.to_string().contains("the rules require"));
+}
+
+#[test]
+fn test_median_time_past() {
+    assert_eq!(median_time_past(&[]), None);
+    // only the last eleven count, whatever their order
+    let mut prev: Vec<BlockHeader> = [5, 1, 9, 3, 7, 2, 8, 4, 6, 10, 11, 12].iter().map(|offset| header(0x1d00ffff, T0 + offset)).collect();
+    prev.insert(0, header(0x1d00ffff, T0 + 100));
+    assert_eq!(median_time_past(&prev), Some(T0 + 7));
+    assert_eq!(params().context_needed(3), 3);
+    assert_eq!(params().context_needed(15), 11);
+    assert_eq!(params().context_needed(INTERVAL), INTERVAL);
+}
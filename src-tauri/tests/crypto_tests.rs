@@ -1,8 +1,11 @@
 use app::crypto_utils::calculate_merkle_root;
 use app::crypto_utils::double_sha256;
 use app::crypto_utils::generate_key_pair;
+use app::crypto_utils::hex_to_bytes;
 use app::crypto_utils::sign;
 use app::crypto_utils::verify;
+use app::error::WalletError;
+use app::secret::SecretBytes;
 
 use hex::decode;
 use hex::encode; 
@@ -30,11 +33,51 @@ fn test_merkle_root_bytes() {
 fn test_signature_verification() {
     let message = "hello";
 
-    let (private_key_hex, public_key_hex) = generate_key_pair();
+    let (private_key, public_key_hex) = generate_key_pair();
 
-    let (signature_hex, _) = sign(&private_key_hex, message);
+    let (signature_hex, _) = sign(&private_key, message).unwrap();
 
-    let is_valid = verify(&public_key_hex, message, &signature_hex);
+    let is_valid = verify(&public_key_hex, message, &signature_hex).unwrap();
 
     assert!(is_valid, "Signature verification failed");
+}
+
+#[test]
+fn test_verify_wrong_message_is_false() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let (signature_hex, _) = sign(&private_key, "hello").unwrap();
+
+    assert!(!verify(&public_key_hex, "goodbye", &signature_hex).unwrap());
+}
+
+#[test]
+fn test_verify_malformed_input_is_error() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let (signature_hex, _) = sign(&private_key, "hello").unwrap();
+
+    assert!(matches!(verify("zz", "hello", &signature_hex), Err(WalletError::Hex(_))));
+    assert!(matches!(verify("02abcd", "hello", &signature_hex), Err(WalletError::Crypto(_))));
+    assert!(matches!(verify(&public_key_hex, "hello", "3006"), Err(WalletError::Crypto(_))));
+}
+
+#[test]
+fn test_sign_rejects_short_key() {
+    let result = sign(&SecretBytes::from_hex("abcd").unwrap(), "hello");
+    assert!(matches!(result, Err(WalletError::KeyLength { expected: 32, actual: 2 })));
+}
+
+#[test]
+fn test_hex_to_bytes_errors() {
+    assert!(matches!(hex_to_bytes("xyz"), Err(WalletError::Hex(_))));
+    assert!(matches!(hex_to_bytes("0011"), Err(WalletError::KeyLength { expected: 32, actual: 2 })));
+}
+
+#[test]
+fn test_wallet_error_serializes_to_structured_json() {
+    let json = serde_json::to_value(WalletError::WrongPasswordOrCorrupt).unwrap();
+    assert_eq!(json["kind"], "wrong_password");
+    assert_eq!(json["message"], "wrong password or corrupted keystore");
+
+    let json = serde_json::to_value(WalletError::UnsupportedVersion(7)).unwrap();
+    assert_eq!(json["kind"], "unsupported_version");
 }
\ No newline at end of file
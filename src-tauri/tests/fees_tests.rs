@@ -0,0 +1,172 @@
+mod support;
+
+use app::api::NodeClient;
+use app::api::RetryPolicy;
+use app::codec::Codec;
+use app::config::Network;
+use app::crypto_utils::generate_key_pair;
+use app::crypto_utils::sign;
+use app::error::WalletError;
+use app::fees::estimate;
+use app::fees::fee_for_size;
+use app::fees::fee_rate;
+use app::fees::signed_size;
+use app::fees::FeeRates;
+use app::fees::FeeWarning;
+use app::fees::MIN_FEE_RATE;
+use app::transaction::sign_transaction;
+use app::transaction::TransactionBuilder;
+use app::transaction::Utxo;
+
+use support::fixtures::paying;
+use support::fixtures::utxos;
+use support::mock_node::Behavior;
+use support::mock_node::MockNode;
+
+const OWN_ADDRESS: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+const OTHER_ADDRESS: &str = "cj1recipient";
+
+fn rates(slow: u64, normal: u64, fast: u64) -> FeeRates {
+    FeeRates { slow, normal, fast }
+}
+
+#[test]
+fn test_fee_for_size_rounds_up() {
+    assert_eq!(fee_for_size(250, 1000), 250);
+    assert_eq!(fee_for_size(250, 1001), 251);
+    assert_eq!(fee_for_size(1, 1), 1);
+    assert_eq!(fee_for_size(0, 5000), 0);
+}
+
+#[test]
+fn test_fee_rate_of_a_transaction() {
+    let tx = paying(500, 1);
+    let size = tx.encode().unwrap().len() as u64;
+
+    assert_eq!(fee_rate(&tx), Some(500 * 1000 / size));
+}
+
+#[test]
+fn test_fee_rate_skips_transactions_without_a_fee() {
+    let mut coinbase = paying(0, 1);
+    coinbase.inputs.clear();
+    assert_eq!(fee_rate(&coinbase), None);
+
+    let mut overspending = paying(0, 1);
+    overspending.outputs[0].amount += 1;
+    assert_eq!(fee_rate(&overspending), None);
+}
+
+#[test]
+fn test_signed_size_allows_for_signatures() {
+    let (private_key, _) = generate_key_pair();
+    let mut tx = paying(10, 1);
+    tx.inputs.push(Utxo::unsigned(format!("{:064x}", 2), 3, 50));
+    let estimated = signed_size(&tx).unwrap();
+
+    sign_transaction(&mut tx, Network::Mainnet, |digest| sign(&private_key, digest)).unwrap();
+    let actual = tx.encode().unwrap().len();
+
+    // DER signatures are 70 to 72 bytes; the estimate assumes the longest
+    assert!(estimated >= actual && estimated <= actual + 4, "{} vs {}", estimated, actual);
+    assert_eq!(signed_size(&tx).unwrap(), actual);
+}
+
+#[test]
+fn test_rates_from_samples() {
+    let blocks = [6000, 2000, 10000, 4000, 8000];
+
+    assert_eq!(FeeRates::from_samples(&blocks, &[]), rates(4000, 6000, 8000));
+    // a backed-up mempool only raises the fast rate
+    assert_eq!(FeeRates::from_samples(&blocks, &[30000, 50000, 70000]), rates(4000, 6000, 50000));
+    // without blocks, the mempool is all there is
+    assert_eq!(FeeRates::from_samples(&[], &[3000, 1000, 2000]), rates(1000, 2000, 2000));
+}
+
+#[test]
+fn test_rates_have_a_floor_and_stay_ordered() {
+    assert_eq!(FeeRates::from_samples(&[], &[]), FeeRates::default());
+    assert_eq!(FeeRates::from_samples(&[10, 20, 30], &[]), rates(MIN_FEE_RATE, MIN_FEE_RATE, MIN_FEE_RATE));
+    assert_eq!(FeeRates::from_samples(&[500, 1500, 9000], &[]), rates(MIN_FEE_RATE, 1500, 1500));
+}
+
+#[test]
+fn test_median_resists_a_minority() {
+    let honest = rates(2000, 3000, 5000);
+    let liar = rates(900_000, 900_000, 900_000);
+
+    assert_eq!(FeeRates::median(&[honest, liar, honest]), honest);
+    assert_eq!(FeeRates::median(&[rates(1000, 2000, 3000), rates(3000, 4000, 5000), rates(2000, 3000, 4000)]), rates(2000, 3000, 4000));
+}
+
+#[test]
+fn test_estimate_prices_the_signed_transaction() {
+    let funds = utxos(&[10_000]);
+    let priced = estimate(&TransactionBuilder::default(), &funds, &[], 1_000, OTHER_ADDRESS, OWN_ADDRESS, rates(1000, 2000, 4000)).unwrap();
+
+    let tx = TransactionBuilder::default().build(&funds, &[], 1_000, OTHER_ADDRESS, priced.normal, OWN_ADDRESS).unwrap();
+    assert_eq!(priced.size, signed_size(&tx).unwrap());
+    assert_eq!((priced.slow, priced.normal, priced.fast), (fee_for_size(priced.size, 1000), fee_for_size(priced.size, 2000), fee_for_size(priced.size, 4000)));
+}
+
+#[test]
+fn test_estimate_counts_the_inputs_the_fee_needs() {
+    let funds = utxos(&[400, 400, 400, 400]);
+    let builder = TransactionBuilder::default();
+
+    // 700 alone needs two inputs; the fee on top of it pulls in a third
+    let small = estimate(&builder, &funds, &[], 300, OTHER_ADDRESS, OWN_ADDRESS, FeeRates::default()).unwrap();
+    let large = estimate(&builder, &funds, &[], 700, OTHER_ADDRESS, OWN_ADDRESS, FeeRates::default()).unwrap();
+
+    let two_inputs = builder.build(&funds, &[], 700, OTHER_ADDRESS, 0, OWN_ADDRESS).unwrap();
+    assert_eq!(two_inputs.inputs.len(), 2);
+    assert!(large.size > signed_size(&two_inputs).unwrap());
+    assert!(large.normal > small.normal);
+}
+
+#[test]
+fn test_estimate_when_the_fee_does_not_fit() {
+    let funds = utxos(&[1_000]);
+
+    // the funds cover the amount, so the fee is priced even though it cannot be paid
+    let priced = estimate(&TransactionBuilder::default(), &funds, &[], 1_000, OTHER_ADDRESS, OWN_ADDRESS, FeeRates::default()).unwrap();
+    assert!(priced.normal > 0);
+
+    let result = estimate(&TransactionBuilder::default(), &funds, &[], 1_001, OTHER_ADDRESS, OWN_ADDRESS, FeeRates::default());
+    assert!(matches!(result, Err(WalletError::InsufficientFunds { .. })));
+}
+
+#[test]
+fn test_fee_warnings() {
+    let priced = estimate(&TransactionBuilder::default(), &utxos(&[10_000]), &[], 1_000, OTHER_ADDRESS, OWN_ADDRESS, rates(1000, 2000, 4000)).unwrap();
+
+    assert_eq!(priced.warning(priced.slow - 1), Some(FeeWarning::BelowEstimate));
+    assert_eq!(priced.warning(priced.slow), None);
+    assert_eq!(priced.warning(priced.fast * 3), None);
+    assert_eq!(priced.warning(priced.fast * 3 + 1), Some(FeeWarning::AboveEstimate));
+}
+
+#[tokio::test]
+async fn test_node_fee_rates() {
+    let cluster = MockNode::spawn_cluster(3).await;
+    for node in &cluster {
+        node.mine(vec![paying(400, 1), paying(800, 2)]);
+        node.mine(vec![paying(1200, 3)]);
+    }
+    // one node's mempool is full of absurd fees
+    cluster[2].add_to_mempool(paying(90_000, 4)).add_to_mempool(paying(90_000, 5));
+
+    let client = NodeClient::with_nodes(Network::Mainnet, cluster.iter().map(MockNode::node).collect());
+    let block_rates: Vec<u64> = [400, 800, 1200].iter().map(|&fee| fee_rate(&paying(fee, 1)).unwrap()).collect();
+
+    assert_eq!(client.fee_rates().await.unwrap(), FeeRates::from_samples(&block_rates, &[]));
+}
+
+#[tokio::test]
+async fn test_fee_rates_need_one_answering_node() {
+    let node = MockNode::spawn().await;
+    node.set_behavior(Behavior::Fail(500));
+    let client = NodeClient::with_nodes(Network::Mainnet, vec![node.node()]).with_retry_policy(RetryPolicy::none());
+
+    assert!(matches!(client.fee_rates().await, Err(WalletError::Network(_))));
+}
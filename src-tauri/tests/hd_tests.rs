@@ -0,0 +1,239 @@
+use app::crypto_utils::public_key_from_private;
+use app::error::WalletError;
+use app::hd::discover_keys;
+use app::hd::DerivationPath;
+use app::hd::ExtendedPrivateKey;
+use app::hd::ExtendedPublicKey;
+use app::hd::DEFAULT_GAP_LIMIT;
+use app::hd::EXTERNAL_CHAIN;
+use app::hd::HARDENED;
+use app::secret::SecretBytes;
+
+use std::collections::HashSet;
+
+// Official BIP32 test vectors
+const VECTOR_1_SEED: &str = "000102030405060708090a0b0c0d0e0f";
+
+// (path, xpub, xprv)
+const VECTOR_1: [(&str, &str, &str); 6] = [
+    (
+        "m",
+        "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+        "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi",
+    ),
+    (
+        "m/0'",
+        "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+        "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7",
+    ),
+    (
+        "m/0'/1",
+        "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ",
+        "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs",
+    ),
+    (
+        "m/0'/1/2'",
+        "xpub6D4BDPcP2GT577Vvch3R8wDkScZWzQzMMUm3PWbmWvVJrZwQY4VUNgqFJPMM3No2dFDFGTsxxpG5uJh7n7epu4trkrX7x7DogT5Uv6fcLW5",
+        "xprv9z4pot5VBttmtdRTWfWQmoH1taj2axGVzFqSb8C9xaxKymcFzXBDptWmT7FwuEzG3ryjH4ktypQSAewRiNMjANTtpgP4mLTj34bhnZX7UiM",
+    ),
+    (
+        "m/0'/1/2'/2",
+        "xpub6FHa3pjLCk84BayeJxFW2SP4XRrFd1JYnxeLeU8EqN3vDfZmbqBqaGJAyiLjTAwm6ZLRQUMv1ZACTj37sR62cfN7fe5JnJ7dh8zL4fiyLHV",
+        "xprvA2JDeKCSNNZky6uBCviVfJSKyQ1mDYahRjijr5idH2WwLsEd4Hsb2Tyh8RfQMuPh7f7RtyzTtdrbdqqsunu5Mm3wDvUAKRHSC34sJ7in334",
+    ),
+    (
+        "m/0'/1/2'/2/1000000000",
+        "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy",
+        "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76",
+    ),
+];
+
+const VECTOR_2_SEED: &str = "fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a29f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542";
+
+// (path, xpub, xprv)
+const VECTOR_2: [(&str, &str, &str); 6] = [
+    (
+        "m",
+        "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB",
+        "xprv9s21ZrQH143K31xYSDQpPDxsXRTUcvj2iNHm5NUtrGiGG5e2DtALGdso3pGz6ssrdK4PFmM8NSpSBHNqPqm55Qn3LqFtT2emdEXVYsCzC2U",
+    ),
+    (
+        "m/0",
+        "xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH",
+        "xprv9vHkqa6EV4sPZHYqZznhT2NPtPCjKuDKGY38FBWLvgaDx45zo9WQRUT3dKYnjwih2yJD9mkrocEZXo1ex8G81dwSM1fwqWpWkeS3v86pgKt",
+    ),
+    (
+        "m/0/2147483647'",
+        "xpub6ASAVgeehLbnwdqV6UKMHVzgqAG8Gr6riv3Fxxpj8ksbH9ebxaEyBLZ85ySDhKiLDBrQSARLq1uNRts8RuJiHjaDMBU4Zn9h8LZNnBC5y4a",
+        "xprv9wSp6B7kry3Vj9m1zSnLvN3xH8RdsPP1Mh7fAaR7aRLcQMKTR2vidYEeEg2mUCTAwCd6vnxVrcjfy2kRgVsFawNzmjuHc2YmYRmagcEPdU9",
+    ),
+    (
+        "m/0/2147483647'/1",
+        "xpub6DF8uhdarytz3FWdA8TvFSvvAh8dP3283MY7p2V4SeE2wyWmG5mg5EwVvmdMVCQcoNJxGoWaU9DCWh89LojfZ537wTfunKau47EL2dhHKon",
+        "xprv9zFnWC6h2cLgpmSA46vutJzBcfJ8yaJGg8cX1e5StJh45BBciYTRXSd25UEPVuesF9yog62tGAQtHjXajPPdbRCHuWS6T8XA2ECKADdw4Ef",
+    ),
+    (
+        "m/0/2147483647'/1/2147483646'",
+        "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL",
+        "xprvA1RpRA33e1JQ7ifknakTFpgNXPmW2YvmhqLQYMmrj4xJXXWYpDPS3xz7iAxn8L39njGVyuoseXzU6rcxFLJ8HFsTjSyQbLYnMpCqE2VbFWc",
+    ),
+    (
+        "m/0/2147483647'/1/2147483646'/2",
+        "xpub6FnCn6nSzZAw5Tw7cgR9bi15UV96gLZhjDstkXXxvCLsUXBGXPdSnLFbdpq8p9HmGsApME5hQTZ3emM2rnY5agb9rXpVGyy3bdW6EEgAtqt",
+        "xprvA2nrNbFZABcdryreWet9Ea4LvTJcGsqrMzxHx98MMrotbir7yrKCEXw7nadnHM8Dq38EGfSh6dqA9QWTyefMLEcBYJUuekgW4BYPJcr9E7j",
+    ),
+];
+
+const VECTOR_3_SEED: &str = "4b381541583be4423346c643850da4b320e46a87ae3d2a4e6da11eba819cd4acba45d239319ac14f863b8d5ab5a0d0c64d2e8a1e7d1457df2e5a3c51c73235be";
+
+// (path, xpub, xprv)
+const VECTOR_3: [(&str, &str, &str); 2] = [
+    (
+        "m",
+        "xpub661MyMwAqRbcEZVB4dScxMAdx6d4nFc9nvyvH3v4gJL378CSRZiYmhRoP7mBy6gSPSCYk6SzXPTf3ND1cZAceL7SfJ1Z3GC8vBgp2epUt13",
+        "xprv9s21ZrQH143K25QhxbucbDDuQ4naNntJRi4KUfWT7xo4EKsHt2QJDu7KXp1A3u7Bi1j8ph3EGsZ9Xvz9dGuVrtHHs7pXeTzjuxBrCmmhgC6",
+    ),
+    (
+        "m/0'",
+        "xpub68NZiKmJWnxxS6aaHmn81bvJeTESw724CRDs6HbuccFQN9Ku14VQrADWgqbhhTHBaohPX4CjNLf9fq9MYo6oDaPPLPxSb7gwQN3ih19Zm4Y",
+        "xprv9uPDJpEQgRQfDcW7BkF7eTya6RPxXeJCqCJGHuCJ4GiRVLzkTXBAJMu2qaMWPrS7AANYqdq6vcBcBUdJCVVFceUvJFjaPdGZ2y9WACViL4L",
+    ),
+];
+
+const VECTOR_4_SEED: &str = "3ddd5602285899a946114506157c7997e5444528f3003f6134712147db19b678";
+
+// (path, xpub, xprv)
+const VECTOR_4: [(&str, &str, &str); 3] = [
+    (
+        "m",
+        "xpub661MyMwAqRbcGczjuMoRm6dXaLDEhW1u34gKenbeYqAix21mdUKJyuyu5F1rzYGVxyL6tmgBUAEPrEz92mBXjByMRiJdba9wpnN37RLLAXa",
+        "xprv9s21ZrQH143K48vGoLGRPxgo2JNkJ3J3fqkirQC2zVdk5Dgd5w14S7fRDyHH4dWNHUgkvsvNDCkvAwcSHNAQwhwgNMgZhLtQC63zxwhQmRv",
+    ),
+    (
+        "m/0'",
+        "xpub69AUMk3qDBi3uW1sXgjCmVjJ2G6WQoYSnNHyzkmdCHEhSZ4tBok37xfFEqHd2AddP56Tqp4o56AePAgCjYdvpW2PU2jbUPFKsav5ut6Ch1m",
+        "xprv9vB7xEWwNp9kh1wQRfCCQMnZUEG21LpbR9NPCNN1dwhiZkjjeGRnaALmPXCX7SgjFTiCTT6bXes17boXtjq3xLpcDjzEuGLQBM5ohqkao9G",
+    ),
+    (
+        "m/0'/1'",
+        "xpub6BJA1jSqiukeaesWfxe6sNK9CCGaujFFSJLomWHprUL9DePQ4JDkM5d88n49sMGJxrhpjazuXYWdMf17C9T5XnxkopaeS7jGk1GyyVziaMt",
+        "xprv9xJocDuwtYCMNAo3Zw76WENQeAS6WGXQ55RCy7tDJ8oALr4FWkuVoHJeHVAcAqiZLE7Je3vZJHxspZdFHfnBEjHqU5hG1Jaj32dVoS6XLT1",
+    ),
+];
+
+fn check_vector(seed: &str, vector: &[(&str, &str, &str)]) {
+    let master = ExtendedPrivateKey::from_seed(&SecretBytes::from_hex(seed).unwrap()).unwrap();
+
+    for (path, xpub, xprv) in vector {
+        let key = master.derive_path(&path.parse().unwrap()).unwrap();
+        assert_eq!(key.to_base58().as_str(), *xprv, "xprv for {}", path);
+        assert_eq!(key.public().to_base58(), *xpub, "xpub for {}", path);
+    }
+}
+
+#[test]
+fn test_bip32_vector_1() {
+    check_vector(VECTOR_1_SEED, &VECTOR_1);
+}
+
+#[test]
+fn test_bip32_vector_2() {
+    check_vector(VECTOR_2_SEED, &VECTOR_2);
+}
+
+#[test]
+fn test_bip32_vector_3_leading_zeros() {
+    check_vector(VECTOR_3_SEED, &VECTOR_3);
+}
+
+#[test]
+fn test_bip32_vector_4_leading_zeros() {
+    check_vector(VECTOR_4_SEED, &VECTOR_4);
+}
+
+#[test]
+fn test_public_derivation_matches_private() {
+    let master = ExtendedPrivateKey::from_seed(&SecretBytes::from_hex(VECTOR_2_SEED).unwrap()).unwrap();
+    let account = master.derive_path(&DerivationPath::cj_coin_account(0)).unwrap();
+
+    let from_public = account.public().derive_path(&"m/0/7".parse().unwrap()).unwrap();
+    let from_private = account.derive_path(&"m/0/7".parse().unwrap()).unwrap();
+
+    assert_eq!(from_public, from_private.public());
+    assert_eq!(from_public.public_key_hex(), public_key_from_private(&from_private.private_key()).unwrap());
+}
+
+#[test]
+fn test_public_key_cannot_derive_hardened() {
+    let master = ExtendedPrivateKey::from_seed(&SecretBytes::from_hex(VECTOR_1_SEED).unwrap()).unwrap();
+    let result = master.public().derive_child(HARDENED);
+
+    assert!(matches!(result, Err(WalletError::Derivation(_))));
+}
+
+#[test]
+fn test_extended_keys_round_trip() {
+    let (_, xpub, xprv) = VECTOR_1[3];
+
+    assert_eq!(ExtendedPrivateKey::from_base58(xprv).unwrap().to_base58().as_str(), xprv);
+    assert_eq!(ExtendedPublicKey::from_base58(xpub).unwrap().to_base58(), xpub);
+    assert!(ExtendedPublicKey::from_base58(xprv).is_err());
+
+    let mut corrupted = xpub.to_string();
+    corrupted.replace_range(10..11, if &xpub[10..11] == "a" { "b" } else { "a" });
+    assert!(ExtendedPublicKey::from_base58(&corrupted).is_err());
+}
+
+#[test]
+fn test_derivation_path_parsing() {
+    let path: DerivationPath = "m/44'/8133h/0'/0/5".parse().unwrap();
+
+    assert_eq!(path, DerivationPath::cj_coin(0, EXTERNAL_CHAIN, 5));
+    assert_eq!(path.to_string(), "m/44'/8133'/0'/0/5");
+    assert_eq!("m".parse::<DerivationPath>().unwrap().indices(), &[] as &[u32]);
+
+    for invalid in ["", "44'/0", "m/", "m/x", "m/2147483648", "m/-1"] {
+        assert!(invalid.parse::<DerivationPath>().is_err(), "{:?} should not parse", invalid);
+    }
+}
+
+#[test]
+fn test_discover_keys_stops_after_gap_limit() {
+    let master = ExtendedPrivateKey::from_seed(&SecretBytes::from_hex(VECTOR_1_SEED).unwrap()).unwrap();
+    let receive = master.derive_path(&DerivationPath::cj_coin_account(0)).unwrap().public().derive_child(EXTERNAL_CHAIN).unwrap();
+
+    // pretend indices 0, 3 and 22 have history; 22 is within the gap after 3, so it is found
+    let used: HashSet<String> = [0, 3, 22].iter().map(|&i| receive.derive_child(i).unwrap().public_key_hex()).collect();
+    let mut checked = 0;
+
+    let discovery = discover_keys(&receive, DEFAULT_GAP_LIMIT, |public_key| {
+        checked += 1;
+        Ok(used.contains(public_key))
+    })
+    .unwrap();
+
+    let indices: Vec<u32> = discovery.used.iter().map(|key| key.index).collect();
+    assert_eq!(indices, vec![0, 3, 22]);
+    assert_eq!(discovery.next_index, 23);
+    assert_eq!(checked, 23 + DEFAULT_GAP_LIMIT);
+}
+
+#[test]
+fn test_discover_keys_misses_keys_beyond_gap() {
+    let master = ExtendedPrivateKey::from_seed(&SecretBytes::from_hex(VECTOR_1_SEED).unwrap()).unwrap();
+    let receive = master.public().derive_child(EXTERNAL_CHAIN).unwrap();
+
+    let far = receive.derive_child(5).unwrap().public_key_hex();
+    let discovery = discover_keys(&receive, 5, |public_key| Ok(public_key == far)).unwrap();
+
+    assert!(discovery.used.is_empty());
+    assert_eq!(discovery.next_index, 0);
+}
+
+#[test]
+fn test_discover_keys_propagates_errors() {
+    let master = ExtendedPrivateKey::from_seed(&SecretBytes::from_hex(VECTOR_1_SEED).unwrap()).unwrap();
+
+    let result = discover_keys(&master.public(), DEFAULT_GAP_LIMIT, |_| Err(WalletError::Crypto("node unreachable".to_string())));
+    assert!(matches!(result, Err(WalletError::Crypto(_))));
+}
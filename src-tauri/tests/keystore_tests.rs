@@ -0,0 +1,323 @@
+use app::crypto_utils::calibrate_argon2id;
+use app::crypto_utils::derive_key;
+use app::crypto_utils::derive_key_material;
+use app::crypto_utils::generate_key_pair;
+use app::crypto_utils::generate_salt;
+use app::crypto_utils::Argon2Params;
+use app::error::WalletError;
+use app::keystore::create_keystore_file;
+use app::keystore::decrypt_legacy_files;
+use app::keystore::keystore_exists;
+use app::keystore::migrate_legacy_files;
+use app::keystore::KdfParams;
+use app::keystore::Keystore;
+use app::keystore::KEYSTORE_VERSION;
+use app::secret::Password;
+use app::secret::SecretBytes;
+
+use aes::Aes256;
+use block_modes::block_padding::Pkcs7;
+use block_modes::BlockMode;
+use block_modes::Cbc;
+use hex::decode;
+use hex::encode;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+
+use std::env::temp_dir;
+use std::fs;
+use std::time::Duration;
+
+const ITERATIONS: u32 = 1_000;
+
+const TEST_ARGON2: Argon2Params = Argon2Params {
+    memory_kib: 1024,
+    iterations: 2,
+    parallelism: 1,
+};
+
+fn test_kdf() -> KdfParams {
+    KdfParams::argon2id(TEST_ARGON2)
+}
+
+// AES-256-CBC as older versions wrote it, returning hex (cipher_text, iv); the wallet itself
+// only decrypts CBC
+fn cbc_encrypt(key: &[u8; 32], plain_text: &str) -> (String, String) {
+    let iv = generate_salt();
+    let cipher = Cbc::<Aes256, Pkcs7>::new_from_slices(key, &iv).unwrap();
+    (encode(cipher.encrypt_vec(plain_text.as_bytes())), encode(iv))
+}
+
+#[test]
+fn test_keystore_round_trip() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let path = temp_dir().join(format!("cj-keystore-{}.json", public_key_hex));
+    let path = path.to_str().unwrap();
+
+    let keystore = Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), test_kdf()).unwrap();
+    keystore.save(path).unwrap();
+
+    let loaded = Keystore::load(path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(loaded.version, KEYSTORE_VERSION);
+    assert_eq!(loaded.public_key, public_key_hex);
+    assert_eq!(loaded.decrypt(&Password::from("hello")).unwrap().expose(), private_key.expose());
+}
+
+#[test]
+fn test_keystore_stores_kdf_params() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let keystore = Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), test_kdf()).unwrap();
+
+    let reloaded = Keystore::from_json(&serde_json::to_string(&keystore).unwrap()).unwrap();
+
+    match &reloaded.kdf {
+        KdfParams::Argon2id { salt, memory_kib, iterations, parallelism } => {
+            assert!(decode(salt).unwrap().len() >= 16);
+            assert_eq!(*memory_kib, TEST_ARGON2.memory_kib);
+            assert_eq!(*iterations, TEST_ARGON2.iterations);
+            assert_eq!(*parallelism, TEST_ARGON2.parallelism);
+        }
+        other => panic!("expected argon2id, got {:?}", other),
+    }
+    assert_eq!(reloaded.decrypt(&Password::from("hello")).unwrap().expose(), private_key.expose());
+}
+
+#[test]
+fn test_keystore_pbkdf2_still_decrypts() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let kdf = KdfParams::pbkdf2_sha256(ITERATIONS);
+    let keystore = Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), kdf).unwrap();
+
+    assert_eq!(keystore.decrypt(&Password::from("hello")).unwrap().expose(), private_key.expose());
+    assert!(matches!(keystore.decrypt(&Password::from("goodbye")), Err(WalletError::WrongPasswordOrCorrupt)));
+}
+
+#[test]
+fn test_keystore_rejects_invalid_argon2_params() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let mut keystore = Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), test_kdf()).unwrap();
+
+    if let KdfParams::Argon2id { memory_kib, .. } = &mut keystore.kdf {
+        *memory_kib = 0;
+    }
+
+    assert!(matches!(keystore.decrypt(&Password::from("hello")), Err(WalletError::Crypto(_))));
+}
+
+#[test]
+fn test_calibrate_argon2id_bounds() {
+    let fast = calibrate_argon2id(Duration::from_millis(0), 1024);
+    let slow = calibrate_argon2id(Duration::from_secs(3600), 1024);
+
+    assert_eq!(fast.memory_kib, 1024);
+    assert_eq!(fast.iterations, 2);
+    assert_eq!(slow.iterations, 64);
+}
+
+#[test]
+fn test_keystore_does_not_store_derived_key() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let keystore = Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), test_kdf()).unwrap();
+
+    let json = serde_json::to_string(&keystore).unwrap();
+    assert!(!json.contains("hmac_array"));
+    assert!(!json.contains(&encode(private_key.expose())));
+}
+
+#[test]
+fn test_keystore_wrong_password() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let keystore = Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), test_kdf()).unwrap();
+
+    assert!(matches!(keystore.decrypt(&Password::from("goodbye")), Err(WalletError::WrongPasswordOrCorrupt)));
+}
+
+#[test]
+fn test_keystore_tampered_ciphertext() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let mut keystore = Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), test_kdf()).unwrap();
+
+    let flipped = if keystore.ciphertext.starts_with('0') { "1" } else { "0" };
+    keystore.ciphertext.replace_range(0..1, flipped);
+
+    assert!(matches!(keystore.decrypt(&Password::from("hello")), Err(WalletError::WrongPasswordOrCorrupt)));
+}
+
+#[test]
+fn test_keystore_binds_public_key() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let (_, other_public_key_hex) = generate_key_pair();
+    let mut keystore = Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), test_kdf()).unwrap();
+
+    keystore.public_key = other_public_key_hex;
+
+    assert!(matches!(keystore.decrypt(&Password::from("hello")), Err(WalletError::WrongPasswordOrCorrupt)));
+}
+
+#[test]
+fn test_keystore_decrypts_version_1_cbc() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let salt = [7u8; 8];
+
+    let material = derive_key_material(b"hello", &salt, ITERATIONS);
+    let enc_key: [u8; 32] = material[..32].try_into().unwrap();
+    let (cipher_text, iv) = cbc_encrypt(&enc_key, &encode(private_key.expose()));
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(&material[32..]).unwrap();
+    mac.update(&decode(&iv).unwrap());
+    mac.update(&decode(&cipher_text).unwrap());
+
+    let document = json!({
+        "version": 1,
+        "public_key": public_key_hex,
+        "kdf": { "algorithm": "pbkdf2-sha256", "salt": encode(salt), "iterations": ITERATIONS },
+        "cipher": { "algorithm": "aes-256-cbc", "iv": iv },
+        "ciphertext": cipher_text,
+        "mac": encode(mac.finalize().into_bytes()),
+    });
+
+    let keystore = Keystore::from_json(&document.to_string()).unwrap();
+    assert_eq!(keystore.decrypt(&Password::from("hello")).unwrap().expose(), private_key.expose());
+    assert!(matches!(keystore.decrypt(&Password::from("goodbye")), Err(WalletError::WrongPasswordOrCorrupt)));
+}
+
+// writes a pre-keystore `aes_key_data.json` / `key_pair_data.json` pair for a new key
+// encrypted under "hello", returning the private key and both paths
+fn write_legacy_files() -> (SecretBytes, String, String) {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let salt = generate_salt();
+    let key = derive_key(b"hello", &salt, ITERATIONS);
+    let (encrypted_private_key, iv) = cbc_encrypt(&key, &encode(private_key.expose()));
+
+    let aes_key_data_path = temp_dir().join(format!("cj-aes-key-data-{}.json", public_key_hex));
+    let key_pair_data_path = temp_dir().join(format!("cj-key-pair-data-{}.json", public_key_hex));
+
+    let aes_key_data = json!({ "hmac_array": encode(key.as_slice()), "salt": encode(&salt), "iterations": ITERATIONS });
+    let key_pair_data = json!({ "encrypted_private_key": encrypted_private_key, "public_key": public_key_hex, "iv": iv });
+    fs::write(&aes_key_data_path, aes_key_data.to_string()).unwrap();
+    fs::write(&key_pair_data_path, key_pair_data.to_string()).unwrap();
+
+    (private_key, aes_key_data_path.to_str().unwrap().to_string(), key_pair_data_path.to_str().unwrap().to_string())
+}
+
+#[test]
+fn test_legacy_files_decode() {
+    let (private_key, aes_key_data_path, key_pair_data_path) = write_legacy_files();
+    let (aes_key_data_path, key_pair_data_path) = (aes_key_data_path.as_str(), key_pair_data_path.as_str());
+
+    let decrypted = decrypt_legacy_files(aes_key_data_path, key_pair_data_path, &Password::from("hello"));
+    let wrong = decrypt_legacy_files(aes_key_data_path, key_pair_data_path, &Password::from("goodbye"));
+
+    fs::remove_file(aes_key_data_path).unwrap();
+    fs::remove_file(key_pair_data_path).unwrap();
+
+    assert_eq!(decrypted.unwrap().expose(), private_key.expose());
+    assert!(matches!(wrong, Err(WalletError::WrongPasswordOrCorrupt)));
+}
+
+#[test]
+fn test_legacy_files_migrate_to_a_keystore() {
+    let (private_key, aes_key_data_path, key_pair_data_path) = write_legacy_files();
+    let path = temp_dir().join(format!("cj-migrated-keystore-{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    let wrong = migrate_legacy_files(&aes_key_data_path, &key_pair_data_path, path, &Password::from("goodbye"), test_kdf());
+    let written_after_wrong = fs::metadata(path).is_ok();
+    let public_key = migrate_legacy_files(&aes_key_data_path, &key_pair_data_path, path, &Password::from("hello"), test_kdf()).unwrap();
+    let keystore = Keystore::load(path).unwrap();
+
+    fs::remove_file(&aes_key_data_path).unwrap();
+    fs::remove_file(&key_pair_data_path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert!(matches!(wrong, Err(WalletError::WrongPasswordOrCorrupt)));
+    assert!(!written_after_wrong);
+    assert_eq!(keystore.version, KEYSTORE_VERSION);
+    assert_eq!(keystore.public_key, public_key);
+    assert_eq!(keystore.decrypt(&Password::from("hello")).unwrap().expose(), private_key.expose());
+}
+
+#[test]
+fn test_keystore_exists() {
+    let path = temp_dir().join(format!("cj-keystore-exists-{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    let before = keystore_exists(path);
+    create_keystore_file(path, &Password::from("hello"), test_kdf()).unwrap();
+    let after = keystore_exists(path);
+    fs::remove_file(path).unwrap();
+
+    assert!(!before);
+    assert!(after);
+    assert!(!keystore_exists(temp_dir().to_str().unwrap()));
+}
+
+#[test]
+fn test_existing_keystore_is_never_replaced() {
+    let (_, aes_key_data_path, key_pair_data_path) = write_legacy_files();
+    let path = temp_dir().join(format!("cj-existing-keystore-{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    create_keystore_file(path, &Password::from("hello"), test_kdf()).unwrap();
+    let original = fs::read(path).unwrap();
+    let created_again = create_keystore_file(path, &Password::from("hello"), test_kdf());
+    let migrated = migrate_legacy_files(&aes_key_data_path, &key_pair_data_path, path, &Password::from("hello"), test_kdf());
+    let after = fs::read(path).unwrap();
+
+    fs::remove_file(&aes_key_data_path).unwrap();
+    fs::remove_file(&key_pair_data_path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert!(matches!(created_again, Err(WalletError::KeystoreExists(_))));
+    assert!(matches!(migrated, Err(WalletError::KeystoreExists(_))));
+    assert_eq!(after, original);
+}
+
+#[test]
+fn test_keystore_rejects_unknown_version() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let keystore = Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), test_kdf()).unwrap();
+
+    let mut json: serde_json::Value = serde_json::to_value(&keystore).unwrap();
+    json["version"] = serde_json::json!(99);
+
+    let result = Keystore::from_json(&json.to_string());
+    assert!(matches!(result, Err(WalletError::UnsupportedVersion(99))));
+}
+
+#[test]
+fn test_keystore_rejects_missing_version() {
+    let result = Keystore::from_json(r#"{"hmac_array": "00", "salt": "00", "iterations": 1}"#);
+    assert!(matches!(result, Err(WalletError::Parse(_))));
+}
+
+#[test]
+fn test_keystore_rejects_algorithms_its_version_never_used() {
+    let (private_key, public_key_hex) = generate_key_pair();
+    let argon2 = serde_json::to_value(Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), test_kdf()).unwrap()).unwrap();
+    let pbkdf2 = serde_json::to_value(Keystore::encrypt(&private_key, &public_key_hex, &Password::from("hello"), KdfParams::pbkdf2_sha256(ITERATIONS)).unwrap()).unwrap();
+    let cbc = json!({ "algorithm": "aes-256-cbc", "iv": "00".repeat(16) });
+
+    let with = |document: &serde_json::Value, version: u32, cipher: Option<&serde_json::Value>| {
+        let mut document = document.clone();
+        document["version"] = json!(version);
+        if let Some(cipher) = cipher {
+            document["cipher"] = cipher.clone();
+        }
+        Keystore::from_json(&document.to_string())
+    };
+
+    assert!(with(&pbkdf2, 2, None).is_ok());
+    assert!(with(&pbkdf2, 3, None).is_ok());
+    assert!(with(&argon2, 3, None).is_ok());
+
+    // argon2id arrived in version 3, GCM in version 2, and CBC left with version 1
+    assert!(matches!(with(&argon2, 1, Some(&cbc)), Err(WalletError::Crypto(_))));
+    assert!(matches!(with(&argon2, 2, None), Err(WalletError::Crypto(_))));
+    assert!(matches!(with(&pbkdf2, 1, None), Err(WalletError::Crypto(_))));
+    assert!(matches!(with(&pbkdf2, 2, Some(&cbc)), Err(WalletError::Crypto(_))));
+    assert!(matches!(with(&pbkdf2, 3, Some(&cbc)), Err(WalletError::Crypto(_))));
+}
@@ -0,0 +1,182 @@
+mod support;
+
+use app::api::NodeClient;
+use app::chain::sync_headers;
+use app::chain::HeaderChain;
+use app::codec::txid;
+use app::config::Network;
+use app::crypto_utils::calculate_merkle_root;
+use app::crypto_utils::double_sha256;
+use app::error::WalletError;
+use app::merkle::verify_merkle_proof;
+use app::merkle::MerkleProof;
+use app::merkle::MerkleTree;
+use app::miner::coinbase;
+
+use hex::decode;
+use hex::encode;
+
+use support::mock_node::MockNode;
+
+const MINER: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+
+// the recursive root computation `MerkleTree` replaced, kept to check the roots still match
+fn reference_root(txs: Vec<Vec<u8>>) -> Vec<u8> {
+    if txs.is_empty() {
+        return vec![0u8; 32];
+    }
+    if txs.len() == 1 {
+        return double_sha256(&txs[0]);
+    }
+    let mut combined = Vec::new();
+    for pair in txs.chunks(2) {
+        let left = double_sha256(&pair[0]);
+        let right = pair.get(1).map_or_else(|| left.clone(), |right| double_sha256(right));
+        combined.push([left, right].concat());
+    }
+    reference_root(combined)
+}
+
+fn txids(count: u64) -> Vec<Vec<u8>> {
+    (0..count).map(|i| double_sha256(&i.to_le_bytes())).collect()
+}
+
+#[test]
+fn test_root_matches_the_existing_vector() {
+    let tree = MerkleTree::new(&[b"a".to_vec(), b"b".to_vec()]);
+    assert_eq!(encode(tree.root()), "b767a3a12f5f8bb1949d163c51f9a42e6bda8dcd02d50353717f73d4338b1bf0");
+}
+
+#[test]
+fn test_root_matches_the_recursive_computation() {
+    for count in 0..=17 {
+        let txids = txids(count);
+        assert_eq!(MerkleTree::new(&txids).root(), reference_root(txids.clone()), "{} transactions", count);
+        assert_eq!(calculate_merkle_root(txids.clone()), reference_root(txids), "{} transactions", count);
+    }
+}
+
+#[test]
+fn test_levels() {
+    let tree = MerkleTree::new(&txids(5));
+    let sizes: Vec<usize> = tree.levels().iter().map(Vec::len).collect();
+    assert_eq!(sizes, vec![5, 3, 2, 1]);
+    assert_eq!(tree.levels()[0][0], double_sha256(&txids(5)[0]));
+    assert_eq!(tree.len(), 5);
+
+    let empty = MerkleTree::new::<Vec<u8>>(&[]);
+    assert!(empty.is_empty());
+    assert_eq!(empty.root(), vec![0u8; 32]);
+    assert_eq!(empty.proof(0), None);
+}
+
+#[test]
+fn test_every_proof_verifies() {
+    for count in 1..=9 {
+        let txids = txids(count);
+        let tree = MerkleTree::new(&txids);
+        let root = encode(tree.root());
+        for (index, txid) in txids.iter().enumerate() {
+            let proof = tree.proof(index).unwrap();
+            assert_eq!(proof.index, index as u64);
+            assert_eq!(tree.proof_for(txid), Some(proof.clone()));
+            assert!(verify_merkle_proof(&encode(txid), &proof, &root).unwrap(), "{} of {}", index, count);
+        }
+        assert_eq!(tree.proof(count as usize), None);
+    }
+}
+
+#[test]
+fn test_single_transaction_needs_no_siblings() {
+    let tree = MerkleTree::new(&txids(1));
+    let proof = tree.proof(0).unwrap();
+    assert!(proof.siblings.is_empty());
+    assert!(verify_merkle_proof(&encode(&txids(1)[0]), &proof, &encode(tree.root())).unwrap());
+}
+
+#[test]
+fn test_tampered_proofs_fail() {
+    let txids = txids(6);
+    let tree = MerkleTree::new(&txids);
+    let root = encode(tree.root());
+    let txid = encode(&txids[4]);
+    let proof = tree.proof(4).unwrap();
+    let fails = |txid: &str, proof: &MerkleProof, root: &str| !verify_merkle_proof(txid, proof, root).unwrap();
+
+    assert!(fails(&encode(&txids[3]), &proof, &root));
+    assert!(fails(&txid, &proof, &encode(double_sha256(b"another root"))));
+    assert!(fails(&txid, &MerkleProof { index: 5, ..proof.clone() }, &root));
+
+    let mut wrong_sibling = proof.clone();
+    wrong_sibling.siblings[1] = "00".repeat(32);
+    assert!(fails(&txid, &wrong_sibling, &root));
+
+    let mut extra_sibling = proof.clone();
+    extra_sibling.siblings.push("00".repeat(32));
+    assert!(fails(&txid, &extra_sibling, &root));
+
+    // an index past the end of the path cannot be told apart from a smaller one, so it is refused
+    assert!(fails(&txid, &MerkleProof { index: 4 + (1 << proof.siblings.len()), ..proof.clone() }, &root));
+    assert!(fails(&txid, &MerkleProof { index: u64::MAX, ..proof.clone() }, &root));
+    assert!(fails(&txid, &MerkleProof { leaf_count: 12, ..proof.clone() }, &root));
+    assert!(fails(&txid, &MerkleProof { leaf_count: u64::MAX, ..proof }, &root));
+
+    assert_eq!(tree.proof_for(b"not in the block"), None);
+}
+
+#[test]
+fn test_phantom_index_after_the_last_transaction_fails() {
+    // with 3 transactions the last leaf is paired with itself, so its path also hashes to the
+    // root from index 3, which does not exist
+    let txids = txids(3);
+    let tree = MerkleTree::new(&txids);
+    let root = encode(tree.root());
+    let txid = encode(&txids[2]);
+    let proof = tree.proof(2).unwrap();
+
+    assert!(verify_merkle_proof(&txid, &proof, &root).unwrap());
+    assert!(!verify_merkle_proof(&txid, &MerkleProof { index: 3, ..proof.clone() }, &root).unwrap());
+    assert!(!verify_merkle_proof(&txid, &MerkleProof { index: 3, leaf_count: 4, ..proof }, &root).unwrap());
+}
+
+#[test]
+fn test_malformed_proofs_are_errors() {
+    let tree = MerkleTree::new(&txids(2));
+    let proof = tree.proof(0).unwrap();
+    let root = encode(tree.root());
+
+    assert!(matches!(verify_merkle_proof("zz", &proof, &root), Err(WalletError::Hex(_))));
+    assert!(matches!(verify_merkle_proof(&encode(&txids(2)[0]), &proof, "zz"), Err(WalletError::Hex(_))));
+    let bad_sibling = MerkleProof { siblings: vec!["zz".to_string()], ..proof };
+    assert!(matches!(verify_merkle_proof(&encode(&txids(2)[0]), &bad_sibling, &root), Err(WalletError::Hex(_))));
+}
+
+#[test]
+fn test_proof_serializes_as_hex() {
+    let proof = MerkleTree::new(&txids(3)).proof(2).unwrap();
+    let json = serde_json::to_value(&proof).unwrap();
+    assert_eq!(json["index"], 2);
+    assert_eq!(json["leaf_count"], 3);
+    assert_eq!(json["siblings"][0].as_str().unwrap().len(), 64);
+    assert_eq!(serde_json::from_value::<MerkleProof>(json).unwrap(), proof);
+}
+
+#[tokio::test]
+async fn test_payment_is_confirmed_against_synced_headers() {
+    let node = MockNode::spawn_on(Network::Regtest).await;
+    node.mine(Vec::new());
+    let payment = coinbase(MINER, 42, 1, 1).unwrap();
+    let txs = vec![coinbase(MINER, 50, 1, 0).unwrap(), payment.clone(), coinbase(MINER, 7, 1, 2).unwrap()];
+    node.mine(txs.clone());
+    let mut chain = HeaderChain::new(Network::Regtest);
+    sync_headers(&NodeClient::with_nodes(Network::Regtest, vec![node.node()]), &mut chain, |_| {}).await.unwrap();
+
+    // the proof comes from whoever has the block; only the header is trusted
+    let tree = MerkleTree::new(&txs.iter().map(|tx| decode(txid(tx).unwrap()).unwrap()).collect::<Vec<_>>());
+    let payment_txid = txid(&payment).unwrap();
+    let proof = tree.proof_for(&decode(&payment_txid).unwrap()).unwrap();
+
+    assert!(chain.verify_inclusion(1, &payment_txid, &proof).unwrap());
+    assert!(!chain.verify_inclusion(0, &payment_txid, &proof).unwrap());
+    assert!(!chain.verify_inclusion(2, &payment_txid, &proof).unwrap());
+}
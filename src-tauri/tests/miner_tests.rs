@@ -0,0 +1,279 @@
+mod support;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use app::address::address_from_public_key;
+use app::api::NodeClient;
+use app::codec::block_hash;
+use app::codec::txid;
+use app::config::Network;
+use app::consensus::ConsensusParams;
+use app::crypto_utils::calculate_merkle_root;
+use app::crypto_utils::generate_key_pair;
+use app::crypto_utils::sign;
+use app::crypto_utils::BlockHeader;
+use app::crypto_utils::Input;
+use app::crypto_utils::Output;
+use app::crypto_utils::Transaction;
+use app::error::WalletError;
+use app::miner::block_template;
+use app::miner::coinbase;
+use app::miner::coinbase_height;
+use app::miner::grind;
+use app::miner::mine_block;
+use app::miner::Miner;
+use app::miner::MiningControl;
+use app::miner::BLOCK_REWARD;
+use app::miner::COINBASE_OUTPUT_INDEX;
+use app::miner::GENESIS_PREV_HASH;
+use app::secret::SecretBytes;
+use app::transaction::sign_transaction;
+use app::transaction::UtxoSet;
+
+use hex::decode;
+use hex::encode;
+
+use support::fixtures::paying;
+use support::mock_node::client_for;
+use support::mock_node::MockNode;
+
+const MINER: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+// the regtest pow limit, which about every other hash meets
+const EASIEST_NBITS: u32 = 0x207fffff;
+// a target no header will meet in a test's lifetime
+const IMPOSSIBLE_NBITS: u32 = 0x0300_0001;
+
+fn header(nbits: u32) -> BlockHeader {
+    BlockHeader {
+        version: 1,
+        prev_hash: GENESIS_PREV_HASH.to_string(),
+        merkle_root: "ab".repeat(32),
+        timestamp: 1_700_000_000,
+        nbits,
+        nonce: 0,
+        hash: None,
+    }
+}
+
+// `private_key` spending output 0 of `prev_txid`, which holds `amount`, to pay `to` `pay`
+fn spend(private_key: &SecretBytes, network: Network, prev_txid: &str, amount: u64, to: &str, pay: u64) -> Transaction {
+    let mut tx = Transaction {
+        version: 1,
+        inputs: vec![Input::unsigned(prev_txid, 0, amount)],
+        outputs: vec![Output { amount: pay, address: to.to_string() }],
+        txid: None,
+    };
+    sign_transaction(&mut tx, network, |digest| sign(private_key, digest)).unwrap();
+    tx
+}
+
+#[test]
+fn test_coinbase_pays_the_address() {
+    let tx = coinbase(MINER, 50, 7, 0).unwrap();
+
+    assert_eq!(tx.inputs.len(), 1);
+    assert_eq!(tx.inputs[0].output_index, COINBASE_OUTPUT_INDEX);
+    assert_eq!(tx.outputs, vec![Output { amount: 50, address: MINER.to_string() }]);
+    assert_eq!(tx.txid, Some(txid(&tx).unwrap()));
+    assert_eq!(coinbase_height(&tx), Some(7));
+    assert_eq!(coinbase_height(&paying(5, 1)), None);
+}
+
+#[test]
+fn test_coinbases_commit_to_height_and_extra_nonce() {
+    let txid_of = |height, extra_nonce| txid(&coinbase(MINER, BLOCK_REWARD, height, extra_nonce).unwrap()).unwrap();
+    assert_ne!(txid_of(1, 0), txid_of(2, 0));
+    assert_ne!(txid_of(1, 0), txid_of(1, 1));
+
+    // the same payout at two heights, as when an address mines twice without fees
+    let template = |prev_headers: &[BlockHeader], height| block_template(prev_headers, height, EASIEST_NBITS, Vec::new(), UtxoSet::new(), Network::Mainnet, MINER, 1_700_000_000, 0).unwrap();
+    let first = template(&[], 0);
+    let second = template(std::slice::from_ref(&first.header), 1);
+    assert_eq!(first.txs[0].outputs, second.txs[0].outputs);
+    assert_ne!(first.txs[0].txid, second.txs[0].txid);
+    assert_ne!(first.header.merkle_root, second.header.merkle_root);
+}
+
+#[test]
+fn test_first_template_starts_the_chain() {
+    let block = block_template(&[], 0, EASIEST_NBITS, Vec::new(), UtxoSet::new(), Network::Mainnet, MINER, 1_700_000_000, 0).unwrap();
+
+    assert_eq!(block.header.prev_hash, GENESIS_PREV_HASH);
+    assert_eq!(block.header.nbits, EASIEST_NBITS);
+    assert_eq!(block.txs, vec![coinbase(MINER, BLOCK_REWARD, 0, 0).unwrap()]);
+    let txids = vec![decode(txid(&block.txs[0]).unwrap()).unwrap()];
+    assert_eq!(block.header.merkle_root, encode(calculate_merkle_root(txids)));
+}
+
+#[test]
+fn test_template_collects_fees() {
+    let mut tip = header(0x1f00ffff);
+    tip.hash = Some("ff".repeat(32));
+    let (private_key, public_key) = generate_key_pair();
+    let owner = address_from_public_key(&public_key, Network::Mainnet).unwrap();
+    let mut utxos = UtxoSet::new();
+    for (prev_txid, amount) in [("aa", 100_004), ("bb", 100_006), ("cc", 100_000)] {
+        utxos.insert(&prev_txid.repeat(32), 0, Output { amount, address: owner.clone() });
+    }
+
+    let first = spend(&private_key, Network::Mainnet, &"aa".repeat(32), 100_004, &owner, 100_000);
+    let second = spend(&private_key, Network::Mainnet, &"bb".repeat(32), 100_006, &owner, 100_000);
+    let child = spend(&private_key, Network::Mainnet, &first.txid.clone().unwrap(), 100_000, &owner, 99_999);
+    // claims more than the output holds, to pass off a fee it does not pay
+    let inflated = spend(&private_key, Network::Mainnet, &"cc".repeat(32), 200_000, &owner, 100_000);
+    let double_spend = spend(&private_key, Network::Mainnet, &"aa".repeat(32), 100_004, &owner, 90_000);
+    let unknown = spend(&private_key, Network::Mainnet, &"dd".repeat(32), 100_000, &owner, 99_000);
+    let mempool = vec![first.clone(), coinbase(MINER, 0, 4, 0).unwrap(), inflated, double_spend, second.clone(), child.clone(), unknown];
+
+    let block = block_template(std::slice::from_ref(&tip), 5, 0x1e00ffff, mempool, utxos, Network::Mainnet, MINER, 1_600_000_000, 3).unwrap();
+
+    // the tip's claimed hash is not trusted
+    assert_eq!(block.header.prev_hash, block_hash(&tip).unwrap());
+    assert_eq!(block.header.nbits, 0x1e00ffff);
+    assert_eq!(block.header.timestamp, tip.timestamp + 1);
+    assert_eq!(block.txs, vec![coinbase(MINER, BLOCK_REWARD + 11, 5, 3).unwrap(), first, second, child]);
+}
+
+#[test]
+fn test_template_is_later_than_the_median_time_past() {
+    let mut prev_headers = vec![header(EASIEST_NBITS); 11];
+    prev_headers[10].timestamp = 1_600_000_000;
+    let block = block_template(&prev_headers, 11, EASIEST_NBITS, Vec::new(), UtxoSet::new(), Network::Mainnet, MINER, 1, 0).unwrap();
+    assert_eq!(block.header.timestamp, 1_700_000_001);
+
+    // a hostile tip cannot make the timestamp overflow
+    let mut tip = header(EASIEST_NBITS);
+    tip.timestamp = u64::MAX;
+    let block = block_template(&[tip], 1, EASIEST_NBITS, Vec::new(), UtxoSet::new(), Network::Mainnet, MINER, 1, 0).unwrap();
+    assert_eq!(block.header.timestamp, u64::MAX);
+}
+
+#[test]
+fn test_grind_finds_a_nonce() {
+    let control = MiningControl::new();
+    let mined = grind(&header(EASIEST_NBITS), 4, &control, Instant::now() + Duration::from_secs(10)).unwrap().unwrap();
+
+    let hash = mined.hash.clone().unwrap();
+    assert_eq!(hash, block_hash(&mined).unwrap());
+    // 0x207fffff allows any hash below 0x7fffff00…
+    assert!(decode(&hash).unwrap()[0] <= 0x7f);
+    assert!(control.hashes() > 0);
+}
+
+#[test]
+fn test_grind_gives_up() {
+    let control = MiningControl::new();
+    let started = Instant::now();
+    assert_eq!(grind(&header(IMPOSSIBLE_NBITS), 2, &control, started + Duration::from_millis(50)).unwrap(), None);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    control.stop();
+    assert_eq!(grind(&header(IMPOSSIBLE_NBITS), 2, &control, Instant::now() + Duration::from_secs(60)).unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_mempool_needs_a_majority() {
+    let cluster = MockNode::spawn_cluster(3).await;
+    for node in &cluster {
+        node.add_to_mempool(paying(5, 1));
+    }
+    cluster[0].add_to_mempool(paying(5, 2));
+    cluster[1].add_to_mempool(paying(5, 3)).add_to_mempool(paying(5, 3));
+    cluster[2].add_to_mempool(paying(5, 3));
+
+    assert_eq!(client_for(&cluster).mempool().await.unwrap(), vec![paying(5, 1), paying(5, 3)]);
+}
+
+#[tokio::test]
+async fn test_mine_block_end_to_end() {
+    // the mock chain is mined at the regtest limit, which mainnet headers may not use
+    let cluster = MockNode::spawn_cluster_on(3, Network::Regtest).await;
+    let (private_key, public_key) = generate_key_pair();
+    let sender = address_from_public_key(&public_key, Network::Regtest).unwrap();
+    for node in &cluster {
+        node.mine(Vec::new());
+        node.fund(&sender, 100_007);
+    }
+    let (funds, _) = client_for(&cluster).get_utxos(&sender).await.unwrap().value;
+    let payment = spend(&private_key, Network::Regtest, &funds[0].prev_txid, 100_007, &sender, 100_000);
+    for node in &cluster {
+        node.add_to_mempool(payment.clone());
+    }
+    let (tip, height) = (cluster[0].tip(), cluster[0].blocks().len() as u64);
+    let miner = address_from_public_key(&generate_key_pair().1, Network::Regtest).unwrap();
+
+    let mined = mine_block(&client_for(&cluster), &miner, 2, &MiningControl::new()).await.unwrap().unwrap();
+
+    assert!(mined.report.is_unanimous());
+    let block = mined.value;
+    assert_eq!(block.header.prev_hash, tip);
+    assert_eq!(block.txs, vec![coinbase(&miner, BLOCK_REWARD + 7, height, 0).unwrap(), payment]);
+    for node in &cluster {
+        assert_eq!(node.blocks().last(), Some(&block));
+    }
+}
+
+#[tokio::test]
+async fn test_first_block_is_mined_at_the_pow_limit() {
+    let node = MockNode::spawn_on(Network::Regtest).await;
+    let miner = address_from_public_key(&generate_key_pair().1, Network::Regtest).unwrap();
+    let client = NodeClient::with_nodes(Network::Regtest, vec![node.node()]);
+
+    let first = mine_block(&client, &miner, 2, &MiningControl::new()).await.unwrap().unwrap().value;
+    let second = mine_block(&client, &miner, 2, &MiningControl::new()).await.unwrap().unwrap().value;
+
+    assert_eq!(first.header.prev_hash, GENESIS_PREV_HASH);
+    assert_eq!(first.header.nbits, ConsensusParams::for_network(Network::Regtest).pow_limit);
+    assert_eq!(second.header.prev_hash, first.header.hash.unwrap());
+    assert_eq!(client.chain_height().await.unwrap().value, 2);
+}
+
+#[tokio::test]
+async fn test_stopped_miner_submits_nothing() {
+    let node = MockNode::spawn().await;
+    let control = MiningControl::new();
+    control.stop();
+
+    let mined = mine_block(&client_for(std::slice::from_ref(&node)), MINER, 1, &control).await.unwrap();
+
+    assert!(mined.is_none());
+    assert!(node.blocks().is_empty());
+}
+
+#[tokio::test]
+async fn test_template_needs_a_valid_address() {
+    let node = MockNode::spawn().await;
+    let result = mine_block(&client_for(std::slice::from_ref(&node)), "cj1notanaddress", 1, &MiningControl::new()).await;
+
+    assert!(matches!(result, Err(WalletError::InvalidAddress(_))));
+}
+
+#[tokio::test]
+async fn test_restart_waits_for_the_stopped_run() {
+    let miner = Miner::default();
+    let finished = Arc::new(AtomicBool::new(false));
+
+    let run_finished = finished.clone();
+    let started = miner
+        .start(|control| async move {
+            while !control.is_stopped() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            // still winding down after the stop
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            run_finished.store(true, Ordering::SeqCst);
+        })
+        .await;
+    assert!(started);
+    assert!(miner.is_running().await);
+    assert!(!miner.start(|_| async {}).await);
+
+    miner.stop().await;
+    assert!(!miner.is_running().await);
+    assert!(miner.start(|_| async {}).await);
+    assert!(finished.load(Ordering::SeqCst));
+}
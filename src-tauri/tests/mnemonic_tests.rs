@@ -0,0 +1,184 @@
+use app::crypto_utils::public_key_from_private;
+use app::error::WalletError;
+use app::hd::ExtendedPrivateKey;
+use app::keystore::create_keystore_from_mnemonic;
+use app::keystore::replace_keystore_from_mnemonic;
+use app::keystore::KdfParams;
+use app::keystore::Keystore;
+use app::mnemonic::generate_mnemonic_phrase;
+use app::mnemonic::mnemonic_from_entropy;
+use app::mnemonic::mnemonic_to_seed;
+use app::mnemonic::parse_mnemonic;
+use app::mnemonic::private_key_from_seed;
+use app::secret::Password;
+
+use hex::decode;
+use hex::encode;
+
+use std::env::temp_dir;
+use std::fs;
+
+// (entropy, mnemonic, seed) from the official BIP39 English vectors, passphrase "TREZOR"
+const VECTORS: [(&str, &str, &str); 10] = [
+    (
+        "00000000000000000000000000000000",
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+    ),
+    (
+        "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+        "legal winner thank year wave sausage worth useful legal winner thank yellow",
+        "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+    ),
+    (
+        "80808080808080808080808080808080",
+        "letter advice cage absurd amount doctor acoustic avoid letter advice cage above",
+        "d71de856f81a8acc65e6fc851a38d4d7ec216fd0796d0a6827a3ad6ed5511a30fa280f12eb2e47ed2ac03b5c462a0358d18d69fe4f985ec81778c1b370b652a8",
+    ),
+    (
+        "ffffffffffffffffffffffffffffffff",
+        "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong",
+        "ac27495480225222079d7be181583751e86f571027b0497b5b5d11218e0a8a13332572917f0f8e5a589620c6f15b11c61dee327651a14c34e18231052e48c069",
+    ),
+    (
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art",
+        "bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd3097170af7a4d73245cafa9c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8",
+    ),
+    (
+        "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+        "legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth title",
+        "bc09fca1804f7e69da93c2f2028eb238c227f2e9dda30cd63699232578480a4021b146ad717fbb7e451ce9eb835f43620bf5c514db0f8add49f5d121449d3e87",
+    ),
+    (
+        "8080808080808080808080808080808080808080808080808080808080808080",
+        "letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic bless",
+        "c0c519bd0e91a2ed54357d9d1ebef6f5af218a153624cf4f2da911a0ed8f7a09e2ef61af0aca007096df430022f7a2b6fb91661a9589097069720d015e4e982f",
+    ),
+    (
+        "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+        "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e1613912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad",
+    ),
+    (
+        "9e885d952ad362caeb4efe34a8e91bd2",
+        "ozone drill grab fiber curtain grace pudding thank cruise elder eight picnic",
+        "274ddc525802f7c828d8ef7ddbcdc5304e87ac3535913611fbbfa986d0c9e5476c91689f9c8a54fd55bd38606aa6a8595ad213d4c9c9f9aca3fb217069a41028",
+    ),
+    (
+        "68a79eaca2324873eacc50cb9c6eca8cc68ea5d936f98787c60c7ebc74e6ce7c",
+        "hamster diagram private dutch cause delay private meat slide toddler razor book happy fancy gospel tennis maple dilemma loan word shrug inflict delay length",
+        "64c87cde7e12ecf6704ab95bb1408bef047c22db4cc7491c4271d170a1b213d20b385bc1588d9c7b38f1b39d415665b8a9030c9ec653d75e65f847d8fc1fc440",
+    ),
+];
+
+#[test]
+fn test_bip39_vectors() {
+    for (entropy, phrase, seed) in VECTORS {
+        let generated = mnemonic_from_entropy(&decode(entropy).unwrap()).unwrap();
+        assert_eq!(generated.as_str(), phrase);
+
+        let derived = mnemonic_to_seed(phrase, &Password::from("TREZOR")).unwrap();
+        assert_eq!(encode(derived.expose()), seed);
+    }
+}
+
+#[test]
+fn test_generate_mnemonic_word_counts() {
+    for word_count in [12, 24] {
+        let phrase = generate_mnemonic_phrase(word_count).unwrap();
+        assert_eq!(phrase.split_whitespace().count(), word_count);
+        assert!(parse_mnemonic(&phrase).is_ok());
+    }
+
+    assert!(matches!(generate_mnemonic_phrase(15), Err(WalletError::InvalidMnemonic(_))));
+}
+
+#[test]
+fn test_generated_mnemonics_differ() {
+    assert_ne!(generate_mnemonic_phrase(12).unwrap(), generate_mnemonic_phrase(12).unwrap());
+}
+
+#[test]
+fn test_mnemonic_rejects_bad_checksum() {
+    let result = mnemonic_to_seed("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon", &Password::from(""));
+    assert!(matches!(result, Err(WalletError::InvalidMnemonic(_))));
+}
+
+#[test]
+fn test_mnemonic_rejects_unknown_word() {
+    let result = parse_mnemonic("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon cjcoin");
+    assert!(matches!(result, Err(WalletError::InvalidMnemonic(_))));
+}
+
+#[test]
+fn test_mnemonic_ignores_case_and_whitespace() {
+    let (_, phrase, seed) = VECTORS[0];
+    let messy = format!("  {}\n", phrase.to_uppercase().replace(' ', "   "));
+
+    let derived = mnemonic_to_seed(&messy, &Password::from("TREZOR")).unwrap();
+    assert_eq!(encode(derived.expose()), seed);
+}
+
+#[test]
+fn test_passphrase_changes_seed() {
+    let (_, phrase, _) = VECTORS[0];
+    let with = mnemonic_to_seed(phrase, &Password::from("TREZOR")).unwrap();
+    let without = mnemonic_to_seed(phrase, &Password::from("")).unwrap();
+
+    assert_ne!(with.expose(), without.expose());
+}
+
+#[test]
+fn test_private_key_from_seed_is_first_receive_key() {
+    let seed = mnemonic_to_seed(VECTORS[0].1, &Password::from("")).unwrap();
+    let private_key = private_key_from_seed(&seed).unwrap();
+
+    let expected = ExtendedPrivateKey::from_seed(&seed).unwrap().derive_path(&"m/44'/8133'/0'/0/0".parse().unwrap()).unwrap();
+    assert_eq!(private_key.expose(), expected.private_key().expose());
+}
+
+#[test]
+fn test_restore_from_mnemonic_recreates_keystore() {
+    let phrase = generate_mnemonic_phrase(24).unwrap();
+    let kdf = || KdfParams::pbkdf2_sha256(1_000);
+
+    let first_path = temp_dir().join(format!("cj-mnemonic-{}-a.json", encode(&phrase[..8])));
+    let second_path = temp_dir().join(format!("cj-mnemonic-{}-b.json", encode(&phrase[..8])));
+    let first_path = first_path.to_str().unwrap();
+    let second_path = second_path.to_str().unwrap();
+
+    let public_key = create_keystore_from_mnemonic(first_path, &phrase, &Password::from("extra"), &Password::from("hello"), kdf()).unwrap();
+    let restored = create_keystore_from_mnemonic(second_path, &phrase, &Password::from("extra"), &Password::from("other"), kdf()).unwrap();
+
+    let first = Keystore::load(first_path).unwrap();
+    let second = Keystore::load(second_path).unwrap();
+    fs::remove_file(first_path).unwrap();
+    fs::remove_file(second_path).unwrap();
+
+    assert_eq!(public_key, restored);
+    let private_key = second.decrypt(&Password::from("other")).unwrap();
+    assert_eq!(first.decrypt(&Password::from("hello")).unwrap().expose(), private_key.expose());
+    assert_eq!(public_key_from_private(&private_key).unwrap(), public_key);
+}
+
+#[test]
+fn test_restoring_over_a_keystore_needs_replace() {
+    let (old_phrase, new_phrase) = (generate_mnemonic_phrase(12).unwrap(), generate_mnemonic_phrase(12).unwrap());
+    let kdf = || KdfParams::pbkdf2_sha256(1_000);
+    let path = temp_dir().join(format!("cj-mnemonic-{}-replaced.json", encode(&old_phrase[..8])));
+    let path = path.to_str().unwrap();
+
+    let old_key = create_keystore_from_mnemonic(path, &old_phrase, &Password::from(""), &Password::from("hello"), kdf()).unwrap();
+    let restored = create_keystore_from_mnemonic(path, &new_phrase, &Password::from(""), &Password::from("hello"), kdf());
+    let kept = Keystore::load(path).unwrap();
+    let new_key = replace_keystore_from_mnemonic(path, &new_phrase, &Password::from(""), &Password::from("other"), kdf()).unwrap();
+    let replaced = Keystore::load(path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert!(matches!(restored, Err(WalletError::KeystoreExists(_))));
+    assert_eq!(kept.public_key, old_key);
+    assert_ne!(new_key, old_key);
+    assert_eq!(replaced.public_key, new_key);
+    assert!(replaced.decrypt(&Password::from("other")).is_ok());
+}
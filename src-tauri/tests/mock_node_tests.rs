@@ -0,0 +1,338 @@
+mod support;
+
+use std::time::Duration;
+use std::time::Instant;
+
+use app::address::address_from_public_key;
+use app::api::NodeClient;
+use app::codec::txid;
+use app::config::Network;
+use app::config::NetworkProfile;
+use app::consensus::ConsensusParams;
+use app::crypto_utils::calculate_merkle_root;
+use app::crypto_utils::generate_key_pair;
+use app::crypto_utils::sign;
+use app::crypto_utils::Block;
+use app::crypto_utils::BlockHeader;
+use app::crypto_utils::Transaction;
+use app::error::WalletError;
+use app::miner::grind;
+use app::miner::MiningControl;
+use app::pow::check_proof_of_work;
+use app::quorum::QuorumPolicy;
+use app::secret::SecretBytes;
+use app::transaction::sign_transaction;
+use app::transaction::TransactionBuilder;
+
+use hex::decode;
+use hex::encode;
+use serde_json::Value;
+
+use support::fixtures::utxos;
+use support::mock_node::client_for;
+use support::mock_node::Behavior;
+use support::mock_node::GENESIS_PREV_HASH;
+use support::mock_node::MockNode;
+use support::mock_node::Verdict;
+
+const RECIPIENT: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+
+struct Wallet {
+    private_key: SecretBytes,
+    address: String,
+}
+
+fn wallet() -> Wallet {
+    let (private_key, public_key) = generate_key_pair();
+    let address = address_from_public_key(&public_key, Network::Mainnet).unwrap();
+    Wallet { private_key, address }
+}
+
+fn unmined_block(prev_hash: &str, txs: Vec<Transaction>) -> Block {
+    let txids = txs.iter().map(|tx| decode(txid(tx).unwrap()).unwrap()).collect();
+    Block {
+        header: BlockHeader {
+            version: 1,
+            prev_hash: prev_hash.to_string(),
+            merkle_root: encode(calculate_merkle_root(txids)),
+            timestamp: 1_700_000_000,
+            nbits: ConsensusParams::for_network(Network::Regtest).pow_limit,
+            nonce: 0,
+            hash: None,
+        },
+        txs,
+    }
+}
+
+fn block(prev_hash: &str, txs: Vec<Transaction>) -> Block {
+    let mut block = unmined_block(prev_hash, txs);
+    block.header = grind(&block.header, 1, &MiningControl::new(), Instant::now() + Duration::from_secs(60)).unwrap().unwrap();
+    block
+}
+
+async fn post_tx(node: &MockNode, tx: &Transaction) -> Value {
+    let url = format!("http://{}/validate/tx", node.host());
+    reqwest::Client::new().post(url).json(tx).send().await.unwrap().json().await.unwrap()
+}
+
+async fn post_block(node: &MockNode, block: &Block) -> Value {
+    let url = format!("http://{}/validate/block", node.host());
+    reqwest::Client::new().post(url).json(block).send().await.unwrap().json().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_discovery_finds_the_cluster() {
+    let cluster = MockNode::spawn_cluster(3).await;
+    let client = NodeClient::new(NetworkProfile::new(Network::Mainnet, vec![cluster[1].host()]));
+
+    let nodes = client.nodes().await.unwrap();
+    assert_eq!(nodes, cluster.iter().map(MockNode::node).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn test_send_end_to_end() {
+    let sender = wallet();
+    let cluster = MockNode::spawn_cluster(3).await;
+    for node in &cluster {
+        node.fund(&sender.address, 70).fund(&sender.address, 50);
+    }
+    let client = client_for(&cluster);
+
+    let sent = client
+        .send(&sender.address, 100, RECIPIENT, 5, &TransactionBuilder::default(), |digest| sign(&sender.private_key, digest))
+        .await
+        .unwrap();
+
+    assert!(sent.report.is_unanimous());
+    for node in &cluster {
+        let accepted = node.transactions();
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].txid.as_deref(), Some(sent.value.as_str()));
+    }
+
+    // the payment and the change are now in the mempool
+    let recipient = client.get_balance(RECIPIENT).await.unwrap().value;
+    assert_eq!((recipient.confirmed, recipient.unconfirmed), (0, 100));
+    let change = client.get_balance(&sender.address).await.unwrap().value;
+    assert_eq!((change.confirmed, change.unconfirmed), (0, 15));
+
+    for node in &cluster {
+        node.confirm_mempool();
+    }
+    assert_eq!(client.get_balance(RECIPIENT).await.unwrap().value.confirmed, 100);
+}
+
+#[tokio::test]
+async fn test_wallet_verifies_before_broadcasting() {
+    let sender = wallet();
+    let impostor = wallet();
+    let node = MockNode::spawn().await;
+    node.fund(&sender.address, 40);
+
+    let result = client_for(std::slice::from_ref(&node))
+        .send(&sender.address, 30, RECIPIENT, 1, &TransactionBuilder::default(), |digest| sign(&impostor.private_key, digest))
+        .await;
+
+    assert!(matches!(result, Err(WalletError::InvalidTransaction(_))));
+    assert!(!node.request_log().contains(&"/validate/tx".to_string()));
+}
+
+#[tokio::test]
+async fn test_nodes_reject_bad_signatures() {
+    let sender = wallet();
+    let impostor = wallet();
+    let node = MockNode::spawn().await;
+    node.fund(&sender.address, 40);
+    let (funds, _) = client_for(std::slice::from_ref(&node)).get_utxos(&sender.address).await.unwrap().value;
+
+    let mut tx = TransactionBuilder::default().build(&funds, &[], 30, RECIPIENT, 1, &sender.address).unwrap();
+    sign_transaction(&mut tx, Network::Mainnet, |digest| sign(&impostor.private_key, digest)).unwrap();
+    let response = post_tx(&node, &tx).await;
+
+    assert_eq!(response["valid"], false);
+    assert!(response["reason"].as_str().unwrap().contains("public key does not belong"));
+    assert!(node.transactions().is_empty());
+}
+
+#[tokio::test]
+async fn test_nodes_reject_double_spends() {
+    let sender = wallet();
+    let node = MockNode::spawn().await;
+    node.fund(&sender.address, 40);
+    let client = client_for(std::slice::from_ref(&node));
+    let (stale, _) = client.get_utxos(&sender.address).await.unwrap().value;
+
+    client.send(&sender.address, 39, RECIPIENT, 1, &TransactionBuilder::default(), |digest| sign(&sender.private_key, digest)).await.unwrap();
+
+    // a stale view of the chain makes the wallet pick the spent output again
+    node.respond(&format!("/utxos/address/{}", sender.address), stale);
+    let result = client.send(&sender.address, 39, RECIPIENT, 1, &TransactionBuilder::default(), |digest| sign(&sender.private_key, digest)).await;
+
+    assert!(matches!(result, Err(WalletError::Rejected(_))));
+    assert_eq!(node.transactions().len(), 1);
+}
+
+#[tokio::test]
+async fn test_lying_minority_is_outvoted() {
+    let cluster = MockNode::spawn_cluster(3).await;
+    for node in &cluster[..2] {
+        node.set_utxos(RECIPIENT, utxos(&[8]), Vec::new());
+    }
+    cluster[2].set_utxos(RECIPIENT, utxos(&[8, 1_000_000]), Vec::new());
+
+    // ask the liar first so its answer is counted before the quorum is reached
+    let nodes = vec![cluster[2].node(), cluster[0].node(), cluster[1].node()];
+    let client = NodeClient::with_nodes(Network::Mainnet, nodes).with_quorum_policy(QuorumPolicy::default().with_max_concurrent(1));
+    let balance = client.get_balance(RECIPIENT).await.unwrap();
+
+    assert_eq!(balance.value.confirmed, 8);
+    assert_eq!(balance.report.dissenting, vec![cluster[2].node()]);
+}
+
+#[tokio::test]
+async fn test_broadcast_waits_for_every_node() {
+    let sender = wallet();
+    let cluster = MockNode::spawn_cluster(3).await;
+    for node in &cluster {
+        node.fund(&sender.address, 40);
+    }
+    cluster[2].set_behavior(Behavior::Delay(Duration::from_millis(300)));
+
+    let client = client_for(&cluster);
+    let (funds, _) = client.get_utxos(&sender.address).await.unwrap().value;
+    let mut tx = TransactionBuilder::default().build(&funds, &[], 30, RECIPIENT, 1, &sender.address).unwrap();
+    sign_transaction(&mut tx, Network::Mainnet, |digest| sign(&sender.private_key, digest)).unwrap();
+    let broadcast = client.broadcast_transaction(&tx).await.unwrap();
+
+    // two fast nodes are a quorum, but the slow one is still waited for
+    assert!(broadcast.value);
+    assert_eq!(broadcast.report.agreeing, cluster.iter().map(MockNode::node).collect::<Vec<_>>());
+    assert!(broadcast.report.skipped.is_empty());
+    for node in &cluster {
+        assert_eq!(node.transactions(), vec![tx.clone()]);
+    }
+}
+
+#[tokio::test]
+async fn test_transactions_must_match_their_txid() {
+    let cluster = MockNode::spawn_cluster(3).await;
+    for node in &cluster {
+        node.fund(RECIPIENT, 70).fund(RECIPIENT, 50);
+    }
+    // ask the liar first so its answer is looked at before the quorum is reached
+    let nodes = vec![cluster[2].node(), cluster[0].node(), cluster[1].node()];
+    let client = NodeClient::with_nodes(Network::Mainnet, nodes).with_quorum_policy(QuorumPolicy::default().with_max_concurrent(1));
+    let (funds, _) = client.get_utxos(RECIPIENT).await.unwrap().value;
+    let (first, second) = (&funds[0].prev_txid, &funds[1].prev_txid);
+
+    let funding = client.transaction(first).await.unwrap();
+    assert_eq!(txid(&funding.value).unwrap(), *first);
+    assert!(funding.report.dissenting.is_empty() && funding.report.unreachable.is_empty());
+
+    // a node answering with another transaction is malformed, not just outvoted
+    let other = client.transaction(second).await.unwrap().value;
+    cluster[2].respond(&format!("/tx/{}", first), other);
+    let funding = client.transaction(first).await.unwrap();
+    assert_eq!(txid(&funding.value).unwrap(), *first);
+    assert_eq!(funding.report.unreachable.len(), 1);
+    assert!(funding.report.unreachable[0].malformed);
+}
+
+#[tokio::test]
+async fn test_split_cluster_has_no_quorum() {
+    let cluster = MockNode::spawn_cluster(2).await;
+    cluster[0].set_verdict(Verdict::AcceptAll);
+    cluster[1].set_verdict(Verdict::RejectAll);
+
+    let tx = Transaction {
+        version: 1,
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        txid: None,
+    };
+    let result = client_for(&cluster).broadcast_transaction(&tx).await;
+
+    assert!(matches!(result, Err(WalletError::NoQuorum { agreeing: 0, responded: 2, .. })));
+}
+
+#[tokio::test]
+async fn test_blocks_must_extend_the_tip() {
+    let node = MockNode::spawn_on(Network::Regtest).await;
+
+    let first = block(GENESIS_PREV_HASH, Vec::new());
+    assert_eq!(post_block(&node, &first).await["valid"], true);
+    let tip = node.tip();
+    assert_ne!(tip, GENESIS_PREV_HASH);
+
+    assert_eq!(post_block(&node, &block(GENESIS_PREV_HASH, Vec::new())).await["valid"], false);
+    assert_eq!(post_block(&node, &block(&tip, Vec::new())).await["valid"], true);
+    assert_eq!(node.blocks().len(), 2);
+}
+
+#[tokio::test]
+async fn test_blocks_must_be_mined() {
+    let node = MockNode::spawn_on(Network::Regtest).await;
+    let mut unmined = unmined_block(GENESIS_PREV_HASH, Vec::new());
+    // the first nonce whose hash misses the target
+    while check_proof_of_work(&unmined.header).is_ok() {
+        unmined.header.nonce += 1;
+    }
+
+    let response = post_block(&node, &unmined).await;
+
+    assert_eq!(response["valid"], false);
+    assert!(response["reason"].as_str().unwrap().contains("above the target"));
+    assert!(node.blocks().is_empty());
+}
+
+#[tokio::test]
+async fn test_mock_mines_valid_blocks() {
+    let node = MockNode::spawn_on(Network::Regtest).await;
+    node.mine(Vec::new());
+    node.mine(Vec::new());
+
+    for block in node.blocks() {
+        check_proof_of_work(&block.header).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_blocks_must_follow_the_difficulty_rules() {
+    let node = MockNode::spawn().await;
+
+    // the first mainnet block must be mined at the mainnet pow limit, not regtest's
+    let response = post_block(&node, &block(GENESIS_PREV_HASH, Vec::new())).await;
+
+    assert_eq!(response["valid"], false);
+    assert!(response["reason"].as_str().unwrap().contains("the rules require 0x1f00ffff"));
+}
+
+#[tokio::test]
+async fn test_blocks_must_commit_to_their_transactions() {
+    let node = MockNode::spawn_on(Network::Regtest).await;
+    let tx = Transaction {
+        version: 1,
+        inputs: utxos(&[1]),
+        outputs: Vec::new(),
+        txid: None,
+    };
+
+    let mut tampered = block(GENESIS_PREV_HASH, vec![tx.clone()]);
+    tampered.txs[0].version = 2;
+    let response = post_block(&node, &tampered).await;
+
+    assert_eq!(response["valid"], false);
+    assert_eq!(response["reason"], "merkle root does not match");
+    assert_eq!(post_block(&node, &block(GENESIS_PREV_HASH, vec![tx])).await["valid"], true);
+}
+
+#[tokio::test]
+async fn test_request_log() {
+    let node = MockNode::spawn().await;
+    client_for(std::slice::from_ref(&node)).get_utxos(RECIPIENT).await.unwrap();
+
+    assert_eq!(
+        node.request_log(),
+        vec![format!("/utxos/address/{}", RECIPIENT), format!("/utxos_mempool/address/{}", RECIPIENT)]
+    );
+}
@@ -0,0 +1,160 @@
+mod support;
+
+use app::api::NodeClient;
+use app::api::RetryPolicy;
+use app::config::Network;
+use app::error::WalletError;
+
+use std::time::Duration;
+use std::time::Instant;
+
+use support::fixtures::utxos;
+use support::mock_node::Behavior;
+use support::mock_node::MockNode;
+
+const ADDRESS: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+
+async fn node_with_utxos(confirmed: &[u64], mempool: &[u64]) -> MockNode {
+    let node = MockNode::spawn().await;
+    node.set_utxos(ADDRESS, utxos(confirmed), utxos(mempool));
+    node
+}
+
+fn fast_retries(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(40),
+    }
+}
+
+#[tokio::test]
+async fn test_nodes_are_queried_concurrently() {
+    let mut mocks = Vec::new();
+    for _ in 0..3 {
+        let mock = node_with_utxos(&[10], &[]).await;
+        mock.set_behavior(Behavior::Delay(Duration::from_millis(400)));
+        mocks.push(mock);
+    }
+    let client = NodeClient::with_nodes(Network::Mainnet, mocks.iter().map(MockNode::node).collect());
+
+    let started = Instant::now();
+    let balance = client.get_balance(ADDRESS).await.unwrap();
+
+    // two requests per node; one after another this would take at least 2.4s
+    assert!(started.elapsed() < Duration::from_millis(1600), "took {:?}", started.elapsed());
+    assert_eq!(balance.value.confirmed, 10);
+}
+
+#[tokio::test]
+async fn test_balance_overflow_is_an_error() {
+    let node = node_with_utxos(&[u64::MAX, 1], &[]).await;
+    let client = NodeClient::with_nodes(Network::Mainnet, vec![node.node()]);
+
+    let result = client.get_balance(ADDRESS).await;
+
+    assert!(matches!(result, Err(WalletError::InvalidAmount(_))), "{:?}", result);
+}
+
+#[tokio::test]
+async fn test_hanging_node_times_out() {
+    let good = node_with_utxos(&[5, 7], &[1]).await;
+    let hanging = node_with_utxos(&[5, 7], &[1]).await;
+    hanging.set_behavior(Behavior::Hang);
+
+    let client = NodeClient::with_nodes(Network::Mainnet, vec![good.node(), hanging.node()])
+        .with_request_timeout(Duration::from_millis(300))
+        .with_retry_policy(RetryPolicy::none());
+
+    let started = Instant::now();
+    let balance = client.get_balance(ADDRESS).await.unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(3), "took {:?}", started.elapsed());
+    assert_eq!((balance.value.confirmed, balance.value.unconfirmed), (12, 1));
+    assert_eq!(balance.report.agreeing, vec![good.node()]);
+    assert_eq!(balance.report.unreachable.len(), 1);
+    assert_eq!(balance.report.unreachable[0].node, hanging.node());
+}
+
+#[tokio::test]
+async fn test_transient_failures_are_retried() {
+    let flaky = node_with_utxos(&[3], &[]).await;
+    flaky.fail_next(2);
+
+    let client = NodeClient::with_nodes(Network::Mainnet, vec![flaky.node()]).with_retry_policy(fast_retries(3));
+    let (confirmed, mempool) = client.get_utxos(ADDRESS).await.unwrap().value;
+
+    assert_eq!((confirmed, mempool), (utxos(&[3]), Vec::new()));
+    // two failed attempts, then one request for each list
+    assert_eq!(flaky.requests(), 4);
+}
+
+#[tokio::test]
+async fn test_retries_give_up_after_max_attempts() {
+    let broken = node_with_utxos(&[3], &[]).await;
+    broken.set_behavior(Behavior::Fail(500));
+
+    let client = NodeClient::with_nodes(Network::Mainnet, vec![broken.node()]).with_retry_policy(fast_retries(3));
+    let result = client.get_utxos(ADDRESS).await;
+
+    assert!(matches!(result, Err(WalletError::NoQuorum { responded: 0, unreachable: 1, .. })));
+    assert_eq!(broken.requests(), 3);
+}
+
+#[tokio::test]
+async fn test_client_errors_are_not_retried() {
+    let node = MockNode::spawn().await;
+    node.set_behavior(Behavior::Fail(404));
+    let client = NodeClient::with_nodes(Network::Mainnet, vec![node.node()]).with_retry_policy(fast_retries(3));
+
+    assert!(client.get_utxos(ADDRESS).await.is_err());
+    assert_eq!(node.requests(), 1);
+}
+
+#[tokio::test]
+async fn test_quorum_cancels_remaining_requests() {
+    let first = node_with_utxos(&[9], &[]).await;
+    let second = node_with_utxos(&[9], &[]).await;
+    let hanging = node_with_utxos(&[9], &[]).await;
+    hanging.set_behavior(Behavior::Hang);
+
+    let client = NodeClient::with_nodes(Network::Mainnet, vec![first.node(), hanging.node(), second.node()]);
+
+    let started = Instant::now();
+    let balance = client.get_balance(ADDRESS).await.unwrap();
+
+    // two of three agreeing decides it, long before the 10s timeout
+    assert!(started.elapsed() < Duration::from_secs(3), "took {:?}", started.elapsed());
+    assert_eq!(balance.report.agreeing, vec![first.node(), second.node()]);
+    assert_eq!(balance.report.skipped, vec![hanging.node()]);
+    assert!(balance.report.is_unanimous());
+}
+
+#[tokio::test]
+async fn test_discovery_falls_back_to_next_seed_node() {
+    let dead = MockNode::spawn().await;
+    dead.set_behavior(Behavior::Fail(503));
+    let seed = MockNode::spawn().await;
+    let peer = node_with_utxos(&[21], &[]).await;
+    seed.set_peers(vec![peer.node()]);
+
+    let profile = app::config::NetworkProfile::new(Network::Regtest, vec![dead.host(), seed.host()]);
+    let client = NodeClient::new(profile).with_retry_policy(fast_retries(2));
+
+    assert_eq!(client.nodes().await.unwrap(), vec![peer.node()]);
+    assert_eq!(client.get_balance(ADDRESS).await.unwrap().value.confirmed, 21);
+    assert_eq!(dead.requests(), 2);
+}
+
+#[test]
+fn test_backoff_doubles_up_to_cap() {
+    let retry = RetryPolicy {
+        max_attempts: 6,
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_millis(500),
+    };
+
+    let waits: Vec<u128> = (0..5).map(|n| retry.backoff(n).as_millis()).collect();
+    assert_eq!(waits, vec![100, 200, 400, 500, 500]);
+    assert_eq!(retry.backoff(u32::MAX), Duration::from_millis(500));
+}
@@ -0,0 +1,286 @@
+mod support;
+
+use app::api::NodeClient;
+use app::config::Network;
+use app::config::NetworkProfile;
+use app::crypto_utils::Node;
+use app::error::WalletError;
+use app::peers::PeerStore;
+use app::peers::BAN_DURATION_SECS;
+use app::peers::BAN_STRIKES;
+use app::peers::MAX_PEERS;
+
+use std::env::temp_dir;
+use std::fs;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::join_all;
+
+use support::fixtures::node;
+use support::fixtures::utxos;
+use support::mock_node::Behavior;
+use support::mock_node::MockNode;
+
+const ADDRESS: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+const NOW: u64 = 1_700_000_000;
+
+#[test]
+fn test_ranking_prefers_healthy_peers() {
+    let mut store = PeerStore::in_memory();
+    store.add_all(&[node(1), node(2), node(3)]);
+
+    store.record_failure(&node(1), false, NOW);
+    store.record_success(&node(3), 40, NOW);
+
+    assert_eq!(store.ranked(NOW), vec![node(3), node(2), node(1)]);
+}
+
+#[test]
+fn test_slow_peers_rank_lower() {
+    let mut store = PeerStore::in_memory();
+    store.record_success(&node(1), 900, NOW);
+    store.record_success(&node(2), 20, NOW);
+
+    assert_eq!(store.ranked(NOW), vec![node(2), node(1)]);
+}
+
+#[test]
+fn test_latency_is_smoothed() {
+    let mut store = PeerStore::in_memory();
+    store.record_success(&node(1), 100, NOW);
+    store.record_success(&node(1), 200, NOW + 1);
+
+    let record = store.get(&node(1)).unwrap();
+    assert_eq!(record.latency_ms, Some(130));
+    assert_eq!(record.successes, 2);
+    assert_eq!(record.last_seen, Some(NOW + 1));
+}
+
+#[test]
+fn test_repeated_disagreement_bans() {
+    let mut store = PeerStore::in_memory();
+    for _ in 0..BAN_STRIKES - 1 {
+        store.record_disagreement(&node(1), NOW);
+    }
+    assert!(!store.get(&node(1)).unwrap().is_banned(NOW));
+
+    store.record_disagreement(&node(1), NOW);
+    assert!(store.get(&node(1)).unwrap().is_banned(NOW));
+    assert!(store.ranked(NOW).is_empty());
+
+    // bans expire
+    assert_eq!(store.ranked(NOW + BAN_DURATION_SECS), vec![node(1)]);
+}
+
+#[test]
+fn test_bans_survive_a_flood_of_new_peers() {
+    let mut store = PeerStore::in_memory();
+    for _ in 0..BAN_STRIKES {
+        store.record_disagreement(&node(1), NOW);
+    }
+
+    let flood: Vec<Node> = (2..MAX_PEERS as u16 + 12).map(node).collect();
+    store.add_all(&flood);
+    store.add_all(&[node(1)]);
+
+    assert_eq!(store.records().len(), MAX_PEERS);
+    assert!(store.get(&node(1)).unwrap().is_banned(NOW));
+    assert!(!store.ranked(NOW).contains(&node(1)));
+}
+
+#[test]
+fn test_agreement_resets_strikes() {
+    let mut store = PeerStore::in_memory();
+    for _ in 0..BAN_STRIKES * 2 {
+        store.record_disagreement(&node(1), NOW);
+        store.record_agreement(&node(1));
+    }
+    assert!(!store.get(&node(1)).unwrap().is_banned(NOW));
+}
+
+#[test]
+fn test_malformed_answers_strike_but_timeouts_do_not() {
+    let mut store = PeerStore::in_memory();
+    for _ in 0..BAN_STRIKES {
+        store.record_failure(&node(1), false, NOW);
+        store.record_failure(&node(2), true, NOW);
+    }
+
+    assert!(!store.get(&node(1)).unwrap().is_banned(NOW));
+    assert!(store.get(&node(2)).unwrap().is_banned(NOW));
+    assert_eq!(store.get(&node(2)).unwrap().malformed, BAN_STRIKES);
+}
+
+#[test]
+fn test_unban() {
+    let mut store = PeerStore::in_memory();
+    for _ in 0..BAN_STRIKES {
+        store.record_disagreement(&node(1), NOW);
+    }
+    store.unban(&node(1));
+    assert_eq!(store.ranked(NOW), vec![node(1)]);
+}
+
+#[test]
+fn test_unban_ignores_unknown_nodes() {
+    let mut store = PeerStore::in_memory();
+    store.record_success(&node(1), 50, NOW);
+    store.unban(&node(2));
+
+    assert!(store.get(&node(2)).is_none());
+    assert_eq!(store.records().len(), 1);
+}
+
+#[test]
+fn test_store_round_trip() {
+    let path = temp_dir().join("cj-peers-round-trip").join("peers-mainnet.json");
+    let mut store = PeerStore::load(&path).unwrap();
+    assert!(store.records().is_empty());
+
+    store.add_all(&[node(1), node(2)]);
+    store.record_success(&node(2), 55, NOW);
+    store.record_disagreement(&node(1), NOW);
+    store.save().unwrap();
+
+    let loaded = PeerStore::load(&path).unwrap();
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    assert_eq!(loaded.records(), store.records());
+}
+
+#[tokio::test]
+async fn test_minority_node_gets_banned() {
+    let cluster = MockNode::spawn_cluster(3).await;
+    for node in &cluster[..2] {
+        node.set_utxos(ADDRESS, utxos(&[4]), Vec::new());
+        // the liar answers first, so its answer is counted before the quorum is decided
+        node.set_behavior(Behavior::Delay(Duration::from_millis(50)));
+    }
+    cluster[2].set_utxos(ADDRESS, utxos(&[4, 4]), Vec::new());
+
+    let nodes = cluster.iter().map(MockNode::node).collect();
+    let client = NodeClient::with_nodes(Network::Mainnet, nodes);
+
+    for _ in 0..BAN_STRIKES {
+        assert_eq!(client.get_balance(ADDRESS).await.unwrap().value.confirmed, 4);
+    }
+
+    assert_eq!(client.nodes().await.unwrap(), vec![cluster[0].node(), cluster[1].node()]);
+    let liar = client.peer_records().into_iter().find(|record| record.node == cluster[2].node()).unwrap();
+    assert_eq!(liar.disagreements, BAN_STRIKES);
+
+    let before = cluster[2].requests();
+    client.get_balance(ADDRESS).await.unwrap();
+    assert_eq!(cluster[2].requests(), before);
+
+    client.unban_peer(&cluster[2].node());
+    assert_eq!(client.nodes().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_malformed_answers_are_recorded() {
+    let good = MockNode::spawn().await;
+    let garbage = MockNode::spawn().await;
+    garbage.respond(&format!("/utxos/address/{}", ADDRESS), "not a utxo list");
+
+    let client = NodeClient::with_nodes(Network::Mainnet, vec![good.node(), garbage.node()]);
+    let result = client.get_utxos(ADDRESS).await.unwrap();
+
+    assert!(result.report.unreachable[0].malformed);
+    let record = client.peer_records().into_iter().find(|record| record.node == garbage.node()).unwrap();
+    assert_eq!((record.failures, record.malformed, record.strikes), (1, 1, 1));
+}
+
+#[tokio::test]
+async fn test_cached_peers_survive_a_dead_seed() {
+    let dir = temp_dir().join(format!("cj-peers-fallback-{}", std::process::id()));
+    let seed = MockNode::spawn().await;
+    let peer = MockNode::spawn().await;
+    peer.set_utxos(ADDRESS, utxos(&[6]), Vec::new());
+    seed.set_peers(vec![peer.node()]);
+
+    let profile = NetworkProfile::new(Network::Mainnet, vec![seed.host()]);
+    let first_run = NodeClient::new(profile.clone()).with_peer_dir(dir.clone());
+    assert_eq!(first_run.get_balance(ADDRESS).await.unwrap().value.confirmed, 6);
+    assert!(dir.join("peers-mainnet.json").exists());
+
+    // next start: the seed is down, but the peer learned last time still answers
+    seed.set_behavior(Behavior::Fail(503));
+    let second_run = NodeClient::new(profile).with_peer_dir(dir.clone()).with_retry_policy(app::api::RetryPolicy::none());
+    assert_eq!(second_run.get_balance(ADDRESS).await.unwrap().value.confirmed, 6);
+
+    let record = second_run.peer_records().into_iter().find(|record| record.node == peer.node()).unwrap();
+    assert_eq!(record.successes, 2);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_peer_store_errors_are_kept_for_the_ui() {
+    let dir = temp_dir().join(format!("cj-peers-errors-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("peers-mainnet.json"), "not json").unwrap();
+
+    let client = NodeClient::new(NetworkProfile::new(Network::Mainnet, Vec::new())).with_peer_dir(dir.clone());
+    assert!(client.peer_store_error().unwrap().contains("starting with no cached peers"));
+
+    // switching profiles reads the other network's peers afresh
+    client.set_profile(NetworkProfile::new(Network::Testnet, Vec::new())).await;
+    assert_eq!(client.peer_store_error(), None);
+
+    // a directory where the peers file should be makes every save fail
+    fs::create_dir_all(dir.join("peers-testnet.json")).unwrap();
+    client.unban_peer(&node(1));
+    assert!(client.peer_store_error().unwrap().contains("could not save peers"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_profiles_keep_separate_peers() {
+    let dir = temp_dir().join(format!("cj-peers-profiles-{}", std::process::id()));
+    let mainnet_seed = MockNode::spawn().await;
+    let testnet_seed = MockNode::spawn_on(Network::Testnet).await;
+
+    let client = NodeClient::new(NetworkProfile::new(Network::Mainnet, vec![mainnet_seed.host()])).with_peer_dir(dir.clone());
+    assert_eq!(client.nodes().await.unwrap(), vec![mainnet_seed.node()]);
+
+    client.set_profile(NetworkProfile::new(Network::Testnet, vec![testnet_seed.host()])).await;
+    assert_eq!(client.nodes().await.unwrap(), vec![testnet_seed.node()]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_unchanged_profile_keeps_its_peers() {
+    let seed = MockNode::spawn().await;
+    let profile = NetworkProfile::new(Network::Mainnet, vec![seed.host()]);
+    let client = NodeClient::new(profile.clone());
+    client.nodes().await.unwrap();
+
+    // as when seed nodes are set for a network other than the selected one
+    assert_eq!(client.update_profile(|| Ok(profile.clone())).await.unwrap(), profile);
+    client.nodes().await.unwrap();
+    assert_eq!(seed.request_log().iter().filter(|path| *path == "/discover/nodes").count(), 1);
+
+    let failed = client.update_profile(|| Err(WalletError::Config("could not save".to_string()))).await;
+    assert!(failed.is_err());
+    assert_eq!(client.network().await, Network::Mainnet);
+}
+
+#[tokio::test]
+async fn test_concurrent_updates_end_on_the_last_saved_profile() {
+    let client = NodeClient::new(NetworkProfile::new(Network::Mainnet, Vec::new()));
+    let saved = Mutex::new(Network::Mainnet);
+
+    let updates = (0..20).map(|index| {
+        let network = if index % 2 == 0 { Network::Testnet } else { Network::Regtest };
+        let saved = &saved;
+        client.update_profile(move || {
+            *saved.lock().unwrap() = network;
+            Ok(NetworkProfile::new(network, Vec::new()))
+        })
+    });
+    join_all(updates).await;
+
+    assert_eq!(client.network().await, *saved.lock().unwrap());
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use app::crypto_utils::BlockHeader;
+use app::error::WalletError;
+use app::miner::grind;
+use app::miner::MiningControl;
+use app::miner::GENESIS_PREV_HASH;
+use app::pow::block_work;
+use app::pow::check_proof_of_work;
+use app::pow::hash_meets_target;
+use app::pow::nbits_from_target;
+use app::pow::target_from_nbits;
+use app::pow::U256;
+
+// the regtest pow limit, which about every other hash meets
+const EASIEST_NBITS: u32 = 0x207fffff;
+
+fn u256(hex: &str) -> U256 {
+    let mut bytes = [0u8; 32];
+    let decoded = hex::decode(format!("{:0>64}", hex)).unwrap();
+    bytes.copy_from_slice(&decoded);
+    U256::from_be_bytes(bytes)
+}
+
+fn mined_header() -> BlockHeader {
+    let header = BlockHeader {
+        version: 1,
+        prev_hash: GENESIS_PREV_HASH.to_string(),
+        merkle_root: "ab".repeat(32),
+        timestamp: 1_700_000_000,
+        nbits: EASIEST_NBITS,
+        nonce: 0,
+        hash: None,
+    };
+    grind(&header, 1, &MiningControl::new(), Instant::now() + Duration::from_secs(60)).unwrap().unwrap()
+}
+
+#[test]
+fn test_u256_arithmetic() {
+    let value = u256("0123456789abcdef00112233445566778899aabbccddeeff0f1e2d3c4b5a6978");
+    assert_eq!(U256::from_be_bytes(value.to_be_bytes()), value);
+    assert_eq!((value << 72) >> 72, u256("112233445566778899aabbccddeeff0f1e2d3c4b5a6978"));
+    assert_eq!(value >> 256, U256::ZERO);
+    assert_eq!(U256::ONE << 255 >> 255, U256::ONE);
+    assert_eq!(value.bits(), 249);
+    assert_eq!(U256::ZERO.bits(), 0);
+
+    assert_eq!(U256::from(100) / U256::from(7), U256::from(14));
+    assert_eq!((U256::ONE << 200) / (U256::ONE << 100), U256::ONE << 100);
+    assert_eq!(U256::MAX / U256::ONE, U256::MAX);
+    assert_eq!(value.checked_div(U256::ZERO), None);
+    assert_eq!(U256::MAX.checked_add(U256::ONE), None);
+    assert_eq!(U256::ZERO.checked_sub(U256::ONE), None);
+    assert_eq!(u256("ffffffffffffffff").checked_add(U256::ONE), Some(U256::ONE << 64));
+    assert!(U256::ONE << 64 > U256::from(u64::MAX));
+}
+
+#[test]
+fn test_nbits_vectors() {
+    // nbits, target, re-encoded nbits
+    let cases = [
+        (0x00000000, "0", 0x00000000),
+        (0x00123456, "0", 0x00000000),
+        (0x01003456, "0", 0x00000000),
+        (0x02000056, "0", 0x00000000),
+        (0x03000000, "0", 0x00000000),
+        (0x04000000, "0", 0x00000000),
+        (0x00923456, "0", 0x00000000),
+        (0x01803456, "0", 0x00000000),
+        (0x02800056, "0", 0x00000000),
+        (0x03800000, "0", 0x00000000),
+        (0x04800000, "0", 0x00000000),
+        (0x01123456, "12", 0x01120000),
+        (0x02123456, "1234", 0x02123400),
+        (0x03123456, "123456", 0x03123456),
+        (0x04123456, "12345600", 0x04123456),
+        (0x05009234, "92340000", 0x05009234),
+        (0x20123456, "1234560000000000000000000000000000000000000000000000000000000000", 0x20123456),
+        (0x1d00ffff, "ffff0000000000000000000000000000000000000000000000000000", 0x1d00ffff),
+        // the same target has more than one encoding; re-encoding picks the canonical one
+        (0x22000001, "0100000000000000000000000000000000000000000000000000000000000000", 0x20010000),
+    ];
+    for (nbits, target, encoded) in cases {
+        assert_eq!(target_from_nbits(nbits).unwrap(), u256(target), "{:#010x}", nbits);
+        assert_eq!(nbits_from_target(u256(target)), encoded, "{:#010x}", nbits);
+    }
+}
+
+#[test]
+fn test_negative_and_overflowing_nbits() {
+    for nbits in [0x01fedcba, 0x04923456, 0x1d80ffff] {
+        let error = target_from_nbits(nbits).unwrap_err();
+        assert!(error.to_string().contains("negative"), "{:#010x}", nbits);
+    }
+    for nbits in [0xff123456, 0x23000001, 0x21010000, 0x22000100] {
+        let error = target_from_nbits(nbits).unwrap_err();
+        assert!(error.to_string().contains("past 256 bits"), "{:#010x}", nbits);
+    }
+    assert!(target_from_nbits(0x2100ffff).is_ok());
+}
+
+#[test]
+fn test_nbits_from_target_avoids_the_sign_bit() {
+    assert_eq!(nbits_from_target(u256("80")), 0x02008000);
+    assert_eq!(nbits_from_target(u256("800000")), 0x04008000);
+    assert_eq!(target_from_nbits(0x02008000).unwrap(), u256("80"));
+    // precision past 3 bytes is dropped
+    assert_eq!(nbits_from_target(u256("12345678")), 0x04123456);
+}
+
+#[test]
+fn test_block_work() {
+    // Bitcoin's genesis block proves 0x100010001 hashes
+    assert_eq!(block_work(0x1d00ffff).unwrap(), u256("0100010001"));
+    assert_eq!(block_work(EASIEST_NBITS).unwrap(), U256::from(2));
+    assert_eq!(block_work(0x03000001).unwrap(), U256::ONE << 255);
+    assert!(block_work(0x1c00ffff).unwrap() > block_work(0x1d00ffff).unwrap());
+    assert!(matches!(block_work(0), Err(WalletError::InvalidBlock(_))));
+    assert!(matches!(block_work(0x04923456), Err(WalletError::InvalidBlock(_))));
+}
+
+#[test]
+fn test_hash_meets_target() {
+    let target = target_from_nbits(0x1d00ffff).unwrap();
+    assert!(hash_meets_target(&format!("00000000ffff{}", "0".repeat(52)), target).unwrap());
+    assert!(!hash_meets_target(&format!("00000000ffff{}1", "0".repeat(51)), target).unwrap());
+    assert!(hash_meets_target("not hex", target).is_err());
+}
+
+#[test]
+fn test_check_proof_of_work() {
+    let mined = mined_header();
+    check_proof_of_work(&mined).unwrap();
+
+    let mut unclaimed = mined.clone();
+    unclaimed.hash = None;
+    check_proof_of_work(&unclaimed).unwrap();
+
+    let mut wrong_hash = mined.clone();
+    wrong_hash.hash = Some("00".repeat(32));
+    assert!(matches!(check_proof_of_work(&wrong_hash), Err(WalletError::InvalidBlock(_))));
+
+    let mut harder = mined;
+    harder.hash = None;
+    harder.nbits = 0x03000001;
+    let error = check_proof_of_work(&harder).unwrap_err();
+    assert!(error.to_string().contains("above the target"));
+}
@@ -0,0 +1,143 @@
+mod support;
+
+use app::crypto_utils::Node;
+use app::error::WalletError;
+use app::quorum::quorum_query;
+use app::quorum::tally;
+use app::quorum::QuorumPolicy;
+
+use futures::executor::block_on;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::time::sleep;
+use serde_json::json;
+use serde_json::Value;
+
+use support::fixtures::node;
+
+fn unreachable() -> Result<Value, WalletError> {
+    Err(WalletError::Network("connection refused".to_string()))
+}
+
+#[test]
+fn test_majority_response_wins() {
+    let responses = vec![
+        (node(1), Ok(json!({"valid": true}))),
+        (node(2), Ok(json!({"valid": false}))),
+        (node(3), Ok(json!({"valid": true}))),
+    ];
+    let result = tally(responses, &QuorumPolicy::default()).unwrap();
+
+    assert_eq!(result.value, json!({"valid": true}));
+    assert_eq!(result.report.agreeing, vec![node(1), node(3)]);
+    assert_eq!(result.report.dissenting, vec![node(2)]);
+    assert!(result.report.unreachable.is_empty());
+    assert!(!result.report.is_unanimous());
+}
+
+#[test]
+fn test_unreachable_nodes_are_tolerated() {
+    let responses = vec![(node(1), Ok(json!(5))), (node(2), unreachable()), (node(3), Ok(json!(5)))];
+    let result = tally(responses, &QuorumPolicy::default()).unwrap();
+
+    assert_eq!(result.value, json!(5));
+    assert_eq!(result.report.unreachable.len(), 1);
+    assert_eq!(result.report.unreachable[0].node, node(2));
+    assert!(result.report.unreachable[0].error.contains("connection refused"));
+}
+
+#[test]
+fn test_unanimous_report() {
+    let result = tally(vec![(node(1), Ok(json!(1))), (node(2), Ok(json!(1)))], &QuorumPolicy::default()).unwrap();
+    assert!(result.report.is_unanimous());
+}
+
+#[test]
+fn test_tie_has_no_quorum() {
+    let responses = vec![(node(1), Ok(json!(1))), (node(2), Ok(json!(2)))];
+    let result = tally(responses, &QuorumPolicy::new(0, 1));
+
+    assert!(matches!(result, Err(WalletError::NoQuorum { agreeing: 0, responded: 2, unreachable: 0 })));
+}
+
+#[test]
+fn test_no_responses_has_no_quorum() {
+    assert!(matches!(tally(Vec::<(Node, Result<Value, WalletError>)>::new(), &QuorumPolicy::default()), Err(WalletError::NoQuorum { .. })));
+    assert!(matches!(
+        tally(vec![(node(1), unreachable())], &QuorumPolicy::default()),
+        Err(WalletError::NoQuorum { responded: 0, unreachable: 1, .. })
+    ));
+}
+
+#[test]
+fn test_threshold_is_enforced() {
+    let responses = || vec![(node(1), Ok(json!(1))), (node(2), Ok(json!(1))), (node(3), Ok(json!(2)))];
+
+    assert!(tally(responses(), &QuorumPolicy::new(66, 1)).is_ok());
+    assert!(matches!(
+        tally(responses(), &QuorumPolicy::new(67, 1)),
+        Err(WalletError::NoQuorum { agreeing: 2, responded: 3, unreachable: 0 })
+    ));
+}
+
+#[test]
+fn test_min_agreeing_is_enforced() {
+    let responses = vec![(node(1), Ok(json!(1))), (node(2), unreachable()), (node(3), unreachable())];
+    assert!(matches!(tally(responses, &QuorumPolicy::new(51, 2)), Err(WalletError::NoQuorum { agreeing: 1, .. })));
+}
+
+#[test]
+fn test_quorum_query_waits_for_an_undecided_outcome() {
+    let nodes = [node(1), node(2), node(3), node(4)];
+    let result = block_on(quorum_query(&nodes, &QuorumPolicy::default(), |node| async move {
+        match node.port {
+            4 => Err(WalletError::Network("timed out".to_string())),
+            port => Ok(port < 3),
+        }
+    }))
+    .unwrap();
+
+    assert!(result.value);
+    assert_eq!(result.report.agreeing, vec![node(1), node(2)]);
+    assert_eq!(result.report.dissenting, vec![node(3)]);
+    assert_eq!(result.report.unreachable.len(), 1);
+    assert!(result.report.skipped.is_empty());
+}
+
+#[test]
+fn test_quorum_query_stops_once_decided() {
+    let asked = AtomicUsize::new(0);
+    let nodes: Vec<Node> = (1..=5).map(node).collect();
+
+    let result = block_on(quorum_query(&nodes, &QuorumPolicy::default().with_max_concurrent(1), |_| {
+        asked.fetch_add(1, Ordering::SeqCst);
+        async { Ok(true) }
+    }))
+    .unwrap();
+
+    // three of five can no longer be outvoted
+    assert_eq!(asked.load(Ordering::SeqCst), 3);
+    assert_eq!(result.report.skipped, vec![node(4), node(5)]);
+}
+
+#[tokio::test]
+async fn test_quorum_query_bounds_concurrency() {
+    let in_flight = AtomicUsize::new(0);
+    let most_in_flight = AtomicUsize::new(0);
+    let nodes: Vec<Node> = (1..=6).map(node).collect();
+    let policy = QuorumPolicy::new(100, 1).with_max_concurrent(2);
+
+    let result = quorum_query(&nodes, &policy, |_| async {
+        let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        most_in_flight.fetch_max(now, Ordering::SeqCst);
+        sleep(Duration::from_millis(20)).await;
+        in_flight.fetch_sub(1, Ordering::SeqCst);
+        Ok(1)
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(result.report.agreeing.len(), 6);
+    assert_eq!(most_in_flight.load(Ordering::SeqCst), 2);
+}
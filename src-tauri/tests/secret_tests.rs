@@ -0,0 +1,74 @@
+// the explicit `&Probe(..)` borrows below drive method resolution, they are not needless
+#![allow(clippy::needless_borrow)]
+
+use app::error::WalletError;
+use app::secret::Password;
+use app::secret::SecretBytes;
+
+use std::fmt::Debug;
+
+use serde::Serialize;
+
+// Autoref specialization: the `Probe<T>` impls are only picked when `T` has the trait,
+// otherwise method resolution falls through to the `&Probe<T>` fallback.
+struct Probe<T>(T);
+
+trait IsDebug {
+    fn is_debug(&self) -> bool {
+        true
+    }
+}
+impl<T: Debug> IsDebug for Probe<T> {}
+
+trait NotDebug {
+    fn is_debug(&self) -> bool {
+        false
+    }
+}
+impl<T> NotDebug for &Probe<T> {}
+
+trait IsSerialize {
+    fn is_serialize(&self) -> bool {
+        true
+    }
+}
+impl<T: Serialize> IsSerialize for Probe<T> {}
+
+trait NotSerialize {
+    fn is_serialize(&self) -> bool {
+        false
+    }
+}
+impl<T> NotSerialize for &Probe<T> {}
+
+#[test]
+fn test_probe_detects_debug_and_serialize() {
+    assert!((&Probe(String::new())).is_debug());
+    assert!((&Probe(String::new())).is_serialize());
+}
+
+#[test]
+fn test_secret_bytes_cannot_be_logged_or_serialized() {
+    assert!(!(&Probe(SecretBytes::new(vec![1, 2, 3]))).is_debug());
+    assert!(!(&Probe(SecretBytes::new(vec![1, 2, 3]))).is_serialize());
+}
+
+#[test]
+fn test_password_cannot_be_logged_or_serialized() {
+    assert!(!(&Probe(Password::from("hello"))).is_debug());
+    assert!(!(&Probe(Password::from("hello"))).is_serialize());
+}
+
+#[test]
+fn test_password_deserializes_from_frontend_string() {
+    let password: Password = serde_json::from_str("\"hello\"").unwrap();
+    assert_eq!(password.as_bytes(), b"hello");
+}
+
+#[test]
+fn test_secret_bytes_to_array() {
+    let secret = SecretBytes::from_hex("00112233").unwrap();
+
+    assert_eq!(*secret.to_array::<4>().unwrap(), [0x00, 0x11, 0x22, 0x33]);
+    assert!(matches!(secret.to_array::<32>(), Err(WalletError::KeyLength { expected: 32, actual: 4 })));
+}
@@ -0,0 +1,228 @@
+use app::address::address_from_public_key;
+use app::codec::message_hash;
+use app::codec::sighash;
+use app::config::Network;
+use app::crypto_utils::verify;
+use app::crypto_utils::Argon2Params;
+use app::crypto_utils::Input;
+use app::crypto_utils::Output;
+use app::crypto_utils::Transaction;
+use app::error::WalletError;
+use app::keystore::create_keystore_file;
+use app::keystore::KdfParams;
+use app::secret::Password;
+use app::session::WalletSession;
+use app::transaction::verify_transaction;
+use app::transaction::UtxoSet;
+
+use hex::encode;
+
+use std::env::temp_dir;
+use std::fs;
+use std::thread::sleep;
+use std::time::Duration;
+
+const TEST_ARGON2: Argon2Params = Argon2Params {
+    memory_kib: 1024,
+    iterations: 2,
+    parallelism: 1,
+};
+
+fn keystore_path(name: &str) -> String {
+    temp_dir()
+        .join(format!("cj-session-{}-{}.json", name, std::process::id()))
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_create_then_unlock() {
+    let path = keystore_path("unlock");
+    let public_key = create_keystore_file(&path, &Password::from("hello"), KdfParams::argon2id(TEST_ARGON2)).unwrap();
+
+    let mut session = WalletSession::default();
+    let handle = session.unlock(&path, &Password::from("hello")).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(handle.len(), 32);
+    assert_eq!(session.handle(), Some(handle.as_str()));
+    assert_eq!(session.public_key(), Some(public_key.as_str()));
+}
+
+#[test]
+fn test_unlock_handle_is_not_key_material() {
+    let path = keystore_path("handle");
+    let public_key = create_keystore_file(&path, &Password::from("hello"), KdfParams::argon2id(TEST_ARGON2)).unwrap();
+
+    let mut session = WalletSession::default();
+    let handle = session.unlock(&path, &Password::from("hello")).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(!contents.contains(&handle));
+    assert!(!public_key.contains(&handle));
+}
+
+#[test]
+fn test_unlock_then_sign() {
+    let path = keystore_path("sign");
+    let public_key = create_keystore_file(&path, &Password::from("hello"), KdfParams::argon2id(TEST_ARGON2)).unwrap();
+
+    let mut session = WalletSession::default();
+    let handle = session.unlock(&path, &Password::from("hello")).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let (signature, signer) = session.sign(&handle, "message").unwrap();
+    assert_eq!(signer, public_key);
+    assert!(verify(&public_key, "message", &signature).unwrap());
+}
+
+#[test]
+fn test_unlock_wrong_password() {
+    let path = keystore_path("wrong");
+    create_keystore_file(&path, &Password::from("hello"), KdfParams::argon2id(TEST_ARGON2)).unwrap();
+
+    let mut session = WalletSession::default();
+    let result = session.unlock(&path, &Password::from("goodbye"));
+    fs::remove_file(&path).unwrap();
+
+    assert!(matches!(result, Err(WalletError::WrongPasswordOrCorrupt)));
+    assert!(session.handle().is_none());
+}
+
+#[test]
+fn test_failed_unlock_locks_the_previous_wallet() {
+    let path = keystore_path("relock");
+    create_keystore_file(&path, &Password::from("hello"), KdfParams::argon2id(TEST_ARGON2)).unwrap();
+
+    let mut session = WalletSession::default();
+    let handle = session.unlock(&path, &Password::from("hello")).unwrap();
+    let result = session.unlock(&path, &Password::from("goodbye"));
+    fs::remove_file(&path).unwrap();
+
+    assert!(matches!(result, Err(WalletError::WrongPasswordOrCorrupt)));
+    assert!(!session.is_unlocked());
+    assert!(matches!(session.sign(&handle, "message"), Err(WalletError::Locked)));
+}
+
+#[test]
+fn test_message_signatures_never_sign_transactions() {
+    let path = keystore_path("message");
+    let public_key = create_keystore_file(&path, &Password::from("hello"), KdfParams::argon2id(TEST_ARGON2)).unwrap();
+    let mut session = WalletSession::default();
+    let handle = session.unlock(&path, &Password::from("hello")).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let owner = address_from_public_key(&public_key, Network::Mainnet).unwrap();
+    let mut utxos = UtxoSet::new();
+    utxos.insert(&"aa".repeat(32), 0, Output { amount: 100, address: owner.clone() });
+    let mut tx = Transaction {
+        version: 1,
+        inputs: vec![Input::unsigned("aa".repeat(32), 0, 100)],
+        outputs: vec![Output { amount: 90, address: owner }],
+        txid: None,
+    };
+
+    // a frontend asking for the sighash itself, as raw text or as hex, still gets a tagged hash signed
+    let digest = sighash(&tx, 0, Network::Mainnet).unwrap();
+    for message in [String::from_utf8_lossy(&digest).into_owned(), encode(&digest)] {
+        let (signature, signer) = session.sign_message(&handle, &message).unwrap();
+        assert!(verify(&signer, message_hash(&message), &signature).unwrap());
+        assert!(!verify(&signer, &message, &signature).unwrap());
+
+        tx.inputs[0].signature = Some(signature);
+        tx.inputs[0].public_key = Some(signer);
+        let result = verify_transaction(&tx, &utxos, Network::Mainnet);
+        assert!(matches!(&result, Err(WalletError::InvalidTransaction(e)) if e.contains("bad signature")), "{:?}", result);
+    }
+}
+
+#[test]
+fn test_sign_needs_the_current_handle() {
+    let path = keystore_path("stale");
+    create_keystore_file(&path, &Password::from("hello"), KdfParams::argon2id(TEST_ARGON2)).unwrap();
+
+    let mut session = WalletSession::default();
+    let stale = session.unlock(&path, &Password::from("hello")).unwrap();
+    let current = session.unlock(&path, &Password::from("hello")).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(matches!(session.sign(&stale, "message"), Err(WalletError::Locked)));
+    assert!(matches!(session.sign("not a handle", "message"), Err(WalletError::Locked)));
+    assert!(session.sign(&current, "message").is_ok());
+}
+
+#[test]
+fn test_unlock_missing_file() {
+    let mut session = WalletSession::default();
+    let result = session.unlock(&keystore_path("missing"), &Password::from("hello"));
+
+    assert!(matches!(result, Err(WalletError::Io(_))));
+}
+
+#[test]
+fn test_sign_while_locked() {
+    let mut session = WalletSession::default();
+    assert!(matches!(session.sign("", "message"), Err(WalletError::Locked)));
+}
+
+#[test]
+fn test_lock_drops_key() {
+    let path = keystore_path("lock");
+    create_keystore_file(&path, &Password::from("hello"), KdfParams::argon2id(TEST_ARGON2)).unwrap();
+
+    let mut session = WalletSession::default();
+    let handle = session.unlock(&path, &Password::from("hello")).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(session.is_unlocked());
+
+    session.lock();
+
+    assert!(!session.is_unlocked());
+    assert!(session.public_key().is_none());
+    assert!(matches!(session.sign(&handle, "message"), Err(WalletError::Locked)));
+}
+
+#[test]
+fn test_auto_lock_after_idle_timeout() {
+    let path = keystore_path("idle");
+    create_keystore_file(&path, &Password::from("hello"), KdfParams::argon2id(TEST_ARGON2)).unwrap();
+
+    let mut session = WalletSession::new(Duration::from_millis(200));
+    let handle = session.unlock(&path, &Password::from("hello")).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(session.is_unlocked());
+
+    sleep(Duration::from_millis(300));
+
+    assert!(session.expire_if_idle());
+    assert!(!session.is_unlocked());
+    assert!(matches!(session.sign(&handle, "message"), Err(WalletError::Locked)));
+}
+
+#[test]
+fn test_signing_counts_as_activity() {
+    let path = keystore_path("activity");
+    create_keystore_file(&path, &Password::from("hello"), KdfParams::argon2id(TEST_ARGON2)).unwrap();
+
+    let mut session = WalletSession::new(Duration::from_millis(500));
+    let handle = session.unlock(&path, &Password::from("hello")).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    for _ in 0..3 {
+        sleep(Duration::from_millis(200));
+        session.sign(&handle, "message").unwrap();
+    }
+
+    assert!(session.is_unlocked());
+}
+
+#[test]
+fn test_set_idle_timeout() {
+    let mut session = WalletSession::default();
+    session.set_idle_timeout(Duration::from_secs(30));
+
+    assert_eq!(session.idle_timeout(), Duration::from_secs(30));
+    assert!(!session.expire_if_idle());
+}
@@ -0,0 +1,31 @@
+// Small builders shared by the integration tests.
+#![allow(dead_code)]
+
+use app::crypto_utils::{Node, Output, Transaction};
+use app::transaction::Utxo;
+
+/// a valid mainnet address, for payments whose recipient does not matter
+pub const ADDRESS: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+
+/// confirmed UTXOs of `amounts`, each with its own txid
+pub fn utxos(amounts: &[u64]) -> Vec<Utxo> {
+    amounts.iter().enumerate().map(|(index, &amount)| Utxo::unsigned(format!("{:064x}", index + 1), 0, amount)).collect()
+}
+
+/// one input, one output to `ADDRESS`, paying `fee`; `seed` keeps txids apart
+pub fn paying(fee: u64, seed: u64) -> Transaction {
+    Transaction {
+        version: 1,
+        inputs: vec![Utxo::unsigned(format!("{:064x}", seed), 0, 100_000 + fee)],
+        outputs: vec![Output { amount: 100_000, address: ADDRESS.to_string() }],
+        txid: None,
+    }
+}
+
+/// a peer nobody listens on, told apart by `port`
+pub fn node(port: u16) -> Node {
+    Node {
+        ipv4_address: "10.0.0.1".to_string(),
+        port,
+    }
+}
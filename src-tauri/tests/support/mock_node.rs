@@ -0,0 +1,464 @@
+// An in-process CJ-Coin node for tests and offline development. It serves the same HTTP
+// routes as a real node from scriptable in-memory state, validates what it is sent the way a
+// node would, and can be told to hang, fail or slow down. Spawn several to test quorums.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use app::api::NodeClient;
+use app::codec::{block_hash, txid};
+use app::config::Network;
+use app::consensus::{check_nbits, ConsensusParams};
+use app::crypto_utils::{calculate_merkle_root, Block, BlockHeader, Node, Transaction};
+use app::miner::{coinbase, grind, MiningControl};
+use app::pow::check_proof_of_work;
+use app::transaction::{verify_transaction, Utxo, UtxoSet};
+use hex::{decode, encode};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::sleep;
+
+pub use app::miner::GENESIS_PREV_HASH;
+
+/// most headers one `/headers/{start}` response carries
+pub const MAX_HEADERS_PER_RESPONSE: usize = 2000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Behavior {
+    Respond,
+    /// accepts the request and never answers
+    Hang,
+    /// answers every request with this status
+    Fail(u16),
+    /// answers normally after a pause
+    Delay(Duration),
+}
+
+/// How `/validate/tx` and `/validate/block` decide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// check signatures, funds and chain linkage like a real node
+    Validate,
+    AcceptAll,
+    RejectAll,
+}
+
+struct MockState {
+    network: Network,
+    peers: Vec<Node>,
+    utxos: HashMap<String, Vec<Utxo>>,
+    mempool: HashMap<String, Vec<Utxo>>,
+    // the transactions `fund` made up, which `/tx/{txid}` serves like any other
+    funding: Vec<Transaction>,
+    transactions: Vec<Transaction>,
+    blocks: Vec<Block>,
+    verdict: Verdict,
+    overrides: HashMap<String, Value>,
+    behavior: Behavior,
+    failures_left: usize,
+    requests: Vec<String>,
+}
+
+/// a client for `nodes`, on their network
+pub fn client_for(nodes: &[MockNode]) -> NodeClient {
+    let network = nodes.first().map_or(Network::Mainnet, MockNode::network);
+    NodeClient::with_nodes(network, nodes.iter().map(MockNode::node).collect())
+}
+
+pub struct MockNode {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockNode {
+    /// a mainnet node on a free local port that lists itself as its only peer
+    pub async fn spawn() -> MockNode {
+        Self::bind("127.0.0.1:0", Network::Mainnet).await.unwrap()
+    }
+
+    pub async fn spawn_on(network: Network) -> MockNode {
+        Self::bind("127.0.0.1:0", network).await.unwrap()
+    }
+
+    /// `count` mainnet nodes that all know each other
+    pub async fn spawn_cluster(count: usize) -> Vec<MockNode> {
+        Self::spawn_cluster_on(count, Network::Mainnet).await
+    }
+
+    /// `count` nodes on `network` that all know each other
+    pub async fn spawn_cluster_on(count: usize, network: Network) -> Vec<MockNode> {
+        let mut nodes = Vec::new();
+        for _ in 0..count {
+            nodes.push(Self::spawn_on(network).await);
+        }
+        let peers: Vec<Node> = nodes.iter().map(MockNode::node).collect();
+        for node in &nodes {
+            node.set_peers(peers.clone());
+        }
+        nodes
+    }
+
+    pub async fn bind(addr: &str, network: Network) -> io::Result<MockNode> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState {
+            network,
+            peers: vec![Node {
+                ipv4_address: addr.ip().to_string(),
+                port: addr.port(),
+            }],
+            utxos: HashMap::new(),
+            mempool: HashMap::new(),
+            funding: Vec::new(),
+            transactions: Vec::new(),
+            blocks: Vec::new(),
+            verdict: Verdict::Validate,
+            overrides: HashMap::new(),
+            behavior: Behavior::Respond,
+            failures_left: 0,
+            requests: Vec::new(),
+        }));
+
+        let shared = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, shared.clone()));
+            }
+        });
+
+        Ok(MockNode { addr, state })
+    }
+
+    pub fn node(&self) -> Node {
+        Node {
+            ipv4_address: self.addr.ip().to_string(),
+            port: self.addr.port(),
+        }
+    }
+
+    pub fn network(&self) -> Network {
+        self.lock().network
+    }
+
+    /// `host:port`, as a seed node entry
+    pub fn host(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// what `/discover/nodes` returns
+    pub fn set_peers(&self, peers: Vec<Node>) -> &Self {
+        self.lock().peers = peers;
+        self
+    }
+
+    pub fn set_utxos(&self, address: &str, confirmed: Vec<Utxo>, mempool: Vec<Utxo>) -> &Self {
+        let mut state = self.lock();
+        state.utxos.insert(address.to_string(), confirmed);
+        state.mempool.insert(address.to_string(), mempool);
+        self
+    }
+
+    /// adds a confirmed UTXO of `amount` to `address`, as if a coinbase `/tx/{txid}` serves had mined it
+    pub fn fund(&self, address: &str, amount: u64) -> &Self {
+        let mut state = self.lock();
+        let tx = coinbase(address, amount, u64::MAX, state.funding.len() as u64).unwrap();
+        let utxo = Utxo::unsigned(tx.txid.clone().unwrap(), 0, amount);
+        state.utxos.entry(address.to_string()).or_default().push(utxo);
+        state.funding.push(tx);
+        self
+    }
+
+    /// confirms everything in the mempool
+    pub fn confirm_mempool(&self) -> &Self {
+        let mut state = self.lock();
+        let mempool: Vec<_> = state.mempool.drain().collect();
+        for (address, utxos) in mempool {
+            state.utxos.entry(address).or_default().extend(utxos);
+        }
+        self
+    }
+
+    pub fn set_verdict(&self, verdict: Verdict) -> &Self {
+        self.lock().verdict = verdict;
+        self
+    }
+
+    /// answers GETs and POSTs to `path` with `body`, bypassing the node's state
+    pub fn respond(&self, path: &str, body: impl Serialize) -> &Self {
+        self.lock().overrides.insert(path.to_string(), serde_json::to_value(body).unwrap());
+        self
+    }
+
+    pub fn set_behavior(&self, behavior: Behavior) -> &Self {
+        self.lock().behavior = behavior;
+        self
+    }
+
+    /// fails the next `count` requests with 503, then goes back to its behaviour
+    pub fn fail_next(&self, count: usize) -> &Self {
+        self.lock().failures_left = count;
+        self
+    }
+
+    /// puts `tx` in the mempool without checking it
+    pub fn add_to_mempool(&self, tx: Transaction) -> &Self {
+        self.lock().transactions.push(tx);
+        self
+    }
+
+    /// Mines a block of `txs` onto the tip without checking them, returning its hash. It is
+    /// mined at the regtest pow limit whatever the network, so tests stay fast.
+    pub fn mine(&self, txs: Vec<Transaction>) -> String {
+        let mut state = self.lock();
+        let txids = txs.iter().map(|tx| decode(txid(tx).unwrap()).unwrap()).collect();
+        let header = BlockHeader {
+            version: 1,
+            prev_hash: state.tip(),
+            merkle_root: encode(calculate_merkle_root(txids)),
+            timestamp: 1_700_000_000 + state.blocks.len() as u64 * 600,
+            nbits: ConsensusParams::for_network(Network::Regtest).pow_limit,
+            nonce: 0,
+            hash: None,
+        };
+        let header = grind(&header, 1, &MiningControl::new(), Instant::now() + Duration::from_secs(60)).unwrap().expect("the easiest target takes a few tries");
+        let hash = header.hash.clone().unwrap();
+        state.blocks.push(Block { header, txs });
+        hash
+    }
+
+    /// drops every block from `height` on, so blocks mined next fork the chain there
+    pub fn rewind(&self, height: usize) -> &Self {
+        self.lock().blocks.truncate(height);
+        self
+    }
+
+    /// accepted transactions, oldest first
+    pub fn transactions(&self) -> Vec<Transaction> {
+        self.lock().transactions.clone()
+    }
+
+    /// accepted blocks, oldest first
+    pub fn blocks(&self) -> Vec<Block> {
+        self.lock().blocks.clone()
+    }
+
+    /// hash of the last accepted block, or all zeros before the first one
+    pub fn tip(&self) -> String {
+        self.lock().tip()
+    }
+
+    /// requests received so far, failed ones included
+    pub fn requests(&self) -> usize {
+        self.lock().requests.len()
+    }
+
+    /// paths requested so far, in order
+    pub fn request_log(&self) -> Vec<String> {
+        self.lock().requests.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl MockState {
+    fn tip(&self) -> String {
+        self.blocks.last().and_then(|block| block.header.hash.clone()).unwrap_or_else(|| GENESIS_PREV_HASH.to_string())
+    }
+
+    // accepted transactions that no block includes yet
+    fn mempool_transactions(&self) -> Vec<Transaction> {
+        let mined: Vec<String> = self.blocks.iter().flat_map(|block| &block.txs).filter_map(|tx| txid(tx).ok()).collect();
+        self.transactions.iter().filter(|tx| !matches!(txid(tx), Ok(id) if mined.contains(&id))).cloned().collect()
+    }
+
+    fn route(&mut self, method: &str, path: &str, body: &[u8]) -> (u16, Value) {
+        if let Some(value) = self.overrides.get(path) {
+            return (200, value.clone());
+        }
+
+        match (method, path) {
+            ("GET", "/discover/nodes") => (200, json!(self.peers)),
+            ("GET", "/mempool") => (200, json!(self.mempool_transactions())),
+            ("GET", "/blocks/count") => (200, json!(self.blocks.len())),
+            ("GET", _) if path.starts_with("/headers/") => match path["/headers/".len()..].parse::<usize>() {
+                Ok(start) => {
+                    let headers: Vec<&BlockHeader> = self.blocks.iter().skip(start).take(MAX_HEADERS_PER_RESPONSE).map(|block| &block.header).collect();
+                    (200, json!(headers))
+                }
+                Err(_) => (400, json!({ "error": "start is not a number" })),
+            },
+            ("GET", _) if path.starts_with("/blocks/recent/") => match path["/blocks/recent/".len()..].parse::<usize>() {
+                Ok(count) => (200, json!(self.blocks[self.blocks.len().saturating_sub(count)..])),
+                Err(_) => (400, json!({ "error": "count is not a number" })),
+            },
+            ("GET", _) if path.starts_with("/utxos/address/") => (200, json!(self.utxos.get(&path["/utxos/address/".len()..]).cloned().unwrap_or_default())),
+            ("GET", _) if path.starts_with("/utxos_mempool/address/") => {
+                (200, json!(self.mempool.get(&path["/utxos_mempool/address/".len()..]).cloned().unwrap_or_default()))
+            }
+            ("GET", _) if path.starts_with("/tx/") => match self.find_transaction(&path["/tx/".len()..]) {
+                Some(tx) => (200, json!(tx)),
+                None => (404, Value::Null),
+            },
+            ("POST", "/validate/tx") => match serde_json::from_slice(body) {
+                Ok(tx) => (200, verdict(self.accept_transaction(tx))),
+                Err(e) => (400, json!({ "error": e.to_string() })),
+            },
+            ("POST", "/validate/block") => match serde_json::from_slice(body) {
+                Ok(block) => (200, verdict(self.accept_block(block))),
+                Err(e) => (400, json!({ "error": e.to_string() })),
+            },
+            _ => (404, Value::Null),
+        }
+    }
+
+    fn accept_transaction(&mut self, tx: Transaction) -> Result<(), String> {
+        match self.verdict {
+            Verdict::AcceptAll => {}
+            Verdict::RejectAll => return Err("rejecting everything".to_string()),
+            Verdict::Validate => {
+                verify_transaction(&tx, &self.utxo_set(), self.network).map_err(|e| e.to_string())?;
+                for pool in [&mut self.utxos, &mut self.mempool] {
+                    for owned in pool.values_mut() {
+                        owned.retain(|utxo| !tx.inputs.iter().any(|input| input.prev_txid == utxo.prev_txid && input.output_index == utxo.output_index));
+                    }
+                }
+                let txid = txid(&tx).map_err(|e| e.to_string())?;
+                for (index, output) in tx.outputs.iter().enumerate() {
+                    let utxo = Utxo::unsigned(txid.clone(), index as u32, output.amount);
+                    self.mempool.entry(output.address.clone()).or_default().push(utxo);
+                }
+            }
+        }
+        self.transactions.push(tx);
+        Ok(())
+    }
+
+    fn find_transaction(&self, id: &str) -> Option<&Transaction> {
+        let mined = self.blocks.iter().flat_map(|block| &block.txs);
+        self.funding.iter().chain(mined).chain(&self.transactions).find(|tx| matches!(txid(tx), Ok(txid) if txid == id))
+    }
+
+    // everything spendable, confirmed or not
+    fn utxo_set(&self) -> UtxoSet {
+        let mut set = UtxoSet::new();
+        for pool in [&self.utxos, &self.mempool] {
+            for (address, owned) in pool {
+                set.add_owned(address, owned);
+            }
+        }
+        set
+    }
+
+    fn accept_block(&mut self, mut block: Block) -> Result<(), String> {
+        let hash = block_hash(&block.header).map_err(|e| e.to_string())?;
+        match self.verdict {
+            Verdict::AcceptAll => {}
+            Verdict::RejectAll => return Err("rejecting everything".to_string()),
+            Verdict::Validate => {
+                if block.header.prev_hash != self.tip() {
+                    return Err("does not extend the tip".to_string());
+                }
+                check_proof_of_work(&block.header).map_err(|e| e.to_string())?;
+                let headers: Vec<BlockHeader> = self.blocks.iter().map(|block| block.header.clone()).collect();
+                check_nbits(&ConsensusParams::for_network(self.network), headers.len() as u64, &headers, &block.header).map_err(|e| e.to_string())?;
+                let txids = block.txs.iter().map(|tx| txid(tx).map(|id| decode(id).expect("txid is hex"))).collect::<Result<_, _>>().map_err(|e| e.to_string())?;
+                if hex::encode(calculate_merkle_root(txids)) != block.header.merkle_root {
+                    return Err("merkle root does not match".to_string());
+                }
+            }
+        }
+        block.header.hash = Some(hash);
+        self.blocks.push(block);
+        Ok(())
+    }
+}
+
+fn verdict(result: Result<(), String>) -> Value {
+    match result {
+        Ok(()) => json!({ "valid": true }),
+        Err(reason) => json!({ "valid": false, "reason": reason }),
+    }
+}
+
+async fn serve(mut stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let Some((method, path, body)) = read_request(&mut stream).await else {
+        return;
+    };
+
+    let (behavior, failing) = {
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.requests.push(path.clone());
+        let failing = state.failures_left > 0;
+        state.failures_left = state.failures_left.saturating_sub(1);
+        (state.behavior, failing)
+    };
+
+    match (behavior, failing) {
+        (_, true) => return respond(&mut stream, 503, Value::Null).await,
+        (Behavior::Hang, _) => return sleep(Duration::from_secs(3600)).await,
+        (Behavior::Fail(status), _) => return respond(&mut stream, status, Value::Null).await,
+        (Behavior::Delay(delay), _) => sleep(delay).await,
+        (Behavior::Respond, _) => {}
+    }
+
+    let (status, body) = state.lock().unwrap_or_else(PoisonError::into_inner).route(&method, &path, &body);
+    respond(&mut stream, status, body).await;
+}
+
+async fn respond(stream: &mut TcpStream, status: u16, body: Value) {
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+// reads one request: (method, path, body)
+async fn read_request(stream: &mut TcpStream) -> Option<(String, String, Vec<u8>)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let head_end = loop {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]).to_string();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+
+    while buffer.len() < head_end + content_length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let mut request_line = head.lines().next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    Some((method, path, buffer[head_end..head_end + content_length].to_vec()))
+}
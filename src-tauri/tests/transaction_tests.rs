@@ -0,0 +1,256 @@
+mod support;
+
+use app::address::address_from_public_key;
+use app::config::Network;
+use app::crypto_utils::generate_key_pair;
+use app::crypto_utils::sign;
+use app::crypto_utils::Output;
+use app::crypto_utils::Transaction;
+use app::error::WalletError;
+use app::secret::SecretBytes;
+use app::transaction::sign_transaction;
+use app::transaction::verify_transaction;
+use app::transaction::CoinSelection;
+use app::transaction::TransactionBuilder;
+use app::transaction::Utxo;
+use app::transaction::UtxoSet;
+
+use support::fixtures::utxos;
+
+const OWN_ADDRESS: &str = "cj1w508d6qejxtdg4y5r3zarvary0c5xw7kg2fc7r";
+const OTHER_ADDRESS: &str = "cj1recipient";
+
+fn amounts(utxos: &[Utxo]) -> Vec<u64> {
+    utxos.iter().map(|utxo| utxo.amount).collect()
+}
+
+fn select(strategy: CoinSelection, confirmed: &[u64], unconfirmed: &[u64], needed: u64) -> Vec<u64> {
+    let selected = TransactionBuilder::new(strategy).select(&utxos(confirmed), &utxos(unconfirmed), needed).unwrap();
+    amounts(&selected)
+}
+
+#[test]
+fn test_oldest_first_keeps_node_order() {
+    assert_eq!(select(CoinSelection::OldestFirst, &[30, 80, 10], &[], 90), vec![30, 80]);
+}
+
+#[test]
+fn test_oldest_first_spends_confirmed_before_unconfirmed() {
+    assert_eq!(select(CoinSelection::OldestFirst, &[20, 20], &[100], 50), vec![20, 20, 100]);
+    assert_eq!(select(CoinSelection::OldestFirst, &[20, 40], &[100], 50), vec![20, 40]);
+}
+
+#[test]
+fn test_largest_first_uses_fewest_inputs() {
+    assert_eq!(select(CoinSelection::LargestFirst, &[30, 80, 10, 50], &[], 100), vec![80, 50]);
+}
+
+#[test]
+fn test_smallest_first_consolidates() {
+    assert_eq!(select(CoinSelection::SmallestFirst, &[30, 80, 10, 50], &[], 60), vec![10, 30, 50]);
+}
+
+#[test]
+fn test_ties_prefer_confirmed_inputs() {
+    assert_eq!(select(CoinSelection::LargestFirst, &[5, 40], &[40], 40), vec![40]);
+    let selected = TransactionBuilder::new(CoinSelection::SmallestFirst).select(&utxos(&[40]), &utxos(&[40]), 60).unwrap();
+    assert_eq!(amounts(&selected), vec![40, 40]);
+}
+
+#[test]
+fn test_branch_and_bound_finds_an_exact_match() {
+    // largest-first would pick 80 + 55 and need change
+    assert_eq!(select(CoinSelection::BranchAndBound, &[30, 80, 25, 55, 45], &[], 100), vec![55, 45]);
+    assert_eq!(select(CoinSelection::BranchAndBound, &[30, 80, 20, 50], &[], 100), vec![80, 20]);
+}
+
+#[test]
+fn test_branch_and_bound_accepts_change_below_dust() {
+    let builder = TransactionBuilder::new(CoinSelection::BranchAndBound).with_dust_threshold(5);
+    let selected = builder.select(&utxos(&[70, 60, 43]), &[], 100).unwrap();
+    assert_eq!(amounts(&selected), vec![60, 43]);
+}
+
+#[test]
+fn test_branch_and_bound_prefers_the_least_excess() {
+    let builder = TransactionBuilder::new(CoinSelection::BranchAndBound).with_dust_threshold(10);
+    let selected = builder.select(&utxos(&[64, 45, 40, 37]), &[], 100).unwrap();
+    assert_eq!(amounts(&selected), vec![64, 37]);
+}
+
+#[test]
+fn test_branch_and_bound_falls_back_to_largest_first() {
+    let builder = TransactionBuilder::new(CoinSelection::BranchAndBound).with_dust_threshold(0);
+    let selected = builder.select(&utxos(&[30, 80, 50]), &[], 100).unwrap();
+    assert_eq!(amounts(&selected), vec![80, 50]);
+}
+
+#[test]
+fn test_selection_is_deterministic() {
+    let confirmed = utxos(&[13, 7, 21, 7, 34, 2, 55, 13]);
+    let unconfirmed = utxos(&[8, 89]);
+    for strategy in [CoinSelection::OldestFirst, CoinSelection::LargestFirst, CoinSelection::SmallestFirst, CoinSelection::BranchAndBound] {
+        let builder = TransactionBuilder::new(strategy);
+        let first = builder.select(&confirmed, &unconfirmed, 90).unwrap();
+        for _ in 0..5 {
+            assert_eq!(builder.select(&confirmed, &unconfirmed, 90).unwrap(), first);
+        }
+    }
+}
+
+#[test]
+fn test_excluding_unconfirmed_inputs() {
+    let builder = TransactionBuilder::new(CoinSelection::LargestFirst).with_unconfirmed(false);
+    let result = builder.select(&utxos(&[20, 30]), &utxos(&[500]), 60);
+    assert!(matches!(result, Err(WalletError::InsufficientFunds { needed: 60, available: 50 })));
+
+    let selected = builder.select(&utxos(&[20, 30, 40]), &utxos(&[500]), 60).unwrap();
+    assert_eq!(amounts(&selected), vec![40, 30]);
+}
+
+#[test]
+fn test_dust_change_goes_to_the_fee() {
+    let builder = TransactionBuilder::new(CoinSelection::OldestFirst).with_dust_threshold(10);
+
+    let tx = builder.build(&utxos(&[50, 58]), &[], 100, OTHER_ADDRESS, 2, OWN_ADDRESS).unwrap();
+    assert_eq!(tx.outputs, vec![Output { amount: 100, address: OTHER_ADDRESS.to_string() }]);
+
+    let tx = builder.build(&utxos(&[50, 62]), &[], 100, OTHER_ADDRESS, 2, OWN_ADDRESS).unwrap();
+    assert_eq!(
+        tx.outputs,
+        vec![
+            Output { amount: 100, address: OTHER_ADDRESS.to_string() },
+            Output { amount: 10, address: OWN_ADDRESS.to_string() },
+        ]
+    );
+}
+
+#[test]
+fn test_build_reports_insufficient_funds() {
+    let result = TransactionBuilder::default().build(&utxos(&[40]), &utxos(&[40]), 80, OTHER_ADDRESS, 5, OWN_ADDRESS);
+    assert!(matches!(result, Err(WalletError::InsufficientFunds { needed: 85, available: 80 })));
+}
+
+#[test]
+fn test_build_rejects_inputs_overflowing_a_u64() {
+    // both inputs are needed, and together they hold more than a u64
+    let result = TransactionBuilder::new(CoinSelection::OldestFirst).build(&utxos(&[u64::MAX - 5, 10]), &[], u64::MAX - 2, OTHER_ADDRESS, 1, OWN_ADDRESS);
+    assert!(matches!(result, Err(WalletError::InvalidAmount(_))));
+}
+
+struct Signed {
+    tx: Transaction,
+    utxos: UtxoSet,
+    private_key: SecretBytes,
+}
+
+// spends two of `owner`'s outputs, 60 + 50, paying 100 with a fee of 4 and 6 change
+fn signed_payment() -> Signed {
+    let (private_key, public_key) = generate_key_pair();
+    let owner = address_from_public_key(&public_key, Network::Mainnet).unwrap();
+    let funds = utxos(&[60, 50]);
+
+    let mut tx = TransactionBuilder::default().with_dust_threshold(0).build(&funds, &[], 100, OWN_ADDRESS, 4, &owner).unwrap();
+    sign_transaction(&mut tx, Network::Mainnet, |digest| sign(&private_key, digest)).unwrap();
+
+    let mut utxos = UtxoSet::new();
+    utxos.add_owned(&owner, &funds);
+    Signed { tx, utxos, private_key }
+}
+
+fn rejection(tx: &Transaction, utxos: &UtxoSet) -> String {
+    match verify_transaction(tx, utxos, Network::Mainnet) {
+        Err(WalletError::InvalidTransaction(reason)) => reason,
+        other => panic!("expected an invalid transaction, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_verify_accepts_a_signed_payment() {
+    let Signed { tx, utxos, .. } = signed_payment();
+
+    assert_eq!(verify_transaction(&tx, &utxos, Network::Mainnet).unwrap(), 4);
+    assert!(tx.inputs.iter().all(|input| input.signature.is_some() && input.public_key.is_some()));
+    assert_eq!(tx.txid.as_deref(), Some(app::codec::txid(&tx).unwrap().as_str()));
+}
+
+#[test]
+fn test_verify_rejects_changed_outputs() {
+    let Signed { mut tx, utxos, .. } = signed_payment();
+    tx.outputs[0].amount -= 1;
+    tx.txid = None;
+
+    assert_eq!(rejection(&tx, &utxos), "input 0: bad signature");
+}
+
+#[test]
+fn test_verify_rejects_a_stale_txid() {
+    let Signed { mut tx, utxos, .. } = signed_payment();
+    tx.txid = Some("00".repeat(32));
+
+    assert_eq!(rejection(&tx, &utxos), "txid does not match its contents");
+}
+
+#[test]
+fn test_verify_rejects_signatures_moved_between_inputs() {
+    let Signed { mut tx, utxos, .. } = signed_payment();
+    let first = tx.inputs[0].signature.take();
+    tx.inputs[0].signature = tx.inputs[1].signature.take();
+    tx.inputs[1].signature = first;
+
+    assert_eq!(rejection(&tx, &utxos), "input 0: bad signature");
+}
+
+#[test]
+fn test_verify_binds_public_keys_to_addresses() {
+    let Signed { mut tx, utxos, .. } = signed_payment();
+    let (impostor, _) = generate_key_pair();
+    sign_transaction(&mut tx, Network::Mainnet, |digest| sign(&impostor, digest)).unwrap();
+
+    assert!(rejection(&tx, &utxos).starts_with("input 0: public key does not belong to cj1"));
+}
+
+#[test]
+fn test_verify_requires_every_input_signed() {
+    let Signed { mut tx, utxos, .. } = signed_payment();
+    tx.inputs[1].public_key = None;
+
+    assert_eq!(rejection(&tx, &utxos), "input 1: unsigned");
+}
+
+#[test]
+fn test_verify_rejects_unknown_and_repeated_outpoints() {
+    let Signed { tx, utxos, private_key } = signed_payment();
+
+    assert!(rejection(&tx, &UtxoSet::new()).ends_with("is not unspent"));
+
+    let mut doubled = tx.clone();
+    doubled.inputs[1] = doubled.inputs[0].clone();
+    doubled.outputs[0].amount = 50;
+    sign_transaction(&mut doubled, Network::Mainnet, |digest| sign(&private_key, digest)).unwrap();
+    assert_eq!(rejection(&doubled, &utxos), "input 1: spends an output twice");
+}
+
+#[test]
+fn test_verify_checks_amounts() {
+    let Signed { tx, utxos, private_key } = signed_payment();
+
+    let mut inflated = tx.clone();
+    inflated.inputs[0].amount = 70;
+    sign_transaction(&mut inflated, Network::Mainnet, |digest| sign(&private_key, digest)).unwrap();
+    assert_eq!(rejection(&inflated, &utxos), "input 0: claims 70 but the output holds 60");
+
+    let mut overspent = tx;
+    overspent.outputs[0].amount = 120;
+    sign_transaction(&mut overspent, Network::Mainnet, |digest| sign(&private_key, digest)).unwrap();
+    assert_eq!(rejection(&overspent, &utxos), "outputs of 126 exceed inputs of 110");
+}
+
+#[test]
+fn test_verify_checks_output_addresses() {
+    let Signed { mut tx, utxos, private_key } = signed_payment();
+    tx.outputs[0].address = "tcj1w508d6qejxtdg4y5r3zarvary0c5xw7kh6r3n4".to_string();
+    sign_transaction(&mut tx, Network::Mainnet, |digest| sign(&private_key, digest)).unwrap();
+
+    assert!(rejection(&tx, &utxos).starts_with("output 0: invalid address"));
+}
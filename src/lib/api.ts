@@ -1,4 +1,6 @@
 import { json }	from '@sveltejs/kit';
+import { invoke } from '@tauri-apps/api/tauri';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 
 async function getData(url: string): Promise<any> {
 	const response = await fetch(url);
@@ -16,4 +18,263 @@ async function postData(url: string, data: any): Promise<any> {
 	});
 	if (!response.ok) throw new Error("Failed to post");
 	return response.json();
-}
\ No newline at end of file
+}
+
+// mirrors `Balance` on the Rust side, in base units
+export type Balance = {
+	confirmed: number;
+	unconfirmed: number;
+};
+
+// base units in one CJC
+export const BASE_UNITS_PER_CJC = 100_000_000;
+
+// `amount` base units in CJC, for display
+export function to_cjc(amount: number): number {
+	return amount / BASE_UNITS_PER_CJC;
+}
+
+// `amount` base units as an exact CJC decimal, without trailing zeros
+export function format_cjc(amount: number): string {
+	const units = BigInt(amount);
+	const base = BigInt(BASE_UNITS_PER_CJC);
+	const fraction = (units % base).toString().padStart(8, '0').replace(/0+$/, '');
+	return fraction ? `${units / base}.${fraction}` : `${units / base}`;
+}
+
+// parses a CJC decimal such as "0.5" into base units without going through a float; throws
+// on anything that is not a plain non-negative decimal with at most 8 fractional digits
+export function from_cjc(text: string): number {
+	const match = /^(\d*)(?:\.(\d{0,8}))?$/.exec(text.trim());
+	if (!match || (match[1] === '' && !match[2])) {
+		throw new Error(`"${text}" is not an amount of CJC with at most 8 decimals`);
+	}
+	const units = BigInt(match[1] || '0') * BigInt(BASE_UNITS_PER_CJC) + BigInt((match[2] ?? '').padEnd(8, '0'));
+	if (units > BigInt(Number.MAX_SAFE_INTEGER)) {
+		throw new Error(`${text} CJC is more than can be sent`);
+	}
+	return Number(units);
+}
+
+// output `output_index` of transaction `prev_txid`; signatures are only set on spent inputs
+export type Utxo = {
+	prev_txid: string;
+	output_index: number;
+	amount: number;
+	signature: string | null;
+	public_key: string | null;
+};
+
+export async function get_balance(): Promise<Balance> {
+	return invoke('get_balance');
+}
+
+// [confirmed, mempool] UTXOs of the unlocked wallet
+export async function get_utxos(): Promise<[Utxo[], Utxo[]]> {
+	return invoke('get_utxos');
+}
+
+// mirrors `CoinSelection`: how inputs are picked from the wallet's UTXOs
+export type CoinSelection = 'oldest_first' | 'largest_first' | 'smallest_first' | 'branch_and_bound';
+
+// signs with the wallet `handle` unlocked and broadcasts; resolves to the txid
+export async function send_transaction(handle: string, amount: number, address: string, fee: number, strategy: CoinSelection = 'oldest_first', includeUnconfirmed: boolean = true): Promise<string> {
+	return invoke('send_transaction', {handle: handle, amount: amount, address: address, fee: fee, strategy: strategy, includeUnconfirmed: includeUnconfirmed});
+}
+
+// mirrors `FeeRates`, in base units per 1000 bytes
+export type FeeRates = {
+	slow: number;
+	normal: number;
+	fast: number;
+};
+
+// mirrors `FeeEstimate`: fees for each preset and the range outside which a fee looks wrong
+export type FeeEstimate = {
+	size: number;
+	rates: FeeRates;
+	slow: number;
+	normal: number;
+	fast: number;
+	warn_below: number;
+	warn_above: number;
+};
+
+export type FeePriority = 'slow' | 'normal' | 'fast';
+
+export async function estimate_fee(amount: number, address: string, strategy: CoinSelection = 'oldest_first', includeUnconfirmed: boolean = true): Promise<FeeEstimate> {
+	return invoke('estimate_fee', {amount: amount, address: address, strategy: strategy, includeUnconfirmed: includeUnconfirmed});
+}
+
+// null if `fee`, in base units, is within the estimate's range
+export function fee_warning(estimate: FeeEstimate, fee: number): string | null {
+	if (fee < estimate.warn_below) {
+		return `A fee of ${format_cjc(fee)} CJC is below the slow estimate of ${format_cjc(estimate.slow)} CJC; the transaction may never confirm`;
+	}
+	if (fee > estimate.warn_above) {
+		return `A fee of ${format_cjc(fee)} CJC is far above the fast estimate of ${format_cjc(estimate.fast)} CJC`;
+	}
+	return null;
+}
+
+export type Network = 'mainnet' | 'testnet' | 'regtest';
+
+// mirrors `NetworkProfile`; prefix and magic are fixed per network
+export type NetworkProfile = {
+	network: Network;
+	address_prefix: string;
+	magic: string;
+	seed_nodes: string[];
+};
+
+export type NetworkSettings = {
+	selected: Network;
+	profiles: NetworkProfile[];
+	// why the saved settings could not be read at startup, if they could not
+	load_error: string | null;
+};
+
+export async function get_network_config(): Promise<NetworkSettings> {
+	return invoke('get_network_config');
+}
+
+// the wallet address changes with the network's prefix
+export async function select_network(network: Network): Promise<NetworkProfile> {
+	return invoke('select_network', {network: network});
+}
+
+// `host:port` entries; an empty list restores the defaults
+export async function set_seed_nodes(network: Network, seedNodes: string[]): Promise<NetworkProfile> {
+	return invoke('set_seed_nodes', {network: network, seedNodes: seedNodes});
+}
+
+export type Node = {
+	ipv4_address: string;
+	port: number;
+};
+
+// mirrors `QuorumReport`: who backed the answer, who disagreed and who did not answer
+export type QuorumReport = {
+	agreeing: Node[];
+	dissenting: Node[];
+	unreachable: { node: Node; error: string; malformed: boolean }[];
+	// not asked, or cancelled, once the quorum was reached
+	skipped: Node[];
+};
+
+export type NodeDisagreement = {
+	operation: string;
+	report: QuorumReport;
+};
+
+// fired when a quorum was reached but not every node agreed
+export async function on_node_disagreement(callback: (warning: NodeDisagreement) => void): Promise<UnlistenFn> {
+	return listen<NodeDisagreement>('node-disagreement', (event) => callback(event.payload));
+}
+
+export function describe_disagreement({ report }: NodeDisagreement): string {
+	const total = report.agreeing.length + report.dissenting.length + report.unreachable.length;
+	return `Only ${report.agreeing.length} of ${total} nodes agreed (${report.dissenting.length} disagreed, ${report.unreachable.length} unreachable)`;
+}
+
+// mirrors `PeerRecord`; times are unix seconds
+export type PeerRecord = {
+	node: Node;
+	last_seen: number | null;
+	latency_ms: number | null;
+	successes: number;
+	failures: number;
+	disagreements: number;
+	malformed: number;
+	strikes: number;
+	banned_until: number | null;
+};
+
+// every known peer of the selected network
+export async function get_peers(): Promise<PeerRecord[]> {
+	return invoke('get_peers');
+}
+
+// why the selected network's cached peers could not be read or saved, if they could not
+export async function get_peer_store_error(): Promise<string | null> {
+	return invoke('get_peer_store_error');
+}
+
+export async function unban_peer(node: Node): Promise<void> {
+	return invoke('unban_peer', {node: node});
+}
+
+// mirrors `MiningProgress`
+export type MiningProgress = {
+	threads: number;
+	hashes: number;
+	hashrate: number;
+};
+
+export type BlockMined = {
+	hash: string | null;
+	transactions: number;
+	reward: number;
+};
+
+// mines to the unlocked wallet until stop_mining; does nothing if already mining
+export async function start_mining(threads: number): Promise<void> {
+	return invoke('start_mining', {threads: threads});
+}
+
+export async function stop_mining(): Promise<void> {
+	return invoke('stop_mining');
+}
+
+export async function is_mining(): Promise<boolean> {
+	return invoke('is_mining');
+}
+
+// fired every second while mining
+export async function on_mining_progress(callback: (progress: MiningProgress) => void): Promise<UnlistenFn> {
+	return listen<MiningProgress>('mining-progress', (event) => callback(event.payload));
+}
+
+// fired for every block the nodes accept
+export async function on_block_mined(callback: (block: BlockMined) => void): Promise<UnlistenFn> {
+	return listen<BlockMined>('block-mined', (event) => callback(event.payload));
+}
+
+// fired once mining ends; `error` says why if it did not end by stop_mining
+export async function on_mining_stopped(callback: (error: string | null) => void): Promise<UnlistenFn> {
+	return listen<{ error: string | null }>('mining-stopped', (event) => callback(event.payload.error));
+}
+
+// mirrors `ChainTip`; `height` counts the blocks in the synced header chain
+export type ChainTip = {
+	network: string;
+	height: number;
+	hash: string | null;
+	chain_work: string;
+};
+
+// mirrors `SyncProgress`
+export type SyncProgress = {
+	height: number;
+	network_height: number;
+	error: string | null;
+};
+
+export async function get_chain_tip(): Promise<ChainTip> {
+	return invoke('get_chain_tip');
+}
+
+// syncs the headers now rather than waiting for the background sync
+export async function sync_headers_now(): Promise<ChainTip> {
+	return invoke('sync_headers_now');
+}
+
+// fired after every batch of headers downloaded
+export async function on_header_sync(callback: (progress: SyncProgress) => void): Promise<UnlistenFn> {
+	return listen<SyncProgress>('header-sync', (event) => callback(event.payload));
+}
+
+// fired whenever the best chain's tip changes, reorganizations included
+export async function on_chain_tip(callback: (tip: ChainTip) => void): Promise<UnlistenFn> {
+	return listen<ChainTip>('chain-tip', (event) => callback(event.payload));
+}
@@ -1,20 +1,77 @@
 import { invoke } from '@tauri-apps/api/tauri';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 
-export async function decrypt_file(aes_key_data_path: string, key_pair_data_path: string, password: string): Promise<string> {
-	console.log("Decrypting file...")
-	return invoke('decrypt_file', {aes_key_data_path: aes_key_data_path, key_pair_data_path: key_pair_data_path, password: password})
-} 
+// mirrors `WalletError` on the Rust side
+export type WalletError = {
+	kind: 'io' | 'parse' | 'hex' | 'key_length' | 'crypto' | 'wrong_password' | 'unsupported_version' | 'keystore_exists' | 'locked' | 'invalid_mnemonic' | 'derivation' | 'invalid_address'
+		| 'codec' | 'network' | 'insufficient_funds' | 'invalid_amount' | 'rejected' | 'config' | 'no_quorum' | 'invalid_transaction' | 'invalid_block';
+	message: string;
+};
 
-export async function generate_aes_key(path: string, password: string, iterations: number) {
-    console.log("Key pair has been called");
-    return invoke('generate_key_pair', {path: path, password: password, iterations: iterations});
+// whether `path` already holds a keystore, which is then unlocked rather than set up again
+export async function keystore_exists(path: string): Promise<boolean> {
+	return invoke('keystore_exists', {path: path});
 }
 
-export async function generate_key_pair(path: string, hex_key: string): Promise<[string, string]> {
-	console.log("Key pair has been generated")
-	return invoke('generate_key_pair_to_file', {path : path, hex_key : hex_key })
+// returns an opaque session handle; the private key stays on the Rust side
+export async function unlock_wallet(path: string, password: string): Promise<string> {
+	return invoke('unlock_wallet', {path: path, password: password})
 }
 
-export async function sign(privateKeyHex: string, message: string): Promise<[string, string]> {
-    return invoke('sign', { privateKeyHex : privateKeyHex, message : message });
-}
\ No newline at end of file
+export async function generate_keystore(path: string, password: string): Promise<string> {
+	return invoke('generate_keystore_to_file', {path: path, password: password});
+}
+
+// 12 or 24 words; shown once so the user can write it down
+export async function generate_mnemonic(wordCount: number): Promise<string> {
+	return invoke('generate_mnemonic', {wordCount: wordCount});
+}
+
+// writes a keystore for the key derived from `mnemonic`; returns the public key
+export async function restore_from_mnemonic(path: string, mnemonic: string, passphrase: string, password: string): Promise<string> {
+	return invoke('restore_from_mnemonic', {path: path, mnemonic: mnemonic, passphrase: passphrase, password: password});
+}
+
+// `restore_from_mnemonic` over the keystore at `path`; only once the user has agreed to lose its key
+export async function replace_wallet_from_mnemonic(path: string, mnemonic: string, passphrase: string, password: string): Promise<string> {
+	return invoke('replace_wallet_from_mnemonic', {path: path, mnemonic: mnemonic, passphrase: passphrase, password: password});
+}
+
+// re-encrypts a pre-keystore `aes_key_data.json` / `key_pair_data.json` pair into a keystore
+// at `path` under the same password; returns the public key
+export async function migrate_legacy_wallet(aesKeyDataPath: string, keyPairDataPath: string, path: string, password: string): Promise<string> {
+	return invoke('migrate_legacy_wallet', {aesKeyDataPath: aesKeyDataPath, keyPairDataPath: keyPairDataPath, path: path, password: password});
+}
+
+export async function lock_wallet(): Promise<void> {
+	return invoke('lock_wallet');
+}
+
+export async function is_unlocked(): Promise<boolean> {
+	return invoke('is_unlocked');
+}
+
+// fired when the session locked itself after sitting idle; its handle is dead from then on
+export async function on_wallet_locked(callback: () => void): Promise<UnlistenFn> {
+	return listen('wallet-locked', () => callback());
+}
+
+export async function set_auto_lock_timeout(seconds: number): Promise<void> {
+	return invoke('set_auto_lock_timeout', {seconds: seconds});
+}
+
+// signs the tagged hash of `message`, never the message itself, with the key the session
+// `handle` unlocked; returns [signature, publicKey]
+export async function sign_message(handle: string, message: string): Promise<[string, string]> {
+	return invoke('sign_message', {handle: handle, message: message});
+}
+
+// `cj1...` address of the unlocked wallet
+export async function get_address(): Promise<string> {
+	return invoke('get_address');
+}
+
+// rejects with an `invalid_address` WalletError if the checksum or prefix is wrong
+export async function validate_address(address: string): Promise<void> {
+	return invoke('validate_address', {address: address});
+}
@@ -1,3 +1,10 @@
 import { writable } from "svelte/store";
 
-export const filePath = writable<string>('');
\ No newline at end of file
+// the folder holding keystore.json, remembered across restarts so an existing wallet is unlocked instead of set up again
+const remembered = typeof localStorage === 'undefined' ? '' : localStorage.getItem('filePath') ?? '';
+
+export const filePath = writable<string>(remembered);
+
+filePath.subscribe((path) => {
+	if (typeof localStorage !== 'undefined') localStorage.setItem('filePath', path);
+});
@@ -0,0 +1,4 @@
+import { writable } from "svelte/store";
+
+// only held until the backup page has been shown, then cleared
+export const mnemonic = writable<string>('');
@@ -1,3 +1,4 @@
 import { writable } from "svelte/store";
 
+// only held until `unlock_wallet` succeeds, then cleared
 export const password = writable<string>('');
\ No newline at end of file
@@ -1,3 +1,4 @@
 import { writable } from "svelte/store";
 
-export const seed_node_ip = ''
\ No newline at end of file
+// seed nodes of the selected network profile, as `host:port`
+export const seed_nodes = writable<string[]>([]);
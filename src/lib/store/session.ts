@@ -0,0 +1,4 @@
+import { writable } from "svelte/store";
+
+// the handle `unlock_wallet` returned; signing commands refuse any other
+export const sessionHandle = writable<string>('');